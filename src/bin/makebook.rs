@@ -0,0 +1,66 @@
+use std::{fs, path::Path, process};
+
+use chess::lib::{pgn::PgnGame, piece::Parity, polyglot::BookBuilder};
+
+const USAGE: &str = "usage: makebook [out=book.bin] [min_games=N] [max_ply=N] [side=white|black|both] games.pgn...";
+
+fn bad(arg: &str) -> ! {
+    eprintln!("bad argument \"{}\"\n{}", arg, USAGE);
+    process::exit(2);
+}
+
+// Reads every game in the PGN files and writes the book of their openings
+fn main() -> () {
+    let mut out = String::from("book.bin");
+    let mut builder = BookBuilder::new(1, 20, Parity::BOTH);
+    let mut files = Vec::new();
+    for arg in std::env::args().skip(1) {
+        if let Some(path) = arg.strip_prefix("out=") {
+            out = path.to_string();
+        } else if let Some(n) = arg.strip_prefix("min_games=") {
+            builder.min_games = n.parse().unwrap_or_else(|_| bad(&arg));
+        } else if let Some(n) = arg.strip_prefix("max_ply=") {
+            builder.max_ply = n.parse().unwrap_or_else(|_| bad(&arg));
+        } else if let Some(side) = arg.strip_prefix("side=") {
+            builder.side = match side {
+                "white" => Parity::WHITE,
+                "black" => Parity::BLACK,
+                "both" => Parity::BOTH,
+                _ => bad(&arg)
+            };
+        } else {
+            files.push(arg);
+        }
+    }
+    if files.is_empty() {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    }
+
+    let mut skipped = 0;
+    for file in &files {
+        let text = match fs::read_to_string(file) {
+            Ok(text) => text,
+            Err(e) => {
+                eprintln!("Could not read {}: {}", file, e);
+                continue;
+            }
+        };
+        match PgnGame::parse_all(&text) {
+            Ok(games) => {
+                for game in &games {
+                    if !builder.add_game(game) {
+                        skipped += 1;
+                    }
+                }
+            },
+            Err(e) => eprintln!("Could not parse {}: {}", file, e)
+        }
+    }
+    let book = builder.build();
+    if let Err(e) = book.save(Path::new(&out)) {
+        eprintln!("Could not write {}: {}", out, e);
+        process::exit(1);
+    }
+    println!("Wrote {} entries from {} games to {} ({} skipped)", book.len(), builder.games(), out, skipped);
+}
@@ -0,0 +1,9 @@
+use std::{io, sync::{Arc, Mutex}};
+
+use chess::lib::{engine::Output, uci};
+
+// Speaks UCI over stdin and stdout, for loading the engine into a chess GUI
+fn main() -> () {
+    let out: Output = Arc::new(Mutex::new(io::stdout()));
+    uci::run(io::stdin().lock(), out);
+}
@@ -0,0 +1,9 @@
+use std::{io, sync::{Arc, Mutex}};
+
+use chess::lib::{engine::Output, xboard};
+
+// Speaks the XBoard protocol (CECP) over stdin and stdout
+fn main() -> () {
+    let out: Output = Arc::new(Mutex::new(io::stdout()));
+    xboard::run(io::stdin().lock(), out);
+}
@@ -1,9 +1,6 @@
-#![feature(unbounded_shifts)]
-#![feature(array_chunks)]
-#![feature(slice_from_ptr_range)]
-#![feature(slice_as_array)]
 pub mod lib {
     pub mod cutil;
+    #[cfg(feature = "gui")]
     pub mod ui;
     pub mod game;
     pub mod heap;
@@ -19,6 +16,24 @@ pub mod lib {
     pub mod boardarray;
     pub mod searchtree;
     pub mod player;
+    #[cfg(feature = "gui")]
     pub mod manager;
     pub mod searcher;
+    pub mod outcome;
+    pub mod fen;
+    pub mod notation;
+    pub mod pgn;
+    pub mod perft;
+    pub mod attacks;
+    pub mod chess960;
+    pub mod variant;
+    pub mod atomic;
+    pub mod crazyhouse;
+    pub mod polyglot;
+    pub mod syzygy;
+    pub mod transposition;
+    pub mod bitbase;
+    pub mod engine;
+    pub mod uci;
+    pub mod xboard;
 }
@@ -0,0 +1,53 @@
+use crate::lib::{
+    attacks,
+    boardarray::{is_capture, BoardArray},
+    chessbyte::ChessByte,
+    mask::Mask,
+    maskset::MaskSet,
+    motion::Motion,
+    piece::Parity
+};
+
+// The capture square and its eight neighbours
+#[inline(always)]
+pub fn blast_zone(at: usize) -> Mask {
+    return attacks::king_attacks(at) | Mask::from_index(at);
+}
+
+// Removes the capturing piece and every piece around the capture square except pawns
+pub fn blast(board: &mut [u8; 64], at: usize) -> () {
+    for iso in blast_zone(at).isolated_bits() {
+        let index = iso.as_index();
+        if index == at || !board[index].is_pawn() {
+            board[index] = 0;
+        }
+    }
+}
+
+// A king is only in check when it could be taken, and taking a king that touches your own would blow up both
+pub fn in_check(maskset: &MaskSet, parity: Parity) -> bool {
+    let (king, enemy) = (maskset.king(parity), maskset.king(!parity));
+    if king == 65 || enemy == 65 || (attacks::king_attacks(king) & Mask::from_index(enemy)).any() {
+        return false;
+    }
+    return maskset.attackers_of(king, parity).any();
+}
+
+// The mover must keep its king, and keep it safe unless the enemy king went up in the same blast
+pub fn is_legal(board: &[u8; 64], motion: &Motion) -> bool {
+    let parity = board[motion.from].get_parity();
+    let capture = is_capture(board, motion);
+    if capture && board[motion.from].is_king() {
+        return false;
+    }
+    let mut after = *board;
+    after.make_soft(motion);
+    if capture {
+        blast(&mut after, motion.to);
+    }
+    let maskset = MaskSet::from_board(&after);
+    if maskset.king(parity) == 65 {
+        return false;
+    }
+    return maskset.king(!parity) == 65 || !in_check(&maskset, parity);
+}
@@ -0,0 +1,209 @@
+use std::sync::OnceLock;
+
+use crate::lib::{mask::Mask, piece::Parity};
+
+// Index 0 is a8, so "up" the board (towards rank 8) is -8
+const ROOK_STEPS: [(i32, i32); 4] = [(0, 1), (0, -1), (1, 0), (-1, 0)];
+const BISHOP_STEPS: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+const KNIGHT_STEPS: [(i32, i32); 8] = [(-2, -1), (-2, 1), (-1, -2), (-1, 2), (1, -2), (1, 2), (2, -1), (2, 1)];
+const KING_STEPS: [(i32, i32); 8] = [(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)];
+
+struct Magic {
+    mask: u64,
+    magic: u64,
+    shift: u32,
+    offset: usize
+}
+
+struct Tables {
+    knight: [u64; 64],
+    king: [u64; 64],
+    // [white, black]
+    pawn: [[u64; 64]; 2],
+    rook: Vec<Magic>,
+    bishop: Vec<Magic>,
+    sliding: Vec<u64>
+}
+
+static TABLES: OnceLock<Tables> = OnceLock::new();
+
+fn offset_square(index: usize, (dx, dy): (i32, i32)) -> Option<usize> {
+    let (x, y) = ((index % 8) as i32 + dx, (index / 8) as i32 + dy);
+    if x < 0 || x > 7 || y < 0 || y > 7 {
+        return None;
+    }
+    return Some((y * 8 + x) as usize);
+}
+
+fn step_table(steps: &[(i32, i32)]) -> [u64; 64] {
+    let mut table = [0u64; 64];
+    for (index, entry) in table.iter_mut().enumerate() {
+        for step in steps {
+            if let Some(to) = offset_square(index, *step) {
+                *entry |= 1u64 << to;
+            }
+        }
+    }
+    return table;
+}
+
+// Walks each ray until it leaves the board or hits an occupied square, which is included
+fn ray_attacks(index: usize, occupancy: u64, steps: &[(i32, i32); 4]) -> u64 {
+    let mut attacks = 0u64;
+    for step in steps {
+        let mut at = index;
+        while let Some(to) = offset_square(at, *step) {
+            attacks |= 1u64 << to;
+            if occupancy & (1u64 << to) != 0 {
+                break;
+            }
+            at = to;
+        }
+    }
+    return attacks;
+}
+
+// The squares whose occupancy can change the attack set, i.e. each ray without its last square
+fn relevant_mask(index: usize, steps: &[(i32, i32); 4]) -> u64 {
+    let mut mask = 0u64;
+    for step in steps {
+        let mut at = index;
+        while let Some(to) = offset_square(at, *step) {
+            if offset_square(to, *step).is_none() {
+                break;
+            }
+            mask |= 1u64 << to;
+            at = to;
+        }
+    }
+    return mask;
+}
+
+fn xorshift(state: &mut u64) -> u64 {
+    *state ^= *state >> 12;
+    *state ^= *state << 25;
+    *state ^= *state >> 27;
+    return state.wrapping_mul(0x2545_F491_4F6C_DD1D);
+}
+
+// Searches for a multiplier that maps every blocker subset of the mask to a distinct (or agreeing) slot
+fn find_magics(steps: &[(i32, i32); 4], sliding: &mut Vec<u64>, seed: &mut u64) -> Vec<Magic> {
+    let mut magics = Vec::with_capacity(64);
+    for index in 0..64 {
+        let mask = relevant_mask(index, steps);
+        let bits = mask.count_ones();
+        let shift = 64 - bits;
+
+        let mut occupancies = Vec::with_capacity(1 << bits);
+        let mut subset = 0u64;
+        loop {
+            occupancies.push((subset, ray_attacks(index, subset, steps)));
+            subset = subset.wrapping_sub(mask) & mask;
+            if subset == 0 {
+                break;
+            }
+        }
+
+        let offset = sliding.len();
+        sliding.resize(offset + (1 << bits), 0);
+        let mut used = vec![0u32; 1 << bits];
+        let mut attempt = 0u32;
+        loop {
+            attempt += 1;
+            let magic = xorshift(seed) & xorshift(seed) & xorshift(seed);
+            if (mask.wrapping_mul(magic) >> 56).count_ones() < 6 {
+                continue;
+            }
+            let mut collided = false;
+            for (occupancy, attacks) in occupancies.iter() {
+                let slot = (occupancy.wrapping_mul(magic) >> shift) as usize;
+                if used[slot] != attempt {
+                    used[slot] = attempt;
+                    sliding[offset + slot] = *attacks;
+                } else if sliding[offset + slot] != *attacks {
+                    collided = true;
+                    break;
+                }
+            }
+            if !collided {
+                magics.push(Magic { mask, magic, shift, offset });
+                break;
+            }
+        }
+    }
+    return magics;
+}
+
+fn build() -> Tables {
+    let mut pawn = [[0u64; 64]; 2];
+    pawn[0] = step_table(&[(-1, -1), (1, -1)]);
+    pawn[1] = step_table(&[(-1, 1), (1, 1)]);
+    let mut sliding = Vec::new();
+    let mut seed = 0x9E37_79B9_7F4A_7C15u64;
+    let rook = find_magics(&ROOK_STEPS, &mut sliding, &mut seed);
+    let bishop = find_magics(&BISHOP_STEPS, &mut sliding, &mut seed);
+    return Tables {
+        knight: step_table(&KNIGHT_STEPS),
+        king: step_table(&KING_STEPS),
+        pawn,
+        rook,
+        bishop,
+        sliding
+    };
+}
+
+#[inline(always)]
+fn tables() -> &'static Tables {
+    return TABLES.get_or_init(build);
+}
+
+#[inline(always)]
+fn lookup(magic: &Magic, sliding: &[u64], occupancy: &Mask) -> Mask {
+    let slot = ((occupancy.raw & magic.mask).wrapping_mul(magic.magic) >> magic.shift) as usize;
+    return Mask { raw: sliding[magic.offset + slot] };
+}
+
+// Building the magic tables takes a moment, so anything timing sensitive can call this up front
+pub fn init() -> () {
+    tables();
+}
+
+#[inline(always)]
+pub fn rook_attacks(index: usize, occupancy: &Mask) -> Mask {
+    let t = tables();
+    return lookup(&t.rook[index], &t.sliding, occupancy);
+}
+#[inline(always)]
+pub fn bishop_attacks(index: usize, occupancy: &Mask) -> Mask {
+    let t = tables();
+    return lookup(&t.bishop[index], &t.sliding, occupancy);
+}
+#[inline(always)]
+pub fn queen_attacks(index: usize, occupancy: &Mask) -> Mask {
+    return rook_attacks(index, occupancy) | bishop_attacks(index, occupancy);
+}
+#[inline(always)]
+pub fn knight_attacks(index: usize) -> Mask {
+    return Mask { raw: tables().knight[index] };
+}
+#[inline(always)]
+pub fn king_attacks(index: usize) -> Mask {
+    return Mask { raw: tables().king[index] };
+}
+// The squares a pawn of the given parity on index attacks
+#[inline(always)]
+pub fn pawn_attacks(index: usize, parity: Parity) -> Mask {
+    return Mask { raw: tables().pawn[if parity == Parity::WHITE { 0 } else { 1 }][index] };
+}
+// The squares strictly between two squares on a shared line, or nothing if they are not aligned
+pub fn between(from: usize, to: usize) -> Mask {
+    let (a, b) = (Mask::from_index(from), Mask::from_index(to));
+    let empty = Mask::default();
+    if (rook_attacks(from, &empty) & b).any() {
+        return rook_attacks(from, &b) & rook_attacks(to, &a);
+    }
+    if (bishop_attacks(from, &empty) & b).any() {
+        return bishop_attacks(from, &b) & bishop_attacks(to, &a);
+    }
+    return Mask::default();
+}
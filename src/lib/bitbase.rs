@@ -0,0 +1,595 @@
+use std::{fs, path::Path, sync::{Arc, RwLock}};
+
+use crate::lib::{
+    attacks,
+    chessbyte::ChessByte,
+    mask::Mask,
+    motion::Motion,
+    piece::{Parity, PieceByte},
+    state::State,
+    variant::Variant
+};
+
+// Exact results for the endgames where one side has a king and a piece or two against a bare king.
+// They are solved by retrograde analysis: start from the mates and walk the moves backwards, so every
+// position gets the plies to mate with best play, or stays a draw. Positions are stored with the strong
+// side as white, the strong king in a corner triangle when there are no pawns, and the pawn on files a-d otherwise.
+// Moves and unmoves come from the attack tables over bitboards of the few pieces involved, rather than from making
+// moves on a State, which would be far slower over every KBNK position. The agrees_with_move_generation test in
+// tests/bitbase.rs checks the results against State's own move generation.
+
+const MAGIC: [u8; 4] = *b"CBB1";
+// Below the evaluation's VARIANT_WIN, less a ply for each ply to mate
+pub const BITBASE_WIN: i32 = 50_000;
+// The strong king's squares without pawns, as our indices: a8-d8, b7-d7, c6-d6, d5 mirrored up to this corner
+const TRIANGLE: [usize; 10] = [0, 1, 2, 3, 9, 10, 11, 18, 19, 27];
+const STRONG: usize = 0;
+const WEAK: usize = 1;
+// What the solver knows of a position
+const ILLEGAL: u8 = 0;
+const UNKNOWN: u8 = 1;
+const DRAW: u8 = 2;
+const WIN: u8 = 3;
+const LOSS: u8 = 4;
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Hash)]
+pub enum Endgame {
+    KQK,
+    KRK,
+    KPK,
+    KBNK
+}
+
+impl Endgame {
+    // In the order they have to be solved, as a pawn promotes into the first two
+    pub const ALL: [Endgame; 4] = [Endgame::KQK, Endgame::KRK, Endgame::KPK, Endgame::KBNK];
+
+    pub fn name(self) -> &'static str {
+        return match self {
+            Endgame::KQK => "KQK",
+            Endgame::KRK => "KRK",
+            Endgame::KPK => "KPK",
+            Endgame::KBNK => "KBNK"
+        };
+    }
+    // The strong side's pieces besides its king
+    fn pieces(self) -> &'static [PieceByte] {
+        return match self {
+            Endgame::KQK => &[PieceByte::QUEEN],
+            Endgame::KRK => &[PieceByte::ROOK],
+            Endgame::KPK => &[PieceByte::PAWN],
+            Endgame::KBNK => &[PieceByte::BISHOP, PieceByte::KNIGHT]
+        };
+    }
+    fn has_pawn(self) -> bool {
+        return self == Endgame::KPK;
+    }
+    fn king_slots(self) -> usize {
+        return if self.has_pawn() { 64 } else { TRIANGLE.len() };
+    }
+    // Positions in the table, including the illegal ones that keep the indexing simple
+    pub fn size(self) -> usize {
+        return 2 * self.king_slots() * 64usize.pow(1 + self.pieces().len() as u32);
+    }
+    fn matching(pieces: &[PieceByte]) -> Option<Endgame> {
+        return Self::ALL.into_iter().find(|e| e.pieces() == pieces);
+    }
+    fn from_id(id: u8) -> Option<Endgame> {
+        return Self::ALL.get(id as usize).copied();
+    }
+    fn id(self) -> u8 {
+        return Self::ALL.iter().position(|e| *e == self).unwrap() as u8;
+    }
+}
+
+// For the side to move, in plies to mate
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum BitbaseValue {
+    Win(u8),
+    Draw,
+    Loss(u8)
+}
+
+impl BitbaseValue {
+    // For the side to move, sooner mates scoring further from zero
+    pub fn score(self) -> i32 {
+        return match self {
+            BitbaseValue::Win(plies) => BITBASE_WIN - plies as i32,
+            BitbaseValue::Draw => 0,
+            BitbaseValue::Loss(plies) => -BITBASE_WIN + plies as i32
+        };
+    }
+}
+
+#[derive(Debug)]
+pub enum BitbaseError {
+    Io(std::io::Error),
+    // Not a bitbase, or a bitbase for a different layout
+    BadHeader,
+    // The runs don't add up to the endgame's size
+    Truncated
+}
+
+impl std::fmt::Display for BitbaseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            Self::Io(e) => write!(f, "could not read bitbase: {}", e),
+            Self::BadHeader => write!(f, "not a bitbase file"),
+            Self::Truncated => write!(f, "bitbase is cut short")
+        };
+    }
+}
+
+impl std::error::Error for BitbaseError {}
+
+impl From<std::io::Error> for BitbaseError {
+    fn from(e: std::io::Error) -> Self { Self::Io(e) }
+}
+
+#[inline(always)]
+fn bit(square: usize) -> u64 {
+    return 1u64 << square;
+}
+
+fn squares_of(mut bits: u64) -> impl Iterator<Item = usize> {
+    return std::iter::from_fn(move || {
+        if bits == 0 {
+            return None;
+        }
+        let square = bits.trailing_zeros() as usize;
+        bits &= bits - 1;
+        return Some(square);
+    });
+}
+
+fn attacks_from(piece: PieceByte, square: usize, occupancy: u64) -> u64 {
+    let occupancy = Mask { raw: occupancy };
+    return match piece {
+        PieceByte::PAWN => attacks::pawn_attacks(square, Parity::WHITE).raw,
+        PieceByte::KNIGHT => attacks::knight_attacks(square).raw,
+        PieceByte::BISHOP => attacks::bishop_attacks(square, &occupancy).raw,
+        PieceByte::ROOK => attacks::rook_attacks(square, &occupancy).raw,
+        PieceByte::QUEEN => attacks::queen_attacks(square, &occupancy).raw,
+        _ => attacks::king_attacks(square).raw
+    };
+}
+
+// A position as the strong king, the weak king, then the strong side's pieces in the endgame's order
+#[derive(Clone, Copy, Eq, PartialEq)]
+struct Placement {
+    squares: [usize; 4],
+    count: usize
+}
+
+impl Placement {
+    fn occupancy(&self) -> u64 {
+        return self.squares[..self.count].iter().fold(0, |occ, s| occ | bit(*s));
+    }
+    // Everything the strong side attacks, leaving out one of its pieces if it is being taken
+    fn strong_attacks(&self, endgame: Endgame, occupancy: u64, skip: Option<usize>) -> u64 {
+        let mut attacked = attacks::king_attacks(self.squares[0]).raw;
+        for (i, piece) in endgame.pieces().iter().enumerate() {
+            if skip != Some(i + 2) {
+                attacked |= attacks_from(*piece, self.squares[i + 2], occupancy);
+            }
+        }
+        return attacked;
+    }
+    fn canonical(mut self, endgame: Endgame) -> Placement {
+        let count = self.count;
+        let squares = &mut self.squares[..count];
+        if endgame.has_pawn() {
+            if squares[2] % 8 > 3 {
+                squares.iter_mut().for_each(|s| *s ^= 7);
+            }
+            return self;
+        }
+        if squares[0] % 8 > 3 {
+            squares.iter_mut().for_each(|s| *s ^= 7);
+        }
+        if squares[0] / 8 > 3 {
+            squares.iter_mut().for_each(|s| *s ^= 56);
+        }
+        // Flip along the diagonal so the first piece off it sits below it, which settles kings on the diagonal too
+        if squares.iter().find(|s| *s / 8 != *s % 8).is_some_and(|s| s / 8 > s % 8) {
+            squares.iter_mut().for_each(|s| *s = ((*s >> 3) | (*s << 3)) & 63);
+        }
+        return self;
+    }
+    fn is_legal(&self, endgame: Endgame, to_move: usize) -> bool {
+        let occupancy = self.occupancy();
+        if occupancy.count_ones() as usize != self.count {
+            return false;
+        }
+        if attacks::king_attacks(self.squares[0]).raw & bit(self.squares[1]) != 0 {
+            return false;
+        }
+        if endgame.has_pawn() && !(1..=6).contains(&(self.squares[2] / 8)) {
+            return false;
+        }
+        // The weak side can't be left in check with the strong side to move
+        return to_move == WEAK || self.strong_attacks(endgame, occupancy, None) & bit(self.squares[1]) == 0;
+    }
+}
+
+fn index(endgame: Endgame, to_move: usize, placement: &Placement) -> usize {
+    let king = if endgame.has_pawn() { placement.squares[0] } else { TRIANGLE.iter().position(|s| *s == placement.squares[0]).unwrap() };
+    let mut idx = to_move * endgame.king_slots() + king;
+    for square in &placement.squares[1..placement.count] {
+        idx = idx * 64 + square;
+    }
+    return idx;
+}
+
+fn decode(endgame: Endgame, mut idx: usize) -> (usize, Placement) {
+    let count = 2 + endgame.pieces().len();
+    let mut placement = Placement { squares: [0; 4], count };
+    for i in (1..count).rev() {
+        placement.squares[i] = idx % 64;
+        idx /= 64;
+    }
+    let king = idx % endgame.king_slots();
+    placement.squares[0] = if endgame.has_pawn() { king } else { TRIANGLE[king] };
+    return (idx / endgame.king_slots(), placement);
+}
+
+// The weak king's moves as the indices they lead to, and whether it can take a piece instead
+fn weak_moves(endgame: Endgame, placement: &Placement) -> (Vec<usize>, bool, bool) {
+    let weak_king = placement.squares[1];
+    // The king doesn't shield the squares behind it
+    let occupancy = placement.occupancy() & !bit(weak_king);
+    let attacked = placement.strong_attacks(endgame, occupancy, None);
+    let mut children = Vec::new();
+    let mut capture = false;
+    // Defended pieces stand on attacked squares, so only undefended ones can be taken here
+    for to in squares_of(attacks::king_attacks(weak_king).raw & !attacked) {
+        if let Some(taken) = (2..placement.count).find(|i| placement.squares[*i] == to) {
+            capture |= placement.strong_attacks(endgame, occupancy, Some(taken)) & bit(to) == 0;
+            continue;
+        }
+        let mut child = *placement;
+        child.squares[1] = to;
+        children.push(index(endgame, STRONG, &child.canonical(endgame)));
+    }
+    children.sort_unstable();
+    children.dedup();
+    return (children, capture, attacked & bit(weak_king) != 0);
+}
+
+fn push(queue: &mut Vec<Vec<u32>>, plies: usize, idx: usize) -> () {
+    if queue.len() <= plies {
+        queue.resize(plies + 1, Vec::new());
+    }
+    queue[plies].push(idx as u32);
+}
+
+// Where the strong side could have moved from to reach this position, with the weak side to move
+fn strong_unmoves(endgame: Endgame, placement: &Placement) -> Vec<usize> {
+    let occupancy = placement.occupancy();
+    let mut parents = Vec::new();
+    for i in 0..placement.count {
+        if i == 1 {
+            continue;
+        }
+        let from = placement.squares[i];
+        let piece = if i == 0 { PieceByte::KING } else { endgame.pieces()[i - 2] };
+        let origins = if piece == PieceByte::PAWN {
+            // Pawns step up the board, towards index 0
+            let mut origins = 0;
+            if from / 8 <= 5 && occupancy & bit(from + 8) == 0 {
+                origins |= bit(from + 8);
+                if from / 8 == 4 && occupancy & bit(from + 16) == 0 {
+                    origins |= bit(from + 16);
+                }
+            }
+            origins
+        } else {
+            attacks_from(piece, from, occupancy) & !occupancy
+        };
+        for origin in squares_of(origins) {
+            let mut parent = *placement;
+            parent.squares[i] = origin;
+            let parent = parent.canonical(endgame);
+            if parent.is_legal(endgame, STRONG) {
+                parents.push(index(endgame, STRONG, &parent));
+            }
+        }
+    }
+    parents.sort_unstable();
+    parents.dedup();
+    return parents;
+}
+
+fn weak_unmoves(endgame: Endgame, placement: &Placement) -> Vec<usize> {
+    let occupancy = placement.occupancy();
+    let mut parents = Vec::new();
+    for origin in squares_of(attacks::king_attacks(placement.squares[1]).raw & !occupancy) {
+        let mut parent = *placement;
+        parent.squares[1] = origin;
+        let parent = parent.canonical(endgame);
+        if parent.is_legal(endgame, WEAK) {
+            parents.push(index(endgame, WEAK, &parent));
+        }
+    }
+    parents.sort_unstable();
+    parents.dedup();
+    return parents;
+}
+
+pub struct Bitbase {
+    endgame: Endgame,
+    // Plies to mate plus one for the side to move, or 0 for draws and impossible positions
+    dtm: Vec<u8>
+}
+
+impl Bitbase {
+    // Solves the endgame, solving any it promotes into first unless they are installed
+    pub fn generate(endgame: Endgame) -> Bitbase {
+        let promotions: Vec<Arc<Bitbase>> = if endgame.has_pawn() {
+            [Endgame::KQK, Endgame::KRK].into_iter().map(|e| installed(e).unwrap_or_else(|| Arc::new(Bitbase::generate(e)))).collect()
+        } else {
+            Vec::new()
+        };
+        let size = endgame.size();
+        let mut status = vec![ILLEGAL; size];
+        // Weak moves not yet known to lose, for each weak-to-move position
+        let mut remaining = vec![0u8; size];
+        let mut dtm = vec![0u8; size];
+        // Positions to settle at each distance from mate
+        let mut queue: Vec<Vec<u32>> = Vec::new();
+
+        for idx in 0..size {
+            let (to_move, placement) = decode(endgame, idx);
+            if placement.canonical(endgame) != placement || !placement.is_legal(endgame, to_move) {
+                continue;
+            }
+            status[idx] = UNKNOWN;
+            if to_move == WEAK {
+                let (children, capture, in_check) = weak_moves(endgame, &placement);
+                if capture {
+                    // A lone king can't lose, so taking anything draws
+                    status[idx] = DRAW;
+                } else if children.is_empty() {
+                    if in_check {
+                        push(&mut queue, 0, idx);
+                    } else {
+                        status[idx] = DRAW;
+                    }
+                }
+                remaining[idx] = children.len() as u8;
+            } else if endgame.has_pawn() {
+                let pawn = placement.squares[2];
+                if pawn / 8 == 1 && placement.occupancy() & bit(pawn - 8) == 0 {
+                    // Promoting moves into another table, so start from what that table says
+                    for table in &promotions {
+                        let promoted = Placement { squares: [placement.squares[0], placement.squares[1], pawn - 8, 0], count: 3 };
+                        let child = table.dtm[index(table.endgame, WEAK, &promoted.canonical(table.endgame))];
+                        if child > 0 && (dtm[idx] == 0 || child + 1 < dtm[idx]) {
+                            dtm[idx] = child + 1;
+                            push(&mut queue, child as usize, idx);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut plies = 0;
+        while plies < queue.len() {
+            for idx in std::mem::take(&mut queue[plies]) {
+                let idx = idx as usize;
+                let (to_move, placement) = decode(endgame, idx);
+                if status[idx] != UNKNOWN || (to_move == STRONG && dtm[idx] as usize != plies + 1) {
+                    continue;
+                }
+                dtm[idx] = (plies + 1).min(255) as u8;
+                if to_move == WEAK {
+                    status[idx] = LOSS;
+                    for parent in strong_unmoves(endgame, &placement) {
+                        if status[parent] == UNKNOWN && (dtm[parent] == 0 || plies + 2 < dtm[parent] as usize) {
+                            dtm[parent] = (plies + 2).min(255) as u8;
+                            push(&mut queue, plies + 1, parent);
+                        }
+                    }
+                } else {
+                    status[idx] = WIN;
+                    for parent in weak_unmoves(endgame, &placement) {
+                        if status[parent] == UNKNOWN {
+                            remaining[parent] -= 1;
+                            if remaining[parent] == 0 {
+                                push(&mut queue, plies + 1, parent);
+                            }
+                        }
+                    }
+                }
+            }
+            plies += 1;
+        }
+        // Strong positions that were queued but never settled are draws
+        for (value, s) in dtm.iter_mut().zip(&status) {
+            if *s != WIN && *s != LOSS {
+                *value = 0;
+            }
+        }
+        return Bitbase { endgame, dtm };
+    }
+
+    pub fn endgame(&self) -> Endgame {
+        return self.endgame;
+    }
+    // The most plies to mate from any position in the table
+    pub fn longest_mate(&self) -> u8 {
+        return self.dtm.iter().max().copied().unwrap_or(0).saturating_sub(1);
+    }
+
+    pub fn open(path: &Path) -> Result<Bitbase, BitbaseError> {
+        return Self::from_bytes(&fs::read(path)?);
+    }
+    // The header, then runs of equal values as the value and a little-endian base-128 length
+    pub fn from_bytes(bytes: &[u8]) -> Result<Bitbase, BitbaseError> {
+        if bytes.len() < 9 || bytes[0..4] != MAGIC {
+            return Err(BitbaseError::BadHeader);
+        }
+        let endgame = Endgame::from_id(bytes[4]).ok_or(BitbaseError::BadHeader)?;
+        if u32::from_le_bytes(bytes[5..9].try_into().unwrap()) as usize != endgame.size() {
+            return Err(BitbaseError::BadHeader);
+        }
+        let mut dtm = Vec::with_capacity(endgame.size());
+        let mut at = 9;
+        while at < bytes.len() {
+            let value = bytes[at];
+            let mut run = 0usize;
+            let mut shift = 0;
+            loop {
+                at += 1;
+                let byte = *bytes.get(at).ok_or(BitbaseError::Truncated)?;
+                run |= ((byte & 0x7F) as usize) << shift;
+                shift += 7;
+                if byte & 0x80 == 0 || shift > 28 {
+                    break;
+                }
+            }
+            at += 1;
+            if dtm.len() + run > endgame.size() {
+                return Err(BitbaseError::Truncated);
+            }
+            dtm.resize(dtm.len() + run, value);
+        }
+        if dtm.len() != endgame.size() {
+            return Err(BitbaseError::Truncated);
+        }
+        return Ok(Bitbase { endgame, dtm });
+    }
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(self.endgame.id());
+        bytes.extend_from_slice(&(self.dtm.len() as u32).to_le_bytes());
+        let mut i = 0;
+        while i < self.dtm.len() {
+            let value = self.dtm[i];
+            let run = self.dtm[i..].iter().take_while(|v| **v == value).count();
+            bytes.push(value);
+            let mut left = run;
+            loop {
+                let byte = (left & 0x7F) as u8;
+                left >>= 7;
+                if left == 0 {
+                    bytes.push(byte);
+                    break;
+                }
+                bytes.push(byte | 0x80);
+            }
+            i += run;
+        }
+        return bytes;
+    }
+    pub fn save(&self, path: &Path) -> Result<(), BitbaseError> {
+        fs::write(path, self.to_bytes())?;
+        return Ok(());
+    }
+
+    pub fn probe(&self, state: &State) -> Option<BitbaseValue> {
+        let (endgame, to_move, placement) = placement(state)?;
+        if endgame != self.endgame {
+            return None;
+        }
+        let value = self.dtm[index(endgame, to_move, &placement.canonical(endgame))];
+        return Some(match (value, to_move) {
+            (0, _) => BitbaseValue::Draw,
+            (_, STRONG) => BitbaseValue::Win(value - 1),
+            _ => BitbaseValue::Loss(value - 1)
+        });
+    }
+}
+
+// The endgame a position is in, with the strong side turned to white
+fn placement(state: &State) -> Option<(Endgame, usize, Placement)> {
+    if state.variant != Variant::Standard || state.info.allowed_castles != 0 {
+        return None;
+    }
+    let mut kings = [65, 65];
+    let mut pieces = Vec::new();
+    let mut strong = None;
+    for (square, byte) in state.board.iter().enumerate().filter(|(_, b)| b.is_piece()) {
+        let parity = if byte.is_white() { Parity::WHITE } else { Parity::BLACK };
+        if byte.is_king() {
+            kings[if byte.is_white() { 0 } else { 1 }] = square;
+            continue;
+        }
+        if strong.is_some_and(|s| s != parity) {
+            return None;
+        }
+        strong = Some(parity);
+        pieces.push((byte.get_piece(), square));
+    }
+    let strong = strong?;
+    // Both bishop and knight orders name the same endgame
+    pieces.sort_by_key(|(piece, _)| *piece != PieceByte::BISHOP);
+    let endgame = Endgame::matching(&pieces.iter().map(|(piece, _)| *piece).collect::<Vec<_>>())?;
+    let flip = if strong == Parity::WHITE { 0 } else { 56 };
+    let (strong_king, weak_king) = if strong == Parity::WHITE { (kings[0], kings[1]) } else { (kings[1], kings[0]) };
+    let mut placement = Placement { squares: [strong_king ^ flip, weak_king ^ flip, 0, 0], count: 2 + pieces.len() };
+    for (i, (_, square)) in pieces.iter().enumerate() {
+        placement.squares[i + 2] = square ^ flip;
+    }
+    return Some((endgame, if state.turn == strong { STRONG } else { WEAK }, placement));
+}
+
+static INSTALLED: RwLock<Vec<Arc<Bitbase>>> = RwLock::new(Vec::new());
+
+// Makes a bitbase available to the evaluation and the search, replacing any for the same endgame
+pub fn install(bitbase: Bitbase) -> () {
+    let mut installed = INSTALLED.write().unwrap();
+    installed.retain(|b| b.endgame != bitbase.endgame);
+    installed.push(Arc::new(bitbase));
+}
+
+pub fn installed(endgame: Endgame) -> Option<Arc<Bitbase>> {
+    return INSTALLED.read().unwrap().iter().find(|b| b.endgame == endgame).cloned();
+}
+
+// Reads each endgame's bitbase from the directory, solving and saving any that aren't there yet
+pub fn load_or_generate(dir: &Path) -> Result<(), BitbaseError> {
+    fs::create_dir_all(dir)?;
+    for endgame in Endgame::ALL {
+        let path = dir.join(format!("{}.bitbase", endgame.name()));
+        let bitbase = if path.exists() {
+            Bitbase::open(&path)?
+        } else {
+            let bitbase = Bitbase::generate(endgame);
+            bitbase.save(&path)?;
+            bitbase
+        };
+        install(bitbase);
+    }
+    return Ok(());
+}
+
+// The installed bitbase's value for the position, if there is one for its material
+pub fn probe(state: &State) -> Option<BitbaseValue> {
+    if state.info.maskset.white.bit_count() + state.info.maskset.black.bit_count() > 4 {
+        return None;
+    }
+    let (endgame, _, _) = placement(state)?;
+    return installed(endgame)?.probe(state);
+}
+
+// The moves that mate soonest, hold the draw, or put off mate longest
+pub fn best_moves(state: &mut State) -> Option<Vec<Motion>> {
+    probe(state)?;
+    let mut scored = Vec::new();
+    for motion in state.moves.parity_vect(state.turn) {
+        state.make_motion(&motion, false);
+        let reply = probe(state);
+        // Taking the last piece, or promoting to a lone minor piece, leaves nothing to mate with
+        let drawn = placement(state).is_none();
+        state.unmake_last(true);
+        let score = match reply {
+            Some(value) => -value.score(),
+            None if drawn => 0,
+            None => return None
+        };
+        scored.push((motion, score));
+    }
+    let best = scored.iter().map(|(_, score)| *score).max()?;
+    return Some(scored.into_iter().filter(|(_, score)| *score == best).map(|(motion, _)| motion).collect());
+}
@@ -6,74 +6,25 @@ use crate::lib::{
     }, point::Point
 };
 
-use super::{motion::{Motion, MotionSet}, state::RetainedStateInfo, zobrist::Zobrist};
-
-const BISHOP_DIRS: [Point; 4] = [ Point {x: -1, y: -1 }, Point { x: -1, y: 1 }, Point { x: 1, y: 1 }, Point { x: 1, y: -1 } ];
-const ROOK_DIRS: [Point; 4] = [ Point { x: 0, y: 1 }, Point { x: 0, y: -1 }, Point { x: 1, y: 0 }, Point { x: -1, y: 0 } ];
+use super::{atomic, attacks::{self, between, pawn_attacks}, crazyhouse::{self, Pocket}, motion::{Motion, MotionSet, PROMOTION_CHOICES}, state::RetainedStateInfo, zobrist::Zobrist};
 
+// Splits an attack set into squares that can be moved to and friendly squares that are only defended
+#[inline(always)]
+fn split_attacks(attacks: Mask, enemy_mask: &Mask, piece_mask: &Mask) -> (Mask, Mask) {
+    let friendly = *piece_mask & enemy_mask.get_not();
+    return (attacks & friendly.get_not(), attacks & friendly);
+}
+#[inline(always)]
 fn bishop_move(bishop_index: usize, enemy_mask: &Mask, piece_mask: &Mask) -> (Mask, Mask) {
-    let mut hits = 0b0000_0000;
-    let mut move_mask = Mask::default();
-    let mut defense_mask = Mask::default();
-    let pos_pos = Point::from_index(bishop_index);
-    for i in 1..=8 {
-        for k in 0..4 {
-            if hits & (1 << k) == 0 {
-                let desire = pos_pos + BISHOP_DIRS[k] * i;
-                if desire.valid() {
-                    let desire_mask = Mask::from_point(desire);
-                    if (*piece_mask & desire_mask).any() {
-                        if (*enemy_mask & desire_mask).any() {
-                            move_mask |= desire_mask;
-                        } else {
-                            defense_mask |= desire_mask;
-                        }
-                        hits |= 1 << k;
-                    } else {
-                        move_mask |= desire_mask;
-                    }
-                } else {
-                    hits |= 1 << k;
-                }
-            }
-        }
-    }
-    return (move_mask, defense_mask);
+    return split_attacks(attacks::bishop_attacks(bishop_index, piece_mask), enemy_mask, piece_mask);
 }
+#[inline(always)]
 fn rook_move(rook_index: usize, enemy_mask: &Mask, piece_mask: &Mask) -> (Mask, Mask) {
-    let mut hits = 0b0000_0000;
-    let mut move_mask = Mask::default();
-    let mut defense_mask = Mask::default();
-    let pos_pos = Point::from_index(rook_index);
-    for i in 1..=8 {
-        for k in 0..4 {
-            if hits & (1 << k) == 0 {
-                let desire = pos_pos + ROOK_DIRS[k] * i;
-                if desire.valid() {
-                    let desire_mask = Mask::from_point(desire);
-                    if (*piece_mask & desire_mask).any() {
-                        if (*enemy_mask & desire_mask).any() {
-                            move_mask |= desire_mask;
-                        } else {
-                            defense_mask |= desire_mask;
-                        }
-                        hits |= 1 << k;
-                    } else {
-                        move_mask |= desire_mask;
-                    }
-                } else {
-                    hits |= 1 << k;
-                }
-            }
-        }
-    }
-    return (move_mask, defense_mask);
+    return split_attacks(attacks::rook_attacks(rook_index, piece_mask), enemy_mask, piece_mask);
 }
-#[inline]
+#[inline(always)]
 fn queen_move(queen_index: usize, enemy_mask: &Mask, piece_mask: &Mask) -> (Mask, Mask) {
-    let r = rook_move(queen_index, enemy_mask, piece_mask);
-    let b = bishop_move(queen_index, enemy_mask, piece_mask);
-    return (r.0 | b.0, r.1 | b.1);
+    return split_attacks(attacks::queen_attacks(queen_index, piece_mask), enemy_mask, piece_mask);
 }
 fn pawn_move(pawn_index: usize, enemy_mask: &Mask, piece_mask: &Mask, parity: Parity, ignore_diagonal_enemy_requirement: bool, enpassant: &Mask) -> (Mask, Mask) {
     let mut move_mask = Mask::default();
@@ -92,86 +43,54 @@ fn pawn_move(pawn_index: usize, enemy_mask: &Mask, piece_mask: &Mask, parity: Pa
             }
         }
     }
-    let pos_pos = Point::from_index(pawn_index);
-    let ydir = if basic.raw > pos_mask.raw { 1 } else { -1 };
-
-    let diag1 = pos_pos + Point { x: 1, y: ydir };
-    if diag1.valid() {
-        if (*piece_mask & diag1).any() && (*enemy_mask & diag1).none() {
-            defense_mask |= diag1;
-        }
-        if (*enemy_mask & diag1).any() || ignore_diagonal_enemy_requirement {
-            move_mask |= diag1;
-        } else if (*piece_mask & *enpassant).none() && (*enpassant & diag1).any() {
-            move_mask |= diag1;
-        }
-    }
-    let diag2 = pos_pos + Point { x: -1, y: ydir };
-    if diag2.valid() {
-        if (*piece_mask & diag2).any() && (*enemy_mask & diag2).none() {
-            defense_mask |= diag2;
+    for diag in pawn_attacks(pawn_index, parity).isolated_bits() {
+        if (*piece_mask & diag).any() && (*enemy_mask & diag).none() {
+            defense_mask |= diag;
         }
-        if (*enemy_mask & diag2).any() || ignore_diagonal_enemy_requirement {
-            move_mask |= diag2;
-        } else if (*piece_mask & *enpassant).none() && (*enpassant & diag2).any() {
-            move_mask |= diag2;
+        if (*enemy_mask & diag).any() || ignore_diagonal_enemy_requirement {
+            move_mask |= diag;
+        } else if (*piece_mask & *enpassant).none() && (*enpassant & diag).any() {
+            move_mask |= diag;
         }
     }
     return (move_mask, defense_mask);
 }
+#[inline(always)]
 fn knight_move(knight_index: usize, enemy_mask: &Mask, piece_mask: &Mask) -> (Mask, Mask) {
-    let mut move_mask = Mask::default();
-    let pos_pos = Point::from_index(knight_index);
-    let offsets = [
-        Point { x: -2, y: -1 }, 
-        Point { x: -2, y: 1 }, 
-        Point { x: -1, y: -2 }, 
-        Point { x: -1, y: 2 }, 
-        Point { x: 1, y: -2 }, 
-        Point { x: 1, y: 2 }, 
-        Point { x: 2, y: -1 }, 
-        Point { x: 2, y: 1 }
-    ];
-    for offset in offsets {
-        let pv = pos_pos + offset;
-        if pv.valid() {
-            move_mask |= Mask::from_point(pv);
+    return split_attacks(attacks::knight_attacks(knight_index), enemy_mask, piece_mask);
+}
+#[inline(always)]
+fn king_move(king_index: usize, enemy_mask: &Mask, piece_mask: &Mask) -> (Mask, Mask) {
+    return split_attacks(attacks::king_attacks(king_index), enemy_mask, piece_mask);
+}
+// For every piece of the king's colour, the squares it may move to without exposing the king.
+// Unpinned pieces get a full mask.
+fn pin_rays(board: &[u8; 64], king_index: usize, maskset: &MaskSet) -> [Mask; 64] {
+    let mut rays = [Mask::default().get_not(); 64];
+    let parity = board[king_index].get_parity();
+    let empty = Mask::default();
+    let snipers = (attacks::rook_attacks(king_index, &empty) & maskset.orthogonal_sliders(!parity))
+        | (attacks::bishop_attacks(king_index, &empty) & maskset.diagonal_sliders(!parity));
+    let friends = maskset.parity(parity);
+    for sniper in snipers.isolated_bits() {
+        let ray = between(king_index, sniper.as_index());
+        let blockers = ray & maskset.all;
+        if blockers.raw.count_ones() == 1 && (blockers & friends).any() {
+            rays[blockers.as_index()] = ray | sniper;
         }
     }
-    return (move_mask & (enemy_mask.get_not() & *piece_mask).get_not(), move_mask & *piece_mask & enemy_mask.get_not());
-    
+    return rays;
 }
-fn king_move(king_index: usize, enemy_mask: &Mask, piece_mask: &Mask) -> (Mask, Mask) {
-    let mut move_mask = Mask::default();
-    let mut defense_mask = Mask::default();
-    let mut maxx = 2i32;
-    let mut minx = -1i32;
-    let mut maxy = 2i32;
-    let mut miny = -1i32;
-    let kim8 = king_index % 8;
-    let kid8 = king_index / 8;
-    if kim8 == 7 { maxx -= 1 };
-    if kim8 == 0 { minx += 1 };
-    if kid8 == 7 { maxy -= 1 };
-    if kid8 == 0 { miny += 1 };
-
-    for y in miny..maxy {
-        for x in minx..maxx {
-            if x == 0 && y == 0 { continue };
-            let thispos = Mask::from_index((king_index as i32 + y * 8 + x) as usize);
-            if (*piece_mask & thispos).any() {
-                if (*enemy_mask & thispos).any() {
-                    move_mask |= thispos;
-                } else {
-                    defense_mask |= thispos;
-                }
-            } else {
-                move_mask |= thispos;
-            }
+#[inline(always)]
+fn promotion_or_queen(promotion: PieceByte) -> PieceByte { if promotion == PieceByte::NONE { PieceByte::QUEEN } else { promotion } }
+fn push_motions(into: &mut Vec<Motion>, byte: u8, from: usize, to: usize) -> () {
+    if byte.is_pawn() && (to < 8 || to > 55) {
+        for promotion in PROMOTION_CHOICES {
+            into.push(Motion { from, to, promotion });
         }
+    } else {
+        into.push(Motion { from, to, promotion: PieceByte::NONE });
     }
-
-    return (move_mask, defense_mask);
 }
 
 impl std::ops::Index<Mask> for [u8; 64] {
@@ -196,21 +115,176 @@ impl std::ops::Index<Point> for [u8; 64] {
         return &self[((index.y * 8) + index.x) as usize];
     }
 }
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum MoveType {
     ENPASSANT,
     PROMOTION,
     CASTLE,
     TAKE,
-    MOVE
+    MOVE,
+    DROP,
+    NULL
+}
+// What make changed, so unmake can put it back without a copy of the board
+#[derive(Clone, Copy)]
+pub struct Undo {
+    pub motion: Motion,
+    pub kind: MoveType,
+    pub moved: u8,
+    // For castles this is the rook, which stood on motion.to
+    pub captured: u8,
+    pub captured_at: usize,
+    pub zkey: u64,
+    pub allowed_castles: u8,
+    pub enpassant_mask: Mask,
+    pub king_indices: [usize; 2],
+    pub halfmove_clock: u64,
+    pub fullmove_number: u64,
+    pub checks: [u8; 2],
+    // Atomic only: what the blast removed, one slot per square of the blast zone in index order
+    pub exploded: [u8; 9],
+    pub pockets: [Pocket; 2]
+}
+fn blank_undo(motion: &Motion, current_info: &RetainedStateInfo) -> Undo {
+    return Undo {
+        motion: *motion,
+        kind: MoveType::NULL,
+        moved: 0,
+        captured: 0,
+        captured_at: 65,
+        zkey: current_info.zkey,
+        allowed_castles: current_info.allowed_castles,
+        enpassant_mask: current_info.enpassant_mask,
+        king_indices: current_info.king_indices,
+        halfmove_clock: current_info.halfmove_clock,
+        fullmove_number: current_info.fullmove_number,
+        checks: current_info.checks,
+        exploded: [0u8; 9],
+        pockets: current_info.pockets
+    };
+}
+// Where the king and rook land, which is the g and f files or the c and d files whatever files they started on
+#[inline(always)]
+pub fn castle_targets(from: usize, to: usize) -> (usize, usize) {
+    let rank = from - from % 8;
+    return if from > to { (rank + 2, rank + 3) } else { (rank + 6, rank + 5) };
+}
+// Every square on the rank from a to b, both included
+#[inline(always)]
+fn rank_span(a: usize, b: usize) -> Mask {
+    let (low, high) = (a.min(b), a.max(b));
+    return Mask { raw: (u64::MAX >> (63 - high)) & (u64::MAX << low) };
+}
+// Whether the motion takes an enemy piece, counting en passant
+pub fn is_capture(board: &[u8; 64], motion: &Motion) -> bool {
+    if motion.is_drop() {
+        return false;
+    }
+    let (mover, target) = (board[motion.from], board[motion.to]);
+    if target.is_piece() {
+        return !target.same_parity(&mover);
+    }
+    return mover.is_pawn() && motion.from % 8 != motion.to % 8;
+}
+// The rooks the king may castle with, with the right still held and nothing else standing where either piece passes.
+// Whether the king's path is attacked is left to the caller.
+fn castling_rooks(board: &[u8; 64], maskset: &MaskSet, king: usize, castles: Option<(u8, [usize; 4])>) -> Vec<usize> {
+    let mut rooks = Vec::new();
+    let parity = board[king].get_parity();
+    let Some((allowed_castles, castle_rooks)) = castles else { return rooks };
+    let bits: [usize; 2] = if parity == Parity::WHITE { [2, 3] } else { [0, 1] };
+    let back_rank = if parity == Parity::WHITE { 7 } else { 0 };
+    if king / 8 != back_rank {
+        return rooks;
+    }
+    for bit in bits {
+        let rook = castle_rooks[bit];
+        if allowed_castles & (1 << bit) == 0 || !board[rook].is_rook() || !board[rook].is_parity(parity) {
+            continue;
+        }
+        let (king_to, rook_to) = castle_targets(king, rook);
+        let movers = Mask::from_index(king) | Mask::from_index(rook);
+        if ((rank_span(king, king_to) | rank_span(rook, rook_to)) & maskset.all & movers.get_not()).none() {
+            rooks.push(rook);
+        }
+    }
+    return rooks;
+}
+// Every move each side could make ignoring its own king's safety, along with the friendly squares it defends
+fn pseudo_motions(board: &[u8; 64], maskset: &MaskSet, enpassant: &Mask) -> MotionSet {
+    let mut ms = MotionSet::default();
+    for (index, byte) in board.iter().enumerate() {
+        let enemy = if byte.get_parity() == Parity::WHITE { &maskset.black } else { &maskset.white };
+        let m = match byte.get_piece() {
+            PieceByte::ROOK => rook_move(index, enemy, &maskset.all),
+            PieceByte::BISHOP => bishop_move(index, enemy, &maskset.all),
+            PieceByte::QUEEN => queen_move(index, enemy, &maskset.all),
+            PieceByte::PAWN => pawn_move(index, enemy, &maskset.all, byte.get_parity(), false, &enpassant),
+            PieceByte::KNIGHT => knight_move(index, enemy, &maskset.all),
+            PieceByte::KING => king_move(index, enemy, &maskset.all),
+            PieceByte::NONE => (Mask::default(), Mask::default())
+        };
+        if byte.is_parity(Parity::WHITE) {
+            for bit in m.0.isolated_bits().iter() {
+                push_motions(&mut ms.white_moves[index], *byte, index, bit.as_index());
+            }
+            for bit in m.1.isolated_bits().iter() {
+                ms.white_defensive_moves[index].push(Motion { from: index, to: bit.as_index(), promotion: PieceByte::NONE });
+            }
+        } else if byte.is_parity(Parity::BLACK) {
+            for bit in m.0.isolated_bits().iter() {
+                push_motions(&mut ms.black_moves[index], *byte, index, bit.as_index());
+            }
+            for bit in m.1.isolated_bits().iter() {
+                ms.black_defensive_moves[index].push(Motion { from: index, to: bit.as_index(), promotion: PieceByte::NONE });
+            }
+        }
+    }
+    return ms;
+}
+// Collects each side's pruned moves into its move list and flat masks
+fn flatten_motions(board: &[u8; 64], ms: &mut MotionSet) -> () {
+    for i in 0..64 {
+        for m in &ms.white_moves[i] {
+            ms.white_vect.push(*m);
+            if board[m.to].is_w_rook() { continue };
+            let mtom = Mask::from_index(m.to);
+            ms.white_flat |= mtom;
+            ms.white_piecewise_flat[i] |= mtom;
+        }
+        for m in &ms.white_defensive_moves[i] {
+            let mtom = Mask::from_index(m.to);
+            ms.white_defensive_flat |= mtom;
+            ms.white_defensive_piecewise_flat[i] |= mtom;
+        }
+        for m in &ms.black_moves[i] {
+            ms.black_vect.push(*m);
+            if board[m.to].is_b_rook() { continue };
+            let mtom = Mask::from_index(m.to);
+            ms.black_flat |= mtom;
+            ms.black_piecewise_flat[i] |= mtom;
+        }
+        for m in &ms.black_defensive_moves[i] {
+            let mtom = Mask::from_index(m.to);
+            ms.black_defensive_flat |= mtom;
+            ms.black_defensive_piecewise_flat[i] |= mtom;
+        }
+    }
 }
 pub trait BoardArray {
     fn flipped(&self) -> Self;
-    fn make(&mut self, from: usize, to: usize, zobrist: Arc<Mutex<Zobrist>>, current_info: &mut RetainedStateInfo, debugging_enabled: bool) -> ([u8; 64], RetainedStateInfo);
-    fn make_soft(&mut self, from: usize, to: usize) -> [u8; 64]; 
-    fn unmake(&mut self, original_board: &[u8; 64], original_info: &RetainedStateInfo, current_info: &mut RetainedStateInfo) -> ();
+    fn make(&mut self, motion: &Motion, zobrist: Arc<Mutex<Zobrist>>, current_info: &mut RetainedStateInfo, debugging_enabled: bool) -> Undo;
+    fn make_soft(&mut self, motion: &Motion) -> [u8; 64];
+    fn make_drop(&mut self, motion: &Motion, parity: Parity, zobrist: Arc<Mutex<Zobrist>>, current_info: &mut RetainedStateInfo) -> Undo;
+    fn unmake(&mut self, undo: &Undo, current_info: &mut RetainedStateInfo) -> ();
+    fn explode(&mut self, undo: &mut Undo, zobrist: Arc<Mutex<Zobrist>>, current_info: &mut RetainedStateInfo) -> ();
     fn index_in_check(&self, index: usize, ip: Parity, info: &RetainedStateInfo) -> bool;
-    fn get_motions(&self, maskset: &MaskSet, enpassant: &Mask, castles: Option<u8>) -> MotionSet;
+    fn get_motions(&self, maskset: &MaskSet, enpassant: &Mask, castles: Option<(u8, [usize; 4])>, pockets: Option<&[Pocket; 2]>) -> MotionSet;
+    fn get_atomic_motions(&self, maskset: &MaskSet, enpassant: &Mask, castles: Option<(u8, [usize; 4])>) -> MotionSet;
+    fn get_antichess_motions(&self, maskset: &MaskSet, enpassant: &Mask) -> MotionSet;
     fn get_specific_motions(&self, ally_parity: Parity, maskset: &MaskSet, enpassant: &Mask) -> Mask;
+    fn attack_map(&self, of_parity: Parity, occupancy: &Mask) -> Mask;
+    fn checkers(&self, king_index: usize, maskset: &MaskSet) -> Mask;
     fn king_in_check(&self, king_index: usize, maskset: &MaskSet, enpassant: &Mask) -> bool;
     fn get_kings(&self) -> [usize; 2];
 }
@@ -282,31 +356,90 @@ impl BoardArray for [u8; 64] {
     }
 
     fn index_in_check(&self, index: usize, ip: Parity, info: &RetainedStateInfo) -> bool {
-        return (Mask::from_index(index) & self.get_specific_motions(!ip, &info.maskset, &info.enpassant_mask)).any();
+        return (Mask::from_index(index) & info.maskset.attack_map(!ip, &info.maskset.all)).any();
     }
-    fn king_in_check(&self, king_index: usize, maskset: &MaskSet, enpassant: &Mask) -> bool {
-        return (Mask::from_index(king_index) & self.get_specific_motions(!self[king_index].get_parity(), maskset, enpassant)).any();
+    fn king_in_check(&self, king_index: usize, maskset: &MaskSet, _enpassant: &Mask) -> bool {
+        return (Mask::from_index(king_index) & maskset.attack_map(!self[king_index].get_parity(), &maskset.all)).any();
     }
-    fn unmake(&mut self, original_board: &[u8; 64], original_info: &RetainedStateInfo, current_info: &mut RetainedStateInfo) -> () {
-        *self = *original_board;
-        *current_info = RetainedStateInfo {
-            maskset: MaskSet {
-                all: original_info.maskset.all,
-                white: original_info.maskset.white,
-                black: original_info.maskset.black
+    fn unmake(&mut self, undo: &Undo, current_info: &mut RetainedStateInfo) -> () {
+        let (from, to) = (undo.motion.from, undo.motion.to);
+        if undo.exploded.iter().any(|byte| byte.is_piece()) {
+            for (slot, iso) in atomic::blast_zone(to).isolated_bits().iter().enumerate() {
+                if undo.exploded[slot].is_piece() {
+                    self[iso.as_index()] = undo.exploded[slot];
+                    current_info.maskset.toggle(iso.as_index(), undo.exploded[slot]);
+                }
+            }
+        }
+        match undo.kind {
+            MoveType::NULL => {},
+            MoveType::DROP => {
+                current_info.maskset.toggle(to, self[to]);
+                self[to] = 0;
             },
-            king_indices: original_info.king_indices,
-            halfmove_clock: original_info.halfmove_clock,
-            fullmove_number: original_info.fullmove_number,
-            zkey: original_info.zkey,
-            allowed_castles: original_info.allowed_castles,
-            enpassant_mask: original_info.enpassant_mask
-        };
+            MoveType::CASTLE => {
+                let (king_to, rook_to) = castle_targets(from, to);
+                current_info.maskset.toggle(king_to, self[king_to]);
+                current_info.maskset.toggle(rook_to, self[rook_to]);
+                self[king_to] = 0;
+                self[rook_to] = 0;
+                self[from] = undo.moved;
+                self[to] = undo.captured;
+                current_info.maskset.toggle(from, undo.moved);
+                current_info.maskset.toggle(to, undo.captured);
+            },
+            _ => {
+                current_info.maskset.toggle(to, self[to]);
+                self[to] = 0;
+                self[from] = undo.moved;
+                current_info.maskset.toggle(from, undo.moved);
+                if undo.captured.is_piece() {
+                    self[undo.captured_at] = undo.captured;
+                    current_info.maskset.toggle(undo.captured_at, undo.captured);
+                }
+            }
+        }
+        current_info.zkey = undo.zkey;
+        current_info.allowed_castles = undo.allowed_castles;
+        current_info.enpassant_mask = undo.enpassant_mask;
+        current_info.king_indices = undo.king_indices;
+        current_info.halfmove_clock = undo.halfmove_clock;
+        current_info.fullmove_number = undo.fullmove_number;
+        current_info.checks = undo.checks;
+        current_info.pockets = undo.pockets;
     }
 
+    // Follows a capture made by make in Atomic, blowing up the capturer and the non-pawns around it
+    fn explode(&mut self, undo: &mut Undo, zobrist: Arc<Mutex<Zobrist>>, current_info: &mut RetainedStateInfo) -> () {
+        let zrist = zobrist.lock().unwrap();
+        let at = undo.motion.to;
+        current_info.zkey ^= zrist.castles(current_info.allowed_castles, &current_info.castle_rooks);
+        for (slot, iso) in atomic::blast_zone(at).isolated_bits().iter().enumerate() {
+            let index = iso.as_index();
+            let byte = self[index];
+            if !byte.is_piece() || (byte.is_pawn() && index != at) {
+                continue;
+            }
+            undo.exploded[slot] = byte;
+            current_info.zkey ^= zrist.pieces(&byte, index);
+            current_info.maskset.toggle(index, byte);
+            self[index] = 0;
+            if byte.is_king() {
+                current_info.king_indices[if byte.is_white() { 0 } else { 1 }] = 65;
+                current_info.allowed_castles &= if byte.is_white() { 0b0000_0011 } else { 0b0000_1100 };
+            }
+            for (bit, rook) in current_info.castle_rooks.iter().enumerate() {
+                if index == *rook {
+                    current_info.allowed_castles &= !(1u8 << bit);
+                }
+            }
+        }
+        current_info.zkey ^= zrist.castles(current_info.allowed_castles, &current_info.castle_rooks);
+    }
 
-    fn make_soft(&mut self, from: usize, to: usize) -> [u8; 64] {
+    fn make_soft(&mut self, motion: &Motion) -> [u8; 64] {
         let og = self.clone();
+        let (from, to) = (motion.from, motion.to);
         let is_enpassant = self[from].is_pawn() && !self[to].is_piece() && from % 8 != to % 8;
         let is_promotion = self[from].is_pawn() && (to < 8 || to > 55);
         let is_castle = self[to].is_piece() && self[from].same_parity(&self[to]) && self[from].is_king() && self[to].is_rook();
@@ -317,21 +450,16 @@ impl BoardArray for [u8; 64] {
             self[to] |= 0b1000_0000;
         } else if is_promotion {
             self.swap(from, to);
-            self[to] ^= 0b0000_0110;
+            self[to] = (self[to] & !0b0000_0111) | promotion_or_queen(motion.promotion) as u8;
             self[to] |= 0b1000_0000;
             self[from] = 0;
         } else if is_castle {
-            if from > to {
-                self.swap(from, from - 2);
-                self.swap(to, from - 1);
-                self[from - 2] |= 0b1000_0000;
-                self[from - 1] |= 0b1000_0000;
-            } else {
-                self.swap(from, from + 2);
-                self.swap(to, from + 1);
-                self[from + 2] |= 0b1000_0000;
-                self[from + 1] |= 0b1000_0000;
-            }
+            let (king, rook) = (self[from], self[to]);
+            let (king_to, rook_to) = castle_targets(from, to);
+            self[from] = 0;
+            self[to] = 0;
+            self[king_to] = king | 0b1000_0000;
+            self[rook_to] = rook | 0b1000_0000;
         } else if is_take {
             self.swap(from, to);
             self[from] = 0;
@@ -343,21 +471,47 @@ impl BoardArray for [u8; 64] {
         }
         return og;
     }
-    fn make(&mut self, from: usize, to: usize, zobrist: Arc<Mutex<Zobrist>>, current_info: &mut RetainedStateInfo, debugging_enabled: bool) -> ([u8; 64], RetainedStateInfo) {
-        let original_info = current_info.clone();
-
-        let original_board = self.clone();
+    // Takes the piece out of the parity's pocket and puts it on the board. It counts as having moved, like anything that arrived.
+    fn make_drop(&mut self, motion: &Motion, parity: Parity, zobrist: Arc<Mutex<Zobrist>>, current_info: &mut RetainedStateInfo) -> Undo {
+        let mut undo = blank_undo(motion, current_info);
+        let (side, slot) = (if parity == Parity::WHITE { 0 } else { 1 }, crazyhouse::pocket_slot(motion.promotion).unwrap());
+        let byte = (parity | motion.promotion) | 0b1000_0000;
+        let zrist = zobrist.lock().unwrap();
+        let held = current_info.pockets[side][slot];
+        undo.kind = MoveType::DROP;
+        undo.moved = byte;
+        current_info.zkey ^= zrist.zside ^ zrist.passant(&current_info.enpassant_mask);
+        current_info.zkey ^= zrist.pocket(side, slot, held) ^ zrist.pocket(side, slot, held - 1);
+        current_info.zkey ^= zrist.pieces(&byte, motion.to);
+        current_info.pockets[side][slot] = held - 1;
+        current_info.enpassant_mask = Mask::default();
+        current_info.halfmove_clock += 1;
+        if parity == Parity::BLACK {
+            current_info.fullmove_number += 1;
+        }
+        current_info.maskset.toggle(motion.to, byte);
+        self[motion.to] = byte;
+        return undo;
+    }
+    fn make(&mut self, motion: &Motion, zobrist: Arc<Mutex<Zobrist>>, current_info: &mut RetainedStateInfo, debugging_enabled: bool) -> Undo {
+        let (from, to) = (motion.from, motion.to);
+        let mut undo = blank_undo(motion, current_info);
         if from == 65 && to == 65 {
             if debugging_enabled {
                 println!("Null move");
             }
+            let zrist = zobrist.lock().unwrap();
+            current_info.zkey ^= zrist.zside ^ zrist.passant(&current_info.enpassant_mask);
             current_info.enpassant_mask = Mask::default();
-            return (original_board, original_info);
+            return undo;
         }
         if !self[from].is_piece() { 
             println!("Cannot make move. No from piece at index {from}! Returning original board");
-            return (original_board, original_info);
+            return undo;
         }
+        undo.moved = self[from];
+        let zrist = &zobrist.lock().unwrap();
+        current_info.zkey ^= zrist.zside ^ zrist.passant(&current_info.enpassant_mask) ^ zrist.castles(current_info.allowed_castles, &current_info.castle_rooks);
         let is_enpassant = self[from].is_pawn() && !self[to].is_piece() && from % 8 != to % 8;
         current_info.enpassant_mask = Mask::default();
         if self[from].is_pawn() && from.abs_diff(to) > 15 {
@@ -367,28 +521,35 @@ impl BoardArray for [u8; 64] {
         let is_promotion = self[from].is_pawn() && (to < 8 || to > 55);
         let is_castle = self[to].is_piece() && self[from].same_parity(&self[to]) && self[from].is_king() && self[to].is_rook();
         let is_take = self[to].is_piece() && !self[to].same_parity(&self[from]);
-        if from == 60 || to == 60 {
-            current_info.allowed_castles &= 0b0000_0011;
-        } else if from == 4 || to == 4 {
-            current_info.allowed_castles &= 0b0000_1100;
+        if self[from].is_pawn() || is_take {
+            current_info.halfmove_clock = 0;
+        } else {
+            current_info.halfmove_clock += 1;
         }
-        if from == 56 || to == 56 {
-            current_info.allowed_castles &= 0b0000_0111;
-        } else if from == 63 || to == 63 {
-            current_info.allowed_castles &= 0b0000_1011;
+        if self[from].is_black() {
+            current_info.fullmove_number += 1;
         }
-        if from == 0 || to == 0 {
-            current_info.allowed_castles &= 0b0000_1101;
-        } else if from == 7 || to == 7 {
-            current_info.allowed_castles &= 0b0000_1110;
+        if self[from].is_king() {
+            current_info.allowed_castles &= if self[from].is_white() { 0b0000_0011 } else { 0b0000_1100 };
+        }
+        for (bit, rook) in current_info.castle_rooks.iter().enumerate() {
+            if from == *rook || to == *rook {
+                current_info.allowed_castles &= !(1u8 << bit);
+            }
         }
-        let zrist = &zobrist.lock().unwrap();
         if is_enpassant {
             if debugging_enabled {
                 println!("Move {from} -> {to} is an enpassant.");
             }
             current_info.zkey ^= zrist.pieces(&(PieceByte::PAWN | self[from].get_parity()), from);
             current_info.zkey ^= zrist.pieces(&(PieceByte::PAWN | self[from].get_parity()), to);
+            let captured = if from > to { to + 8 } else { to - 8 };
+            undo.kind = MoveType::ENPASSANT;
+            undo.captured = self[captured];
+            undo.captured_at = captured;
+            current_info.maskset.toggle(from, self[from]);
+            current_info.maskset.toggle(to, self[from]);
+            current_info.maskset.toggle(captured, self[captured]);
             if from > to {
                 current_info.zkey ^= zrist.pieces(&(PieceByte::PAWN | !self[from].get_parity()), to + 8);
                 self[to + 8] = 0;
@@ -399,50 +560,65 @@ impl BoardArray for [u8; 64] {
             self.swap(from, to);
             self[to] |= 0b1000_0000;
         } else if is_promotion {
+            let promotion = promotion_or_queen(motion.promotion);
             if debugging_enabled {
-                println!("Move {from} -> {to} is a promotion.");
+                println!("Move {from} -> {to} is a promotion to {promotion}.");
             }
 
+            undo.kind = MoveType::PROMOTION;
+            undo.captured = self[to];
+            undo.captured_at = to;
             current_info.zkey ^= zrist.pieces(&(PieceByte::PAWN | self[from].get_parity()), from);
-            current_info.zkey ^= zrist.pieces(&(PieceByte::QUEEN | self[from].get_parity()), to);
+            if self[to].is_piece() {
+                current_info.zkey ^= zrist.pieces(&(self[to].get_piece() | self[to].get_parity()), to);
+            }
+            current_info.zkey ^= zrist.pieces(&(promotion | self[from].get_parity()), to);
+            current_info.maskset.toggle(from, self[from]);
+            if self[to].is_piece() {
+                current_info.maskset.toggle(to, self[to]);
+            }
+            self[to] = 0;
             self.swap(from, to);
-            self[to] ^= 0b0000_0110;
-            self[to] |= 0b1000_0000;
-            self[from] = 0;
+            self[to] = (self[to] & !0b0000_0111) | promotion as u8;
+            self[to] |= 0b1000_0000 | crazyhouse::PROMOTED;
+            current_info.maskset.toggle(to, self[to]);
         
         } else if is_castle {
             if debugging_enabled {
                 println!("Move {from} -> {to} is a castle.");
             }
-            current_info.allowed_castles &= if from == 60 { 0b0000_0011 } else { 0b0000_1100 };
-            current_info.zkey ^= zrist.pieces(&(PieceByte::KING | self[from].get_parity()), from);
-            current_info.zkey ^= zrist.pieces(&(PieceByte::ROOK | self[to].get_parity()), to);
-            if from > to {
-                current_info.king_indices[if self[from].is_white() { 0 } else { 1 }] = from - 2;
-                current_info.zkey ^= zrist.pieces(&(PieceByte::KING | self[from].get_parity()), from - 2);
-                current_info.zkey ^= zrist.pieces(&(PieceByte::ROOK | self[from].get_parity()), from - 1);
-                self.swap(from, from - 2);
-                self.swap(to, from - 1);
-                self[from - 2] |= 0b1000_0000;
-                self[from - 1] |= 0b1000_0000;
-            } else {
-                current_info.king_indices[if self[from].is_white() { 0 } else { 1 }] = from + 2;
-                current_info.zkey ^= zrist.pieces(&(PieceByte::KING | self[from].get_parity()), from + 2);
-                current_info.zkey ^= zrist.pieces(&(PieceByte::ROOK | self[from].get_parity()), from + 1);
-                self.swap(from, from + 2);
-                self.swap(to, from + 1);
-                self[from + 2] |= 0b1000_0000;
-                self[from + 1] |= 0b1000_0000;
-            }
+            undo.kind = MoveType::CASTLE;
+            undo.captured = self[to];
+            undo.captured_at = to;
+            let (king, rook) = (self[from], self[to]);
+            let (king_to, rook_to) = castle_targets(from, to);
+            current_info.zkey ^= zrist.pieces(&king, from) ^ zrist.pieces(&rook, to);
+            current_info.zkey ^= zrist.pieces(&king, king_to) ^ zrist.pieces(&rook, rook_to);
+            current_info.maskset.toggle(from, king);
+            current_info.maskset.toggle(to, rook);
+            current_info.maskset.toggle(king_to, king);
+            current_info.maskset.toggle(rook_to, rook);
+            current_info.king_indices[if king.is_white() { 0 } else { 1 }] = king_to;
+            // In Chess960 the king or rook may land where the other started, so clear both before placing
+            self[from] = 0;
+            self[to] = 0;
+            self[king_to] = king | 0b1000_0000;
+            self[rook_to] = rook | 0b1000_0000;
         } else if is_take {
             if debugging_enabled {
                 println!("Move {from} -> {to} is a take.");
             }
+            undo.kind = MoveType::TAKE;
+            undo.captured = self[to];
+            undo.captured_at = to;
             if self[from].is_king() {
                 current_info.king_indices[if self[from].is_white() { 0 } else { 1 }] = to;
             }
             current_info.zkey ^= zrist.pieces(&(self[from].get_piece() | self[from].get_parity()), from);
             current_info.zkey ^= zrist.pieces(&(self[to].get_piece() | self[to].get_parity()), to);
+            current_info.maskset.toggle(to, self[to]);
+            current_info.maskset.toggle(from, self[from]);
+            current_info.maskset.toggle(to, self[from]);
             self.swap(from, to);
             self[from] = 0;
             self[to] |= 0b1000_0000;
@@ -451,18 +627,21 @@ impl BoardArray for [u8; 64] {
             if debugging_enabled {
                 println!("Move {from} -> {to} is a normal move.");
             }
+            undo.kind = MoveType::MOVE;
             if self[from].is_king() {
                 current_info.king_indices[if self[from].is_white() { 0 } else { 1 }] = to;
             }
             current_info.zkey ^= zrist.pieces(&(self[from].get_piece() | self[from].get_parity()), from);
+            current_info.maskset.toggle(from, self[from]);
+            current_info.maskset.toggle(to, self[from]);
             self.swap(from, to);
             current_info.zkey ^= zrist.pieces(&(self[to].get_piece() | self[to].get_parity()), to);
             self[to] |= 0b1000_0000;
             self[from] = 0;
         }
 
-        current_info.maskset = MaskSet::from_board(self);
-        return (original_board, original_info);
+        current_info.zkey ^= zrist.passant(&current_info.enpassant_mask) ^ zrist.castles(current_info.allowed_castles, &current_info.castle_rooks);
+        return undo;
     }
     fn flipped(&self) -> Self {
         let mut array: [u8; 64] = [0u8; 64];
@@ -513,145 +692,129 @@ impl BoardArray for [u8; 64] {
         }
         return mask;
     }
-    fn get_motions(&self, maskset: &MaskSet, enpassant: &Mask, castles: Option<u8>) -> MotionSet {
-        let mut ms = MotionSet::default();
-        let mut wking = 65;
-        let mut bking = 65;
-        for i in 0..64 {
-            if self[i].is_w_king() {
-                wking = i;
-            } else if self[i].is_b_king() {
-                bking = i;
-            }
-            if wking != 65 && bking != 65 { break };
-        }
-        if wking == 65 || bking == 65 { return ms };
+    fn attack_map(&self, of_parity: Parity, occupancy: &Mask) -> Mask {
+        return MaskSet::from_board(self).attack_map(of_parity, occupancy);
+    }
+    fn checkers(&self, king_index: usize, maskset: &MaskSet) -> Mask {
+        return maskset.attackers_of(king_index, self[king_index].get_parity());
+    }
+    // With pockets, as in Crazyhouse, each side's drops follow its board moves in the move list
+    fn get_motions(&self, maskset: &MaskSet, enpassant: &Mask, castles: Option<(u8, [usize; 4])>, pockets: Option<&[Pocket; 2]>) -> MotionSet {
+        let [wking, bking] = maskset.kings();
+        if wking == 65 || bking == 65 { return MotionSet::default() };
+        let mut ms = pseudo_motions(self, maskset, enpassant);
+        let mut drops: [Vec<Motion>; 2] = [Vec::new(), Vec::new()];
 
-        for (index, byte) in self.iter().enumerate() {
-            let enemy = if byte.get_parity() == Parity::WHITE { &maskset.black } else { &maskset.white };
-            let m = match byte.get_piece() {
-                PieceByte::ROOK => rook_move(index, enemy, &maskset.all),
-                PieceByte::BISHOP => bishop_move(index, enemy, &maskset.all),
-                PieceByte::QUEEN => queen_move(index, enemy, &maskset.all),
-                PieceByte::PAWN => pawn_move(index, enemy, &maskset.all, byte.get_parity(), false, &enpassant),
-                PieceByte::KNIGHT => knight_move(index, enemy, &maskset.all),
-                PieceByte::KING => king_move(index, enemy, &maskset.all),
-                PieceByte::NONE => (Mask::default(), Mask::default())
+        // Prune the pseudo-legal moves of each side down to the legal ones, as if that side were to move.
+        for (parity, king) in [(Parity::WHITE, wking), (Parity::BLACK, bking)] {
+            let checkers = self.checkers(king, maskset);
+            // The king can't hide from a slider by stepping along its ray, so look through it
+            let danger = maskset.attack_map(!parity, &(maskset.all ^ Mask::from_index(king)));
+            let evasions = match checkers.bit_count() {
+                0 => Mask::default().get_not(),
+                1 => checkers | between(king, checkers.as_index()),
+                _ => Mask::default()
             };
-            if byte.is_parity(Parity::WHITE) {
-                for bit in m.0.isolated_bits().iter() {
-                    ms.white_moves[index].push(Motion { from: index, to: bit.as_index() });
-                }
-                for bit in m.1.isolated_bits().iter() {
-                    ms.white_defensive_moves[index].push(Motion { from: index, to: bit.as_index() });
-                }
-            } else if byte.is_parity(Parity::BLACK) {
-                for bit in m.0.isolated_bits().iter() {
-                    ms.black_moves[index].push(Motion { from: index, to: bit.as_index() });
+            let pins = pin_rays(self, king, maskset);
+            let moves = if parity == Parity::WHITE { &mut ms.white_moves } else { &mut ms.black_moves };
+            for i in 0..64 {
+                if i == king {
+                    moves[i].retain(|m| (danger & Mask::from_index(m.to)).none());
+                    continue;
                 }
-                for bit in m.1.isolated_bits().iter() {
-                    ms.black_defensive_moves[index].push(Motion { from: index, to: bit.as_index() });
-                }
-            }
-        }
-        let mut cloned = self.clone();
-        for i in 0..64 {
-            ms.white_moves[i].retain(|m| {
-                let held = cloned.make_soft(m.from, m.to);
-                let sp = cloned.get_specific_motions(Parity::BLACK, &MaskSet::from_board(&cloned), &Mask::default());
-                let mut wk = wking;
-                for wki in 0..64 {
-                    if cloned[wki].is_w_king() {
-                        wk = wki;
-                        break;
+                moves[i].retain(|m| {
+                    let to = Mask::from_index(m.to);
+                    if self[m.from].is_pawn() && (to & *enpassant).any() && !self[m.to].is_piece() {
+                        // En passant removes two pieces from a rank at once, which no pin ray can describe
+                        let mut cloned = self.clone();
+                        cloned.make_soft(m);
+                        let after = MaskSet::from_board(&cloned);
+                        return (after.attack_map(!parity, &after.all) & Mask::from_index(king)).none();
                     }
-                }
+                    return (to & evasions & pins[m.from]).any();
+                });
+            }
+            if parity == Parity::WHITE { ms.white_checkers = checkers } else { ms.black_checkers = checkers };
+            if let Some(pockets) = pockets {
+                let side = if parity == Parity::WHITE { 0 } else { 1 };
+                drops[side] = crazyhouse::drops(maskset, &pockets[side], crazyhouse::drop_targets(king, checkers));
+            }
 
-                cloned = held;
-                if (Mask::from_index(wk) & sp).any() {
-                    return false;
-                }
-                return true;
-                 
-            });
-            ms.black_moves[i].retain(|m| {
-                let held = cloned.make_soft(m.from, m.to);
-                let sp = cloned.get_specific_motions(Parity::WHITE, &MaskSet::from_board(&cloned), &Mask::default());
-                let mut bk = bking;
-                for bki in 0..64 {
-                    if cloned[bki].is_b_king() {
-                        bk = bki;
-                        break;
+            if checkers.none() {
+                for rook in castling_rooks(self, maskset, king, castles) {
+                    let movers = Mask::from_index(king) | Mask::from_index(rook);
+                    let path = rank_span(king, castle_targets(king, rook).0);
+                    // The rook may be shielding the king's path from a slider along the back rank
+                    let exposed = maskset.attack_map(!parity, &(maskset.all & movers.get_not()));
+                    if (path & exposed).none() {
+                        moves[king].push(Motion { from: king, to: rook, promotion: PieceByte::NONE });
                     }
                 }
+            }
+        }
 
-                cloned = held;
-                if (Mask::from_index(bk) & sp).any() {
-                    return false;
-                }
-                return true;
-            });
-            for m in &ms.white_moves[i] {
-                ms.white_vect.push(*m);
-                let mtom = Mask::from_index(m.to);
-                ms.white_flat |= mtom;
-                ms.white_piecewise_flat[i] |= mtom;
-            }
-            for m in &ms.white_defensive_moves[i] {
-                let mtom = Mask::from_index(m.to);
-                ms.white_defensive_flat |= mtom;
-                ms.white_defensive_piecewise_flat[i] |= mtom;
-            }
-            for m in &ms.black_moves[i] {
-                ms.black_vect.push(*m);
-                let mtom = Mask::from_index(m.to);
-                ms.black_flat |= mtom;
-                ms.black_piecewise_flat[i] |= mtom;
-            }
-            for m in &ms.black_defensive_moves[i] {
-                let mtom = Mask::from_index(m.to);
-                ms.black_defensive_flat |= mtom;
-                ms.black_defensive_piecewise_flat[i] |= mtom;
-            }
-        }
-        if let Some(allowed_castles) = castles {
-            if !ms.white_moves[wking].is_empty() {
-                let pcast = allowed_castles & if wking == 60 { 0b0000_0011 } else { 0b0000_1100 };
-                if pcast > 0 {
-                    let shallow = self.get_specific_motions(Parity::BLACK, maskset, enpassant);
-                    if pcast & 0b0000_0101 != 0 && wking + 2 < 64 {
-                        let mask = Mask::from_index(wking + 1) | Mask::from_index(wking + 2);
-                        if (mask & maskset.all).none() && ((mask | Mask::from_index(wking)) & shallow).none() {
-                            ms.white_moves[wking].push(Motion { from: wking, to: 63 });
-                        }
-                    }
-                    if pcast & 0b0000_1010 != 0 && wking > 2 {
-                        let mask = Mask::from_index(wking - 1) | Mask::from_index(wking - 2);
-                        if ((mask | Mask::from_index(wking - 3)) & maskset.all).none() && ((mask | Mask::from_index(wking)) & shallow).none() {
-                            ms.white_moves[wking].push(Motion { from: wking, to: 56 });
-                        }
+        flatten_motions(self, &mut ms);
+        let [white_drops, black_drops] = drops;
+        ms.white_vect.extend(white_drops);
+        ms.black_vect.extend(black_drops);
+        return ms;
+    }
+    // Atomic moves are checked by playing each one out, since a capture can clear pieces anywhere around the king
+    fn get_atomic_motions(&self, maskset: &MaskSet, enpassant: &Mask, castles: Option<(u8, [usize; 4])>) -> MotionSet {
+        let [wking, bking] = maskset.kings();
+        if wking == 65 || bking == 65 { return MotionSet::default() };
+        let mut ms = pseudo_motions(self, maskset, enpassant);
+
+        for (parity, king) in [(Parity::WHITE, wking), (Parity::BLACK, bking)] {
+            let in_check = atomic::in_check(maskset, parity);
+            let moves = if parity == Parity::WHITE { &mut ms.white_moves } else { &mut ms.black_moves };
+            for i in 0..64 {
+                moves[i].retain(|m| atomic::is_legal(self, m));
+            }
+            let checkers = if in_check { maskset.attackers_of(king, parity) } else { Mask::default() };
+            if parity == Parity::WHITE { ms.white_checkers = checkers } else { ms.black_checkers = checkers };
+
+            if !in_check {
+                for rook in castling_rooks(self, maskset, king, castles) {
+                    let (king_to, _) = castle_targets(king, rook);
+                    // Try the king on each square it crosses, with the rook lifted off the board
+                    let safe = rank_span(king, king_to).isolated_bits().iter().all(|square| {
+                        let mut crossing = *self;
+                        crossing[king] = 0;
+                        crossing[rook] = 0;
+                        crossing[square.as_index()] = self[king];
+                        return !atomic::in_check(&MaskSet::from_board(&crossing), parity);
+                    });
+                    let castle = Motion { from: king, to: rook, promotion: PieceByte::NONE };
+                    if safe && atomic::is_legal(self, &castle) {
+                        moves[king].push(castle);
                     }
                 }
             }
-            if !ms.black_moves[bking].is_empty() {
-                let pcast = allowed_castles & if bking == 60 { 0b0000_0011 } else { 0b0000_1100 };
-                if pcast > 0 {
-                    let shallow = self.get_specific_motions(Parity::WHITE, maskset, enpassant);
-                    if pcast & 0b0000_0101 != 0 && bking + 2 < 64 {
-                        let mask = Mask::from_index(bking + 1) | Mask::from_index(bking + 2);
-                        if (mask & maskset.all).none() && ((mask | Mask::from_index(bking)) & shallow).none() {
-                            ms.black_moves[bking].push(Motion { from: bking, to: 7 });
-                        }
-                    }
-                    if pcast & 0b0000_1010 != 0 && bking > 2 {
-                        let mask = Mask::from_index(bking - 1) | Mask::from_index(bking - 2);
-                        if ((mask | Mask::from_index(bking - 3)) & maskset.all).none() && ((mask | Mask::from_index(bking)) & shallow).none() {
-                            ms.black_moves[bking].push(Motion { from: bking, to: 0 });
-                        }
-                    }
+        }
+
+        flatten_motions(self, &mut ms);
+        return ms;
+    }
+    // Kings are ordinary pieces that nothing checks and pawns may become one, but a side that can capture must
+    fn get_antichess_motions(&self, maskset: &MaskSet, enpassant: &Mask) -> MotionSet {
+        let mut ms = pseudo_motions(self, maskset, enpassant);
+        for parity in [Parity::WHITE, Parity::BLACK] {
+            let moves = if parity == Parity::WHITE { &mut ms.white_moves } else { &mut ms.black_moves };
+            for i in 0..64 {
+                let mut kings: Vec<Motion> = moves[i].iter()
+                    .filter(|m| m.promotion == PieceByte::QUEEN)
+                    .map(|m| Motion { from: m.from, to: m.to, promotion: PieceByte::KING })
+                    .collect();
+                moves[i].append(&mut kings);
+            }
+            if moves.iter().any(|from| from.iter().any(|m| is_capture(self, m))) {
+                for i in 0..64 {
+                    moves[i].retain(|m| is_capture(self, m));
                 }
             }
         }
+        flatten_motions(self, &mut ms);
         return ms;
     }
 }
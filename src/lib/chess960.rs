@@ -0,0 +1,48 @@
+use voxell_rng::slice_methods::SelectRandom;
+
+pub const POSITION_COUNT: usize = 960;
+// The classical setup, RNBQKBNR, in Scharnagl's numbering
+pub const STANDARD_POSITION: usize = 518;
+
+// The knights' places among the five squares left once the bishops and queen are down
+const KNIGHT_PLACEMENTS: [(usize, usize); 10] = [(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)];
+
+fn place(rank: &mut [char; 8], nth_empty: usize, piece: char) -> () {
+    let index = (0..8).filter(|i| rank[*i] == ' ').nth(nth_empty).unwrap();
+    rank[index] = piece;
+}
+
+// White's back rank of setup n, a file first. Black mirrors it.
+pub fn back_rank(n: usize) -> [char; 8] {
+    let mut rank = [' '; 8];
+    let mut n = n % POSITION_COUNT;
+    rank[(n % 4) * 2 + 1] = 'B';
+    n /= 4;
+    rank[(n % 4) * 2] = 'B';
+    n /= 4;
+    place(&mut rank, n % 6, 'Q');
+    n /= 6;
+    let (first, second) = KNIGHT_PLACEMENTS[n];
+    place(&mut rank, second, 'N');
+    place(&mut rank, first, 'N');
+    // The king always lands between the rooks
+    for piece in ['R', 'K', 'R'] {
+        place(&mut rank, 0, piece);
+    }
+    return rank;
+}
+
+// The Shredder-FEN of setup n, which names the castling rooks by file so it always reads back as Chess960
+pub fn start_fen(n: usize) -> String {
+    let rank = back_rank(n);
+    let white: String = rank.iter().collect();
+    let rooks: Vec<char> = (0..8).filter(|i| rank[*i] == 'R').map(|i| (b'a' + i as u8) as char).collect();
+    let castling = format!("{}{}{}{}", rooks[1].to_ascii_uppercase(), rooks[0].to_ascii_uppercase(), rooks[1], rooks[0]);
+    return format!("{}/pppppppp/8/8/8/8/PPPPPPPP/{} w {} - 0 1", white.to_ascii_lowercase(), white, castling);
+}
+
+pub fn random_start_fen() -> String {
+    let numbers: Vec<usize> = (0..POSITION_COUNT).collect();
+    let n = numbers.select_random().ok().flatten().copied().unwrap_or(STANDARD_POSITION);
+    return start_fen(n);
+}
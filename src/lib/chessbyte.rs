@@ -12,6 +12,7 @@ pub trait ChessByte {
     fn get_piece(&self) -> PieceByte;
     fn get_directions(&self) -> u8;
     fn has_moved(&self) -> bool;
+    fn is_promoted(&self) -> bool;
     fn is_kingside(&self) -> bool;
     fn is_queenside(&self) -> bool;
     fn is_king(&self) -> bool;
@@ -90,6 +91,9 @@ impl ChessByte for u8 {
     #[inline(always)]
     fn has_moved(&self) ->      bool { (self & 0b1000_0000) != 0 }
 
+    #[inline(always)]
+    fn is_promoted(&self) ->    bool { (self & 0b0001_0000) != 0 }
+
     #[inline(always)]
     fn is_kingside(&self) ->    bool { (self & 0b0010_0111) != 0 }
     
@@ -0,0 +1,92 @@
+use crate::lib::{
+    attacks::between,
+    chessbyte::ChessByte,
+    fen::FenError,
+    mask::Mask,
+    maskset::MaskSet,
+    motion::Motion,
+    piece::PieceByte
+};
+
+// How many of each droppable piece one side holds, in POCKET_PIECES order
+pub type Pocket = [u8; 5];
+pub const POCKET_PIECES: [PieceByte; 5] = [PieceByte::PAWN, PieceByte::KNIGHT, PieceByte::BISHOP, PieceByte::ROOK, PieceByte::QUEEN];
+// Set on a piece that began as a pawn, so it goes back to being one when taken
+pub const PROMOTED: u8 = 0b0001_0000;
+
+#[inline(always)]
+pub fn pocket_slot(piece: PieceByte) -> Option<usize> {
+    return POCKET_PIECES.iter().position(|p| *p == piece);
+}
+
+// What a captured piece turns into in its captor's hand
+#[inline(always)]
+pub fn pocketed(captured: u8) -> PieceByte {
+    return if captured.is_promoted() { PieceByte::PAWN } else { captured.get_piece() };
+}
+
+// Every drop the side can make onto the target squares. Pawns never land on the first or last rank.
+pub fn drops(maskset: &MaskSet, pocket: &Pocket, targets: Mask) -> Vec<Motion> {
+    let mut motions = Vec::new();
+    let open = targets & maskset.all.get_not();
+    for (slot, piece) in POCKET_PIECES.iter().enumerate() {
+        if pocket[slot] == 0 {
+            continue;
+        }
+        for square in open.isolated_bits() {
+            let to = square.as_index();
+            if *piece == PieceByte::PAWN && (to < 8 || to > 55) {
+                continue;
+            }
+            motions.push(Motion::drop(*piece, to));
+        }
+    }
+    return motions;
+}
+
+// Drops can only answer a check by blocking it, which is impossible against two checkers or a contact check
+pub fn drop_targets(king: usize, checkers: Mask) -> Mask {
+    return match checkers.bit_count() {
+        0 => Mask::default().get_not(),
+        1 => between(king, checkers.as_index()),
+        _ => Mask::default()
+    };
+}
+
+// The pockets as written inside a FEN's brackets, white's pieces first and the strongest first
+pub fn pocket_field(pockets: &[Pocket; 2]) -> String {
+    let mut field = String::new();
+    for (side, pocket) in pockets.iter().enumerate() {
+        for slot in (0..POCKET_PIECES.len()).rev() {
+            let letter = match POCKET_PIECES[slot] {
+                PieceByte::PAWN => 'p',
+                PieceByte::KNIGHT => 'n',
+                PieceByte::BISHOP => 'b',
+                PieceByte::ROOK => 'r',
+                _ => 'q'
+            };
+            for _ in 0..pocket[slot] {
+                field.push(if side == 0 { letter.to_ascii_uppercase() } else { letter });
+            }
+        }
+    }
+    return field;
+}
+
+pub fn parse_pocket(field: &str) -> Result<[Pocket; 2], FenError> {
+    let mut pockets = [[0u8; 5]; 2];
+    for c in field.chars() {
+        let piece = match c.to_ascii_lowercase() {
+            'p' => PieceByte::PAWN,
+            'n' => PieceByte::KNIGHT,
+            'b' => PieceByte::BISHOP,
+            'r' => PieceByte::ROOK,
+            'q' => PieceByte::QUEEN,
+            _ => return Err(FenError::BadPocket(field.to_string()))
+        };
+        let side = if c.is_ascii_uppercase() { 0 } else { 1 };
+        let slot = pocket_slot(piece).unwrap();
+        pockets[side][slot] = pockets[side][slot].saturating_add(1);
+    }
+    return Ok(pockets);
+}
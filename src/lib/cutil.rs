@@ -1,6 +1,7 @@
+#[cfg(feature = "gui")]
 pub mod draw {
     use eframe::egui::{self, Painter};
-    use crate::lib::{ chessbyte::ChessByte, heap::EvaluatedMotion, mask::Mask, motion::Motion, piece::{Parity, PieceByte}, point::Point };
+    use crate::lib::{ chessbyte::ChessByte, crazyhouse::{Pocket, POCKET_PIECES}, heap::EvaluatedMotion, mask::Mask, motion::{Motion, PROMOTION_CHOICES}, piece::{Parity, PieceByte}, point::Point };
     pub fn remap_cha(v: u64, omax: u64) -> u64 {
         return u64::MIN + (v - u64::MIN) * (omax - u64::MIN) / (u64::MAX - u64::MIN);
     }
@@ -80,16 +81,19 @@ pub mod draw {
         }
         return sqsize;
     }
+    pub fn piece_uri(byte: u8) -> String {
+        let mut path = "file:///home/sm/assm/final/rust/chess/assets/".to_string();
+        if byte.get_parity() == Parity::WHITE { path.push_str("light/") } else { path.push_str("dark/") };
+        path.push_str(&byte.get_piece().to_string().to_lowercase());
+        path.push_str(".png");
+        return path;
+    }
     pub fn draw_pieces (board: &[u8; 64], ui: &mut egui::Ui, sqsize: f32) -> () {
         for (index, &byte) in board.iter().enumerate() {
             if byte == 0 {
                 continue;
             }
-            let mut path = "file:///home/sm/assm/final/rust/chess/assets/".to_string();
-            if byte.get_parity() == Parity::WHITE { path.push_str("light/") } else { path.push_str("dark/") };
-            path.push_str(&byte.get_piece().to_string().to_lowercase());
-            path.push_str(".png");
-            egui::Image::from_uri(path).paint_at(ui, egui::Rect {
+            egui::Image::from_uri(piece_uri(byte)).paint_at(ui, egui::Rect {
                 min: (Point::from_index(index) * sqsize).into(),
                 max: ((Point::from_index(index) + Point { x: 1, y: 1 }) * sqsize).into()
             });
@@ -122,6 +126,52 @@ pub mod draw {
             }
         }
     }
+    // One side's pieces in hand, each with its count. Returns the piece the player has just started dragging out.
+    pub fn pocket_panel(ui: &mut egui::Ui, parity: Parity, pocket: &Pocket, draggable: bool, sqsize: f32) -> Option<PieceByte> {
+        let mut picked = None;
+        ui.horizontal(|ui| {
+            ui.label(egui::RichText::new(format!("{:<6}", parity.to_string())).monospace());
+            for (slot, piece) in POCKET_PIECES.iter().enumerate() {
+                if pocket[slot] == 0 {
+                    continue;
+                }
+                let image = egui::Image::from_uri(piece_uri(parity | *piece))
+                    .fit_to_exact_size(egui::Vec2 { x: sqsize * 0.5, y: sqsize * 0.5 })
+                    .sense(if draggable { egui::Sense::drag() } else { egui::Sense::hover() });
+                if ui.add(image).on_hover_text(piece.to_string()).drag_started() {
+                    picked = Some(*piece);
+                }
+                ui.label(egui::RichText::new(pocket[slot].to_string()).monospace());
+            }
+        });
+        return picked;
+    }
+    // The piece being dragged out of a pocket, following the pointer, with the squares it may land on
+    pub fn draw_dragged_drop(ui: &mut egui::Ui, byte: u8, drops: &[Motion], sqsize: f32) -> () {
+        for m in drops.iter().filter(|m| m.promotion == byte.get_piece()) {
+            ui.painter().debug_rect(usize_painter_rect(m.to, sqsize), egui::Color32::LIGHT_GREEN, "DROP");
+        }
+        if let Some(pos) = ui.ctx().pointer_latest_pos() {
+            let half = egui::Vec2 { x: sqsize / 2.0, y: sqsize / 2.0 };
+            egui::Image::from_uri(piece_uri(byte)).paint_at(ui, egui::Rect { min: pos - half, max: pos + half });
+        }
+    }
+    pub fn promotion_picker(ctx: &egui::Context, square: usize, parity: Parity, choices: &[PieceByte], sqsize: f32) -> Option<PieceByte> {
+        let mut chosen = None;
+        let anchor: egui::Pos2 = (Point::from_index(square) * sqsize).into();
+        egui::Area::new(egui::Id::new("promotion_picker")).order(egui::Order::Foreground).fixed_pos(anchor).show(ctx, |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                for piece in if choices.is_empty() { &PROMOTION_CHOICES[..] } else { choices } {
+                    let piece = *piece;
+                    let image = egui::Image::from_uri(piece_uri(parity | piece)).fit_to_exact_size(egui::Vec2 { x: sqsize * 0.75, y: sqsize * 0.75 });
+                    if ui.add(egui::ImageButton::new(image)).on_hover_text(piece.to_string()).clicked() {
+                        chosen = Some(piece);
+                    }
+                }
+            });
+        });
+        return chosen;
+    }
     pub fn highlight_mtm(painter: &egui::Painter, mtm: &Motion, sqsize: f32) -> (){
         if mtm.from != 65 && mtm.to != 65 {
             painter.debug_rect(Mask::from_index(mtm.from).to_painter_rect(sqsize), egui::Color32::BLUE, "FROM");
@@ -138,7 +188,7 @@ pub mod draw {
                 mine = mine.min(c.evaluation);
                 maxe = maxe.max(c.evaluation);
             }
-            for c in consider {
+            for c in consider.iter().filter(|c| !c.motion.is_drop()) {
                 let from: egui::Pos2 = Point::from_index(c.motion.from).into();
                 let to: egui::Pos2 = Point::from_index(c.motion.to).into();
                 taken_text_pos.push(draw_arrow(painter, (from * sqsize) + offset, (to * sqsize) + offset, egui::Color32::from_rgba_unmultiplied(0, 255, 0, 10), 1.0, &c.evaluation.to_string(), &taken_text_pos));
@@ -0,0 +1,257 @@
+use std::{collections::HashSet, io::Write, path::Path, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, thread::{self, JoinHandle}, time::{Duration, Instant}};
+use crate::lib::{
+    attacks,
+    fen::{self, FenError},
+    motion::Motion,
+    notation::NotationError,
+    polyglot::{Book, BookError, BookSelection, BookSettings},
+    searcher::{SearchControl, SearchReport, Searcher},
+    state::State,
+    syzygy::{Tablebase, TablebaseSettings},
+    transposition::TranspositionTable
+};
+
+// Where the protocol front ends write their replies. Searches finish on their own thread, so it's shared.
+pub type Output = Arc<Mutex<dyn Write + Send>>;
+
+pub fn send(out: &Output, line: &str) -> () {
+    let mut out = out.lock().unwrap();
+    let _ = writeln!(out, "{}", line);
+    let _ = out.flush();
+}
+
+#[derive(Debug)]
+pub enum PositionError {
+    Fen(FenError),
+    Move(NotationError)
+}
+
+impl std::fmt::Display for PositionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            Self::Fen(e) => write!(f, "bad FEN: {}", e),
+            Self::Move(e) => write!(f, "{}", e)
+        };
+    }
+}
+
+impl std::error::Error for PositionError {}
+
+impl From<FenError> for PositionError {
+    fn from(e: FenError) -> Self { Self::Fen(e) }
+}
+impl From<NotationError> for PositionError {
+    fn from(e: NotationError) -> Self { Self::Move(e) }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SearchLimits {
+    // None searches until stopped
+    pub time: Option<Duration>,
+    pub depth: Option<u8>,
+    pub nodes: Option<u64>,
+    // Hold the move back until told to stop, as UCI wants for infinite and pondering searches
+    pub wait_for_stop: bool
+}
+
+// One side's clock, as the GUI reports it
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Clock {
+    pub remaining: Duration,
+    pub increment: Duration,
+    pub moves_to_go: Option<u32>
+}
+
+impl Clock {
+    const SAFETY_MARGIN: Duration = Duration::from_millis(50);
+    const MOVES_LEFT_GUESS: u32 = 30;
+    // An even share of what's left until the next time control, plus most of the increment
+    pub fn budget(&self) -> Duration {
+        let usable = self.remaining.saturating_sub(Self::SAFETY_MARGIN);
+        let moves = self.moves_to_go.unwrap_or(Self::MOVES_LEFT_GUESS).max(1);
+        let share = usable / moves + self.increment * 3 / 4;
+        return share.min(usable).max(Duration::from_millis(10));
+    }
+}
+
+// A game position and a searcher to think about it, for front ends that aren't the egui app
+pub struct Engine {
+    pub state: Arc<Mutex<State>>,
+    pub book: Option<BookSettings>,
+    searcher: Arc<Mutex<Searcher>>,
+    control: Arc<SearchControl>,
+    // Set when the search's move is no longer wanted, because the position changed under it
+    abandoned: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+    started: Instant
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        attacks::init();
+        let control = Arc::new(SearchControl::default());
+        return Self {
+            state: Arc::new(Mutex::new(State::from_fen(fen::STARTING_FEN).unwrap())),
+            book: None,
+            searcher: Arc::new(Mutex::new(Searcher { control: control.clone(), ..Searcher::default() })),
+            control,
+            abandoned: Arc::new(AtomicBool::new(false)),
+            worker: None,
+            started: Instant::now()
+        };
+    }
+    pub fn searching(&self) -> bool {
+        return self.worker.as_ref().is_some_and(|w| !w.is_finished());
+    }
+    // Ends the search, and waits for it to hand over its move
+    pub fn stop(&mut self) -> () {
+        self.control.stop();
+        self.wait();
+    }
+    // Ends the search without handing over its move
+    pub fn abort(&mut self) -> () {
+        self.abandoned.store(true, Ordering::SeqCst);
+        self.stop();
+    }
+    // Waits for the search to end by itself
+    pub fn wait(&mut self) -> () {
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+    pub fn new_game(&mut self) -> () {
+        self.abort();
+        let mut searcher = self.searcher.lock().unwrap();
+        searcher.tt.clear();
+        searcher.echo.clear();
+        drop(searcher);
+        *self.state.lock().unwrap() = State::from_fen(fen::STARTING_FEN).unwrap();
+    }
+    // Sets up the FEN and plays the UCI moves from it. On an error the old position stays.
+    pub fn set_position(&mut self, fen: &str, moves: &[&str]) -> Result<(), PositionError> {
+        self.abort();
+        let mut state = State::from_fen(fen)?;
+        let mut echo = HashSet::from([state.info.zkey]);
+        for uci in moves {
+            let motion = state.parse_uci(uci)?;
+            state.make_motion(&motion, false);
+            echo.insert(state.info.zkey);
+        }
+        *self.state.lock().unwrap() = state;
+        self.searcher.lock().unwrap().echo = echo;
+        return Ok(());
+    }
+    pub fn make_move(&mut self, uci: &str) -> Result<Motion, PositionError> {
+        self.abort();
+        let mut state = self.state.lock().unwrap();
+        let motion = state.parse_uci(uci)?;
+        state.make_motion(&motion, false);
+        self.searcher.lock().unwrap().echo.insert(state.info.zkey);
+        return Ok(motion);
+    }
+    // Takes back the last move, returning false when there's none
+    pub fn undo(&mut self) -> bool {
+        self.abort();
+        let mut state = self.state.lock().unwrap();
+        if state.played_motions().is_empty() {
+            return false;
+        }
+        state.unmake_last(true);
+        // The position the move led to may also have come up earlier, so the keys are rebuilt from the game
+        self.searcher.lock().unwrap().echo = state.position_keys().into_iter().collect();
+        return true;
+    }
+    // Whether the position has come up in this game, so the search scores reaching it again as a draw
+    pub fn seen(&self, key: u64) -> bool {
+        return self.searcher.lock().unwrap().echo.contains(&key);
+    }
+    pub fn set_hash(&mut self, megabytes: usize) -> () {
+        self.abort();
+        self.searcher.lock().unwrap().tt = Arc::new(TranspositionTable::new(megabytes));
+    }
+    pub fn set_threads(&mut self, threads: usize) -> () {
+        self.abort();
+        self.searcher.lock().unwrap().threads = threads.max(1);
+    }
+    pub fn set_book(&mut self, path: &str, selection: BookSelection) -> Result<usize, BookError> {
+        let book = Book::open(Path::new(path))?;
+        let entries = book.len();
+        self.book = Some(BookSettings { book: Arc::new(book), selection, max_ply: 16 });
+        return Ok(entries);
+    }
+    // Returns the most pieces the tables found cover, 0 when there were none and probing is off
+    pub fn set_syzygy(&mut self, paths: &str, probe_depth: u8) -> usize {
+        self.abort();
+        let tables = Tablebase::open(paths);
+        let largest = tables.largest();
+        self.searcher.lock().unwrap().tablebase = (largest > 0).then(|| TablebaseSettings { tables: Arc::new(tables), probe_depth });
+        return largest;
+    }
+    pub fn set_syzygy_depth(&mut self, probe_depth: u8) -> () {
+        self.abort();
+        if let Some(tb) = &mut self.searcher.lock().unwrap().tablebase {
+            tb.probe_depth = probe_depth;
+        }
+    }
+    // Searches on another thread. Every finished depth goes to on_report, then the move (None without one) to done.
+    pub fn go(&mut self, limits: SearchLimits, mut on_report: impl FnMut(&SearchReport) + Send + 'static, done: impl FnOnce(&mut State, Option<Motion>) + Send + 'static) -> () {
+        self.abort();
+        self.abandoned.store(false, Ordering::SeqCst);
+        self.control.reset();
+        self.started = Instant::now();
+        let state = self.state.clone();
+        let searcher = self.searcher.clone();
+        let control = self.control.clone();
+        let abandoned = self.abandoned.clone();
+        let book = self.book.clone();
+        self.worker = Some(thread::spawn(move || {
+            let (sender, receiver) = crossbeam_channel::unbounded::<SearchReport>();
+            let reporter = thread::spawn(move || {
+                for report in receiver.iter() {
+                    on_report(&report);
+                }
+            });
+            let lock = state.lock().unwrap();
+            let book_move = book.as_ref().and_then(|b| b.probe(&lock)).map(|b| b.motion);
+            let has_moves = !lock.moves.parity_vect(lock.turn).is_empty();
+            drop(lock);
+            let best = if book_move.is_some() || !has_moves {
+                book_move
+            } else {
+                let mut searcher = searcher.lock().unwrap();
+                searcher.time_limit = limits.time.unwrap_or(Duration::MAX);
+                searcher.depth_limit = limits.depth;
+                searcher.node_limit = limits.nodes;
+                searcher.driver.reports = Some(sender.clone());
+                searcher.tree.clear();
+                let best = searcher.run(state.clone());
+                searcher.driver.reports = None;
+                Some(best)
+            };
+            drop(sender);
+            let _ = reporter.join();
+            while limits.wait_for_stop && !control.stopped() && control.deadline.load(Ordering::SeqCst) == 0 {
+                thread::sleep(Duration::from_millis(5));
+            }
+            if abandoned.load(Ordering::SeqCst) {
+                return;
+            }
+            let mut lock = state.lock().unwrap();
+            done(&mut lock, best);
+            // Whatever done played is now part of the game, so the search should see it repeat
+            searcher.lock().unwrap().echo.insert(lock.info.zkey);
+        }));
+    }
+    // The move being pondered was played, so the search gets its own time from now on
+    pub fn ponderhit(&mut self, budget: Option<Duration>) -> () {
+        let Some(budget) = budget else {
+            return;
+        };
+        let deadline = self.started.elapsed() + budget;
+        self.control.deadline.store((deadline.as_millis() as u64).max(1), Ordering::SeqCst);
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self { Self::new() }
+}
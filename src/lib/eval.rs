@@ -1,5 +1,5 @@
 use crate::lib::{
-    boardarray::BoardArray, chessbyte::ChessByte, piece::{Parity, PieceByte}, state::State
+    bitbase, chessbyte::ChessByte, piece::Parity, state::State, variant::Variant
 };
 
 #[derive(Clone)]
@@ -32,11 +32,13 @@ impl Evaluator {
         for i in self.scores.iter() {
             self.eval += i.white_score + i.black_score;
         }
-        self.eval *= (100 - halfmove as i32) / 100;
+        self.eval = self.eval * (100 - (halfmove as i32).min(100)) / 100;
     }
 }
 
 const DO_TIMING: bool = false;
+// Reaching a variant's goal is as good as mate but well clear of the search's own mate scores
+pub const VARIANT_WIN: i32 = 100_000;
 pub fn start_eval(state: &State) -> Evaluator {
     let mut evaluator = Evaluator {
         eval: 0,
@@ -49,14 +51,35 @@ pub fn start_eval(state: &State) -> Evaluator {
         }
     }
     drop(zbrist);
+    if let Some(winner) = state.variant_winner() {
+        evaluator.eval = if winner == Parity::WHITE { VARIANT_WIN } else { -VARIANT_WIN };
+        return evaluator;
+    }
+    if state.variant == Variant::Antichess {
+        // The usual terms all lean on a king to keep safe, so only shedding pieces and the tempo count here
+        evaluator.push("Shedding", general::shedding(state.info.maskset.white.bit_count()), -general::shedding(state.info.maskset.black.bit_count()));
+        evaluator.push("Tempo", general::tempo(state.turn).max(0), general::tempo(state.turn).min(0));
+        evaluator.finalize(state.info.halfmove_clock);
+        state.zobrist.lock().unwrap().save((state.info.clone(), state.moves.clone(), Some(evaluator.clone())));
+        return evaluator;
+    }
     if !(state.board[state.info.king_indices[0]].is_w_king() && state.board[state.info.king_indices[1]].is_b_king()) {
         println!("King mismatch W: {}. B: {}", state.info.king_indices[0], state.info.king_indices[1]);
         evaluator.eval = i32::MIN;
         return evaluator;
     }
+    if let Some(value) = bitbase::probe(state) {
+        // The endgame is solved, so the distance to mate is all that matters
+        let score = if state.turn == Parity::WHITE { value.score() } else { -value.score() };
+        evaluator.push("Bitbase", score.max(0), score.min(0));
+        evaluator.finalize(state.info.halfmove_clock);
+        state.zobrist.lock().unwrap().save((state.info.clone(), state.moves.clone(), Some(evaluator.clone())));
+        return evaluator;
+    }
     
     let flipped = state.partial_flipped();
-    let fmoves = flipped.board.get_motions(&flipped.maskset, &flipped.enpassant_mask, Some(flipped.allowed_castles));
+    let fpockets = [state.info.pockets[1], state.info.pockets[0]];
+    let fmoves = state.variant.motions(&flipped.board, &flipped.maskset, &flipped.enpassant_mask, Some((flipped.allowed_castles, flipped.castle_rooks)), &fpockets);
 
     let mut ev = std::time::Instant::now();
     evaluator.push("Material", material::midgame_material(&state.board), -material::midgame_material(&flipped.board));
@@ -71,7 +94,7 @@ pub fn start_eval(state: &State) -> Evaluator {
     if DO_TIMING { println!("PImbalance {:.2?}", ev.elapsed()) };
 
     ev = std::time::Instant::now();
-    evaluator.push("BImbalance", imbalance::bishop_pair(&state.board) / 16, -imbalance::bishop_pair(&flipped.board) / 16);
+    evaluator.push("BImbalance", imbalance::bishop_pair(&state.info.maskset) / 16, -imbalance::bishop_pair(&flipped.maskset) / 16);
     if DO_TIMING { println!("BImbalance {:.2?}", ev.elapsed()) };
 
     ev = std::time::Instant::now();
@@ -79,7 +102,7 @@ pub fn start_eval(state: &State) -> Evaluator {
     if DO_TIMING { println!("Pawn {:.2?}", ev.elapsed()) };
     
     ev = std::time::Instant::now();
-    evaluator.push("Pieces", pieces::midgame_pieces(&state.board, state.info.allowed_castles, &state.moves), -pieces::midgame_pieces(&flipped.board, flipped.allowed_castles, &fmoves)); 
+    evaluator.push("Pieces", pieces::midgame_pieces(&state.board, &state.info.maskset, state.info.allowed_castles, &state.moves), -pieces::midgame_pieces(&flipped.board, &flipped.maskset, flipped.allowed_castles, &fmoves)); 
     if DO_TIMING { println!("Pieces {:.2?}", ev.elapsed()) };
 
     ev = std::time::Instant::now();
@@ -100,6 +123,12 @@ pub fn start_eval(state: &State) -> Evaluator {
         evaluator.push("Tempo", 0, general::tempo(state.turn));
     }
     if DO_TIMING { println!("Tempo {:.2?}", ev.elapsed()) };
+    match state.variant {
+        Variant::ThreeCheck => evaluator.push("Checks", general::checks_given(state.info.checks[0]), -general::checks_given(state.info.checks[1])),
+        Variant::KingOfTheHill => evaluator.push("Hill", general::hill(state.info.king_indices[0]), -general::hill(flipped.king_indices[0])),
+        Variant::Crazyhouse => evaluator.push("Pocket", material::pocket(&state.info.pockets[0]), -material::pocket(&state.info.pockets[1])),
+        Variant::Standard | Variant::Atomic | Variant::Antichess => ()
+    }
     evaluator.finalize(state.info.halfmove_clock);
     let mut zbrist2 = state.zobrist.lock().unwrap();
     zbrist2.save((state.info.clone(), state.moves.clone(), Some(evaluator.clone())));
@@ -297,6 +326,25 @@ mod general {
     const WEIGHT_TEMPO: i32 = 28;
     pub fn tempo(parity: Parity) -> i32 { WEIGHT_TEMPO * if parity == Parity::WHITE { 1 } else { -1 } }
 
+    const WEIGHT_SHED_PIECE: i32 = 120;
+    pub fn shedding(pieces: u32) -> i32 {
+        return (16 - pieces as i32) * WEIGHT_SHED_PIECE;
+    }
+
+    const WEIGHT_CHECKS_GIVEN: [i32; 3] = [0, 180, 520];
+    pub fn checks_given(given: u8) -> i32 {
+        return WEIGHT_CHECKS_GIVEN[(given as usize).min(2)];
+    }
+
+    const WEIGHT_HILL_DISTANCE: [i32; 4] = [0, 140, 60, 20];
+    // King steps to the nearest centre square, from white's side of the board
+    pub fn hill(king_index: usize) -> i32 {
+        let file = (king_index % 8) as i32;
+        let row = (king_index / 8) as i32;
+        let distance = (3 - file).max(file - 4).max(3 - row).max(row - 4).max(0);
+        return WEIGHT_HILL_DISTANCE.get(distance as usize).copied().unwrap_or(0);
+    }
+
     const WEIGHT_PASSED_RANK: [i32; 7] = [0, 10, 17, 15, 62, 168, 276];
     const WEIGHT_COMPOUNDING_PASSED: [i32; 5] = [2, 7, 12, 17, 22];
     const WEIGHT_COMPOUNDING_MULT: i32 = 70;
@@ -376,10 +424,10 @@ mod general {
 mod imbalance {
     use crate::lib::{
         chessbyte::ChessByte,
-        piece::PieceByte
+        maskset::MaskSet,
+        piece::{Parity, PieceByte}
     };
 
-    use super::count_pieces;
     fn get_piece_value_for_ordering(piece: PieceByte) -> i32 {
         match piece {
             PieceByte::NONE => -1,
@@ -446,28 +494,20 @@ mod imbalance {
         return sum;
     }
 
-    pub fn bishop_pair(board: &[u8; 64]) -> i32 {
-        return if count_pieces(board, PieceByte::BISHOP) < 2 { 0 } else { 1438 };
+    pub fn bishop_pair(maskset: &MaskSet) -> i32 {
+        return if maskset.count(Parity::WHITE, PieceByte::BISHOP) < 2 { 0 } else { 1438 };
     }
 
 }
-fn count_pieces(board: &[u8; 64], piece: PieceByte) -> i32 {
-    let mut count = 0;
-    for p in board.iter() {
-        if p.get_piece() == piece && p.is_parity(Parity::WHITE) {
-            count += 1;
-        }
-    }
-    return count;
-}
 pub mod pieces {
     use crate::lib::{
-        boardarray::BoardArray, chessbyte::ChessByte, motion::MotionSet, piece::PieceByte
+        boardarray::BoardArray, chessbyte::ChessByte, maskset::MaskSet, motion::MotionSet, piece::{Parity, PieceByte}
     };
 
     use super::{mobility, pawn::is_backwards};
 
-    fn king_ring(board: &[u8; 64], index: usize, full: bool) -> bool {
+    // Whether index is in the ring around the black king, which sits on king
+    fn king_ring(board: &[u8; 64], king: usize, index: usize, full: bool) -> bool {
         if !full {
             if index >= 9 {
                 if board[index - 7].is_pawn() && board[index - 7].is_black() && board[index - 9].is_pawn() && board[index - 9].is_black() {
@@ -475,6 +515,9 @@ pub mod pieces {
                 }
             }
         }
+        if king < index || king - index > 18 {
+            return false;
+        }
         let xi: i32 = (index % 8) as i32;
         let yi: i32 = (index / 8) as i32;
         for x in -2i32..=2 {
@@ -484,7 +527,7 @@ pub mod pieces {
                     continue;
                 }
                 if index + (i as usize) < 64 {
-                    if board[index + i as usize].is_king() && board[index + i as usize].is_black() {
+                    if index + i as usize == king {
                         if x >= -1 && x <= 1 || xi + x == 0 || xi + x == 7 {
                             if y >= -1 && y <= 1 || yi + y == 0 || yi + y == 7 {
                                 return true;
@@ -497,7 +540,7 @@ pub mod pieces {
         }
         return false;
     }
-    fn count_king_attackers(board: &[u8; 64], index: usize) -> f32 {
+    fn count_king_attackers(board: &[u8; 64], king: usize, index: usize) -> f32 {
         if board[index].is_king() { return 0.0 };
         let xi = index % 8;
         let yi = index / 8;
@@ -505,11 +548,11 @@ pub mod pieces {
             let mut value = 0.0;
             if xi > 1 && xi < 6 {
                 let is = board[yi * 8 + xi - 2].is_pawn() && board[yi * 8 + xi - 2].is_white();
-                if king_ring(board, index, true) {
+                if king_ring(board, king, index, true) {
                     value += if is { 0.5 } else { 1.0 };
                 }
                 let is2 = board[yi * 8 + xi + 2].is_pawn() && board[yi * 8 + xi + 2].is_white();
-                if king_ring(board, index, true) {
+                if king_ring(board, king, index, true) {
                     value += if is2 { 0.5 } else { 1.0 };
                 }
             }
@@ -521,19 +564,19 @@ pub mod pieces {
             let mut value = 0f32;
             let x = index % 8;
             if x > 0 && x - 1 <= 7 && index > 8 {
-                if king_ring(board, index - 9, true) {
+                if king_ring(board, king, index - 9, true) {
                     value += if board[index - 2].get_piece() == PieceByte::PAWN && board[index - 2].is_white() { 0.5 } else { 1.0 };
                 }
             }
             if x > 0 && x + 1 <= 7 {
-                if king_ring(board, index - 7, true) {
+                if king_ring(board, king, index - 7, true) {
                     value += if board[index + 2].get_piece() == PieceByte::PAWN && board[index - 2].is_white() { 0.5 } else { 1.0 };
                 }
             }
             return value;
         }
         for i in 0..64 {
-            if king_ring(board, i, false) {
+            if king_ring(board, king, i, false) {
                 return 1.0;
             }
         }
@@ -691,16 +734,16 @@ pub mod pieces {
         }
         return WEIGHT_OUTPOST[if board[index].is_knight() { 4 } else { 3 }];
     }
-    fn rook_king_ring(board: &[u8; 64], index: usize) -> i32 {
-        if count_king_attackers(board, index) > 0.0 { return 0 };
+    fn rook_king_ring(board: &[u8; 64], king: usize, index: usize) -> i32 {
+        if count_king_attackers(board, king, index) > 0.0 { return 0 };
         let xi = index % 8;
         for y in 0..8 {
-            if king_ring(board, y * 8 + xi, false) { return WEIGHT_ROOK_KING_RING };
+            if king_ring(board, king, y * 8 + xi, false) { return WEIGHT_ROOK_KING_RING };
         }
         return 0;
     }
-    fn bishop_king_ring(board: &[u8; 64], index: usize) -> i32 {
-        if count_king_attackers(board, index) > 0.0 { return 0 };
+    fn bishop_king_ring(board: &[u8; 64], king: usize, index: usize) -> i32 {
+        if count_king_attackers(board, king, index) > 0.0 { return 0 };
         let xi = (index % 8) as i32;
         let yi = (index / 8) as i32;
         for i in 0..4 {
@@ -714,7 +757,7 @@ pub mod pieces {
                     if !board[sqi].is_piece() {
                         break;
                     }
-                    if king_ring(board, sqi, false) {
+                    if king_ring(board, king, sqi, false) {
                         return WEIGHT_BISHOP_KING_RING;
                     }
                     if board[sqi].is_pawn() {
@@ -833,15 +876,16 @@ pub mod pieces {
         }
         return 0;
     }
-    pub fn midgame_pieces(board: &[u8; 64], castling: u8, moves: &MotionSet) -> i32 {
+    pub fn midgame_pieces(board: &[u8; 64], maskset: &MaskSet, castling: u8, moves: &MotionSet) -> i32 {
         let mut value = 0;
+        let king = maskset.king(Parity::BLACK);
         for (index, piece) in board.iter().enumerate() {
             if piece.is_white() {
                 if piece.is_knight() {
                     value += outpost(board, index);
                     value += mbehind_pawn(board, index);
                     value += rook_queen_file(board, index);
-                    value += rook_king_ring(board, index);
+                    value += rook_king_ring(board, king, index);
                     let open_file = rook_open_file(board, index);
                     value += open_file;
                     if open_file > 0 { value += trapped_rook(board, index, castling, moves) };
@@ -851,7 +895,7 @@ pub mod pieces {
                     value += mbehind_pawn(board, index);
                     value += bishop_pawns(board, index);
                     value += bishop_xray(board, index);
-                    value += bishop_king_ring(board, index);
+                    value += bishop_king_ring(board, king, index);
                     value += king_protector(board, index, false);
                     value += long_diagonal_bishop(board, index);
 
@@ -1200,6 +1244,7 @@ mod pawn {
 pub mod material {
     use crate::lib::{
         chessbyte::ChessByte,
+        crazyhouse::{Pocket, POCKET_PIECES},
         piece::{Parity, PieceByte}
     };
 
@@ -1247,6 +1292,14 @@ pub mod material {
             _ => 0
         }
     }
+    // Pieces in hand can land anywhere, so they are worth a little more than the same piece on the board
+    pub fn pocket(pocket: &Pocket) -> i32 {
+        let mut sum = 0;
+        for (slot, piece) in POCKET_PIECES.iter().enumerate() {
+            sum += pocket[slot] as i32 * (price_piece(*piece as u8) * 9 / 8);
+        }
+        return sum;
+    }
     pub fn price_parity(board: &[u8; 64], parity: Parity) -> i32 {
         let mut sum = 0;
         for i in 0..64 {
@@ -0,0 +1,453 @@
+use crate::lib::{
+    atomic,
+    boardarray::BoardArray,
+    chessbyte::ChessByte,
+    crazyhouse::{self, Pocket},
+    mask::Mask,
+    maskset::MaskSet,
+    piece::{Parity, PieceByte},
+    state::{RetainedStateInfo, State, STANDARD_CASTLE_ROOKS},
+    variant::{Variant, CHECKS_TO_WIN}
+};
+
+pub const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+pub const SAMPLE_FENS: [&str; 12] = [
+    STARTING_FEN, // Default
+    "rnbqkbnr/8/8/8/8/8/8/RNBQKBNR w KQkq - 0 1", // No Pawns
+    "r3k3/8/8/8/8/8/8/R3K3 w Qq - 0 1", // Just rooks,
+    "r4k2/8/8/8/8/8/8/R4K2 w - - 0 1", // Check,
+    "r4k2/8/8/8/8/8/8/RR3K2 w - - 0 1", // Check,
+    "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1", //Castling 
+    "rnbqkbnr/pppppppp/8/8/8/8/P7/K7 w kq - 0 1", // White only pawns
+    "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2",
+    "rnbqkbnr/pppppppp/8/4B3/8/8/PPPPPPPP/RN1QKBNR w KQkq - 0 1",
+    "rnbqkbnr/pppppppp/2Q5/8/8/8/PPPPPPPP/RNB1KBNR w KQkq - 1 1",
+    "rnbqk2Q/pppppppp/8/8/8/8/PPPPPPPP/RNB1KBNR b KQq - 0 1",
+    "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2"
+];
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum FenError {
+    BadFieldCount(usize),
+    BadRankCount(usize),
+    BadRankLength { rank: usize, length: usize },
+    UnknownPiece(char),
+    BadSideToMove(String),
+    BadCastling(String),
+    BadEnPassant(String),
+    ImpossibleEnPassant(String),
+    BadMoveCounter(String),
+    MissingKing(Parity),
+    TooManyKings(Parity),
+    TooManyPawns(Parity),
+    PawnOnBackRank(usize),
+    SideNotToMoveInCheck,
+    BadChecks(String),
+    BadPocket(String)
+}
+
+impl std::fmt::Display for FenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            Self::BadFieldCount(n) => write!(f, "expected 4 to 6 space separated fields, found {}", n),
+            Self::BadRankCount(n) => write!(f, "expected 8 ranks, found {}", n),
+            Self::BadRankLength { rank, length } => write!(f, "rank {} describes {} squares instead of 8", rank, length),
+            Self::UnknownPiece(c) => write!(f, "unknown piece letter '{}'", c),
+            Self::BadSideToMove(s) => write!(f, "side to move must be 'w' or 'b', found \"{}\"", s),
+            Self::BadCastling(s) => write!(f, "bad castling field \"{}\"", s),
+            Self::BadEnPassant(s) => write!(f, "bad en passant square \"{}\"", s),
+            Self::ImpossibleEnPassant(s) => write!(f, "en passant square {} is impossible in this position", s),
+            Self::BadMoveCounter(s) => write!(f, "bad move counter \"{}\"", s),
+            Self::MissingKing(p) => write!(f, "{} has no king", p),
+            Self::TooManyKings(p) => write!(f, "{} has more than one king", p),
+            Self::TooManyPawns(p) => write!(f, "{} has more than 8 pawns", p),
+            Self::PawnOnBackRank(i) => write!(f, "pawn on a back rank at {}", square_name(*i)),
+            Self::SideNotToMoveInCheck => write!(f, "the side not to move is in check"),
+            Self::BadChecks(s) => write!(f, "bad Three-check field \"{}\"", s),
+            Self::BadPocket(s) => write!(f, "bad Crazyhouse pocket \"{}\"", s)
+        };
+    }
+}
+
+impl std::error::Error for FenError {}
+
+pub fn square_name(index: usize) -> String {
+    return format!("{}{}", (b'a' + (index % 8) as u8) as char, 8 - index / 8);
+}
+
+pub fn square_index(name: &str) -> Option<usize> {
+    let bytes = name.as_bytes();
+    if bytes.len() != 2 || !(b'a'..=b'h').contains(&bytes[0]) || !(b'1'..=b'8').contains(&bytes[1]) {
+        return None;
+    }
+    return Some((8 - (bytes[1] - b'0') as usize) * 8 + (bytes[0] - b'a') as usize);
+}
+
+pub fn piece_from_char(c: char) -> Option<u8> {
+    let parity = if c.is_ascii_uppercase() { Parity::WHITE } else { Parity::BLACK };
+    let piece = match c.to_ascii_lowercase() {
+        'r' => PieceByte::ROOK,
+        'n' => PieceByte::KNIGHT,
+        'b' => PieceByte::BISHOP,
+        'q' => PieceByte::QUEEN,
+        'k' => PieceByte::KING,
+        'p' => PieceByte::PAWN,
+        _ => return None
+    };
+    return Some(parity | piece);
+}
+
+pub fn piece_to_char(byte: u8) -> char {
+    let c = match byte.get_piece() {
+        PieceByte::ROOK => 'r',
+        PieceByte::KNIGHT => 'n',
+        PieceByte::BISHOP => 'b',
+        PieceByte::QUEEN => 'q',
+        PieceByte::KING => 'k',
+        PieceByte::PAWN => 'p',
+        PieceByte::NONE => ' '
+    };
+    return if byte.is_white() { c.to_ascii_uppercase() } else { c };
+}
+
+// Marks each piece as kingside or queenside of its own king, which in Chess960 need not be on the e file
+fn mark_sides(board: &mut [u8; 64], kings: [usize; 2]) -> () {
+    for index in 0..64 {
+        if !board[index].is_piece() {
+            continue;
+        }
+        let king_file = kings[if board[index].is_white() { 0 } else { 1 }] % 8;
+        let file = index % 8;
+        board[index] |= if file < king_file { 0b0100_0000 } else if file > king_file { 0b0010_0000 } else { 0 };
+    }
+}
+
+fn parse_board(field: &str) -> Result<[u8; 64], FenError> {
+    let ranks: Vec<&str> = field.split('/').collect();
+    if ranks.len() != 8 {
+        return Err(FenError::BadRankCount(ranks.len()));
+    }
+    let mut board = [0u8; 64];
+    for (r, rank) in ranks.iter().enumerate() {
+        let mut length = 0usize;
+        for c in rank.chars() {
+            if let Some(skip) = c.to_digit(10) {
+                length += skip as usize;
+                continue;
+            }
+            // Crazyhouse marks a piece that was once a pawn with a trailing ~
+            if c == '~' && length > 0 && length <= 8 && board[r * 8 + length - 1].is_piece() {
+                board[r * 8 + length - 1] |= crazyhouse::PROMOTED;
+                continue;
+            }
+            let byte = piece_from_char(c).ok_or(FenError::UnknownPiece(c))?;
+            if length < 8 {
+                let index = r * 8 + length;
+                board[index] = byte;
+            }
+            length += 1;
+        }
+        if length != 8 {
+            return Err(FenError::BadRankLength { rank: 8 - r, length });
+        }
+    }
+    return Ok(board);
+}
+
+// Reads standard, X-FEN and Shredder-FEN castling fields into the castling bits and each bit's rook square.
+// The flag is set when the rights could only come from a Chess960 game.
+fn parse_castling(field: &str, board: &[u8; 64], kings: [usize; 2]) -> Result<(u8, [usize; 4], bool), FenError> {
+    let mut rooks = STANDARD_CASTLE_ROOKS;
+    if field == "-" {
+        return Ok((0, rooks, false));
+    }
+    let bad = || FenError::BadCastling(field.to_string());
+    let mut castles = 0u8;
+    let mut chess960 = false;
+    for c in field.chars() {
+        let parity = if c.is_ascii_uppercase() { Parity::WHITE } else { Parity::BLACK };
+        let king = kings[if parity == Parity::WHITE { 0 } else { 1 }];
+        let back_rank = if parity == Parity::WHITE { 56 } else { 0 };
+        if king / 8 != back_rank / 8 {
+            return Err(bad());
+        }
+        let is_rook = |index: usize| board[index].is_rook() && board[index].is_parity(parity);
+        let rook = match c.to_ascii_lowercase() {
+            // X-FEN letters mean the outermost rook on that side of the king
+            'k' => (king + 1..back_rank + 8).rev().find(|i| is_rook(*i)).ok_or_else(bad)?,
+            'q' => (back_rank..king).find(|i| is_rook(*i)).ok_or_else(bad)?,
+            f @ 'a'..='h' => {
+                chess960 = true;
+                let index = back_rank + (f as usize - 'a' as usize);
+                if !is_rook(index) || index == king {
+                    return Err(bad());
+                }
+                index
+            },
+            _ => return Err(bad())
+        };
+        let kingside = rook > king;
+        let bit = match (parity == Parity::WHITE, kingside) {
+            (false, true) => 0,
+            (false, false) => 1,
+            (true, true) => 2,
+            (true, false) => 3
+        };
+        if castles & (1 << bit) != 0 {
+            return Err(bad());
+        }
+        castles |= 1 << bit;
+        rooks[bit] = rook;
+    }
+    for bit in 0..4 {
+        if castles & (1 << bit) != 0 && rooks[bit] != STANDARD_CASTLE_ROOKS[bit] {
+            chess960 = true;
+        }
+        let king = kings[if bit < 2 { 1 } else { 0 }];
+        if castles & (1 << bit) != 0 && king % 8 != 4 {
+            chess960 = true;
+        }
+    }
+    return Ok((castles, rooks, chess960));
+}
+
+fn parse_enpassant(field: &str, board: &[u8; 64], turn: Parity) -> Result<Mask, FenError> {
+    if field == "-" {
+        return Ok(Mask::default());
+    }
+    let index = square_index(field).ok_or(FenError::BadEnPassant(field.to_string()))?;
+    let target_rank = if turn == Parity::WHITE { 6 } else { 3 };
+    if 8 - index / 8 != target_rank {
+        return Err(FenError::ImpossibleEnPassant(field.to_string()));
+    }
+    // The pawn that just double pushed sits in front of the target square, from the mover's point of view
+    let (pawn, origin) = if turn == Parity::WHITE { (index + 8, index - 8) } else { (index - 8, index + 8) };
+    let possible = board[index] == 0
+        && board[origin] == 0
+        && board[pawn].is_pawn()
+        && board[pawn].is_parity(!turn);
+    if !possible {
+        return Err(FenError::ImpossibleEnPassant(field.to_string()));
+    }
+    return Ok(Mask { raw: 1u64 << index });
+}
+
+fn parse_counter(field: Option<&str>, default: u64) -> Result<u64, FenError> {
+    return match field {
+        Some(s) => s.parse::<u64>().map_err(|_| FenError::BadMoveCounter(s.to_string())),
+        None => Ok(default)
+    };
+}
+
+// Three-check counters, either as checks remaining ("3+3", before the clocks) or checks given ("+0+0", after them).
+// Returns the checks given by [white, black].
+fn parse_checks(field: &str) -> Result<[u8; 2], FenError> {
+    let bad = || FenError::BadChecks(field.to_string());
+    let (given, counts) = match field.strip_prefix('+') {
+        Some(rest) => (true, rest),
+        None => (false, field)
+    };
+    let (white, black) = counts.split_once('+').ok_or_else(bad)?;
+    let mut checks = [0u8; 2];
+    for (side, count) in [white, black].iter().enumerate() {
+        let n = count.parse::<u8>().map_err(|_| bad())?;
+        if n > CHECKS_TO_WIN {
+            return Err(bad());
+        }
+        checks[side] = if given { n } else { CHECKS_TO_WIN - n };
+    }
+    return Ok(checks);
+}
+
+// Crazyhouse pockets, either in brackets after the board ("...RNBQKBNR[Qp]") or as a ninth rank ("...RNBQKBNR/Qp")
+fn split_pocket(field: &str) -> Result<(&str, Option<[Pocket; 2]>), FenError> {
+    if let Some((board, rest)) = field.split_once('[') {
+        let pocket = rest.strip_suffix(']').ok_or(FenError::BadPocket(rest.to_string()))?;
+        return Ok((board, Some(crazyhouse::parse_pocket(pocket)?)));
+    }
+    if field.matches('/').count() == 8 {
+        let (board, pocket) = field.rsplit_once('/').unwrap();
+        return Ok((board, Some(crazyhouse::parse_pocket(pocket)?)));
+    }
+    return Ok((field, None));
+}
+
+// Antichess kings are ordinary pieces, so any number of them is fine.
+// Crazyhouse pawns can be dropped back in, so a side may have more than eight.
+fn validate_material(board: &[u8; 64], variant: Variant) -> Result<[usize; 2], FenError> {
+    let mut kings = [65usize; 2];
+    let mut pawns = [0usize; 2];
+    for (index, byte) in board.iter().enumerate() {
+        let side = if byte.is_white() { 0 } else { 1 };
+        if byte.is_king() && variant == Variant::Antichess {
+            kings[side] = kings[side].min(index);
+        } else if byte.is_king() {
+            if kings[side] != 65 {
+                return Err(FenError::TooManyKings(byte.get_parity()));
+            }
+            kings[side] = index;
+        } else if byte.is_pawn() {
+            if index < 8 || index >= 56 {
+                return Err(FenError::PawnOnBackRank(index));
+            }
+            pawns[side] += 1;
+        }
+    }
+    if variant != Variant::Antichess {
+        if kings[0] == 65 { return Err(FenError::MissingKing(Parity::WHITE)) };
+        if kings[1] == 65 { return Err(FenError::MissingKing(Parity::BLACK)) };
+    }
+    let most_pawns = if variant == Variant::Crazyhouse { 16 } else { 8 };
+    if pawns[0] > most_pawns { return Err(FenError::TooManyPawns(Parity::WHITE)) };
+    if pawns[1] > most_pawns { return Err(FenError::TooManyPawns(Parity::BLACK)) };
+    return Ok(kings);
+}
+
+impl State {
+    pub fn from_fen(fen: &str) -> Result<State, FenError> {
+        return State::from_variant_fen(fen, Variant::Standard);
+    }
+    // A Three-check field or a pocket picks its own variant, so it overrides the one asked for
+    pub fn from_variant_fen(fen: &str, mut variant: Variant) -> Result<State, FenError> {
+        let mut fields: Vec<&str> = fen.split_whitespace().collect();
+        let checks = match fields.iter().skip(4).position(|f| f.contains('+')) {
+            Some(at) => Some(parse_checks(fields.remove(at + 4))?),
+            None => None
+        };
+        if fields.len() < 4 || fields.len() > 6 {
+            return Err(FenError::BadFieldCount(fields.len()));
+        }
+        let (placement, pockets) = split_pocket(fields[0])?;
+        if pockets.is_some() {
+            variant = Variant::Crazyhouse;
+        }
+        let mut board = parse_board(placement)?;
+        let turn = match fields[1] {
+            "w" => Parity::WHITE,
+            "b" => Parity::BLACK,
+            s => return Err(FenError::BadSideToMove(s.to_string()))
+        };
+        let kings = validate_material(&board, variant)?;
+        // Antichess has no castling, so rights in the field are dropped rather than refused
+        let (allowed_castles, castle_rooks, chess960) = match variant {
+            Variant::Antichess => (0, STANDARD_CASTLE_ROOKS, false),
+            _ => parse_castling(fields[2], &board, kings)?
+        };
+        mark_sides(&mut board, kings);
+        let enpassant_mask = parse_enpassant(fields[3], &board, turn)?;
+        let halfmove_clock = parse_counter(fields.get(4).copied(), 0)?;
+        let fullmove_number = parse_counter(fields.get(5).copied(), 1)?;
+
+        let waiting = kings[if turn == Parity::WHITE { 1 } else { 0 }];
+        let maskset = MaskSet::from_board(&board);
+        let waiting_in_check = match variant {
+            Variant::Atomic => atomic::in_check(&maskset, !turn),
+            Variant::Antichess => false,
+            _ => (board.attack_map(turn, &maskset.all).raw & (1u64 << waiting)) != 0
+        };
+        if waiting_in_check {
+            return Err(FenError::SideNotToMoveInCheck);
+        }
+
+        let mut state = State::default();
+        state.board = board;
+        state.turn = turn;
+        state.info.allowed_castles = allowed_castles;
+        state.info.castle_rooks = castle_rooks;
+        state.chess960 = chess960;
+        state.variant = variant;
+        if let Some(checks) = checks {
+            state.variant = Variant::ThreeCheck;
+            state.info.checks = checks;
+        }
+        state.info.pockets = pockets.unwrap_or_default();
+        state.info.enpassant_mask = enpassant_mask;
+        state.info.halfmove_clock = halfmove_clock;
+        state.info.fullmove_number = fullmove_number;
+        state.init();
+        return Ok(state);
+    }
+}
+
+pub fn board_to_fen(board: &[u8; 64], turn: Parity, info: &RetainedStateInfo) -> String {
+    return write_fen(board, turn, info, Variant::Standard, false);
+}
+
+pub fn board_to_shredder_fen(board: &[u8; 64], turn: Parity, info: &RetainedStateInfo) -> String {
+    return write_fen(board, turn, info, Variant::Standard, true);
+}
+
+// KQkq where that names the rook unambiguously (X-FEN), the rook's file otherwise or when asked to (Shredder-FEN)
+pub fn castling_field(board: &[u8; 64], info: &RetainedStateInfo, shredder: bool) -> String {
+    let mut castling = String::new();
+    for (bit, letter) in [(2, 'K'), (3, 'Q'), (0, 'k'), (1, 'q')] {
+        if info.allowed_castles & (1 << bit) == 0 {
+            continue;
+        }
+        let rook = info.castle_rooks[bit];
+        let (low, high) = if bit % 2 == 0 { (rook + 1, rook - rook % 8 + 8) } else { (rook - rook % 8, rook) };
+        let outermost = !(low..high).any(|i| board[i].is_rook() && board[i].same_parity(&board[rook]));
+        let white = bit >= 2;
+        if outermost && !shredder {
+            castling.push(letter);
+        } else {
+            let file = (b'a' + (rook % 8) as u8) as char;
+            castling.push(if white { file.to_ascii_uppercase() } else { file });
+        }
+    }
+    if castling.is_empty() { castling.push('-') };
+    return castling;
+}
+
+// Three-check positions get their remaining checks after the en passant square, as in "3+3".
+// Crazyhouse positions get their pockets in brackets after the board and a ~ after promoted pieces.
+pub fn write_fen(board: &[u8; 64], turn: Parity, info: &RetainedStateInfo, variant: Variant, shredder: bool) -> String {
+    let mut fen = String::new();
+    for rank in 0..8 {
+        let mut empty = 0;
+        for file in 0..8 {
+            let byte = board[rank * 8 + file];
+            if !byte.is_piece() {
+                empty += 1;
+                continue;
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+                empty = 0;
+            }
+            fen.push(piece_to_char(byte));
+            if variant == Variant::Crazyhouse && byte.is_promoted() {
+                fen.push('~');
+            }
+        }
+        if empty > 0 { fen.push_str(&empty.to_string()) };
+        if rank < 7 { fen.push('/') };
+    }
+    if variant == Variant::Crazyhouse {
+        fen.push_str(&format!("[{}]", crazyhouse::pocket_field(&info.pockets)));
+    }
+    fen.push_str(if turn == Parity::WHITE { " w " } else { " b " });
+
+    fen.push_str(&castling_field(board, info, shredder));
+    fen.push(' ');
+    if info.enpassant_mask.raw == 0 {
+        fen.push('-');
+    } else {
+        fen.push_str(&square_name(info.enpassant_mask.raw.trailing_zeros() as usize));
+    }
+    if variant == Variant::ThreeCheck {
+        fen.push_str(&format!(" {}+{}", CHECKS_TO_WIN.saturating_sub(info.checks[0]), CHECKS_TO_WIN.saturating_sub(info.checks[1])));
+    }
+    fen.push_str(&format!(" {} {}", info.halfmove_clock, info.fullmove_number));
+    return fen;
+}
+
+impl State {
+    pub fn to_fen(&self) -> String {
+        return write_fen(&self.board, self.turn, &self.info, self.variant, false);
+    }
+    pub fn to_shredder_fen(&self) -> String {
+        return write_fen(&self.board, self.turn, &self.info, self.variant, true);
+    }
+}
@@ -1,17 +1,17 @@
 use crate::lib::{
     chessbyte::ChessByte, 
-    mask::Mask, 
     piece::{
         Parity, 
         PieceByte
     }, 
-    point::{
-        algebraic_to_point, 
-        Point
-    }, 
+    point::Point, 
     state::State,
+    motion::Motion,
+    outcome::GameOutcome,
+    fen::FenError,
     searchtree::SearchTree,
-    player::Player
+    player::Player,
+    variant::Variant
 
 };
 use std::sync::{Arc, Mutex};
@@ -19,101 +19,35 @@ use std::sync::{Arc, Mutex};
 pub struct ChessGame {
     pub selected: usize,
     pub state: Arc<Mutex<State>>,
-    pub game_over: bool,
+    pub outcome: Option<GameOutcome>,
     pub players: (Option<Arc<Mutex<dyn Player>>>, Option<Arc<Mutex<dyn Player>>>),
     pub human_player: Parity,
     pub state_history: Vec<Arc<Mutex<State>>>,
     pub tree: Option<Arc<Mutex<SearchTree>>>,
     pub paused: bool,
-    pub visual_weights: Option<[i32; 64]>
+    pub visual_weights: Option<[i32; 64]>,
+    pub pending_promotion: Option<Motion>
 }
 
 
-fn get_king_or_queenside(index: usize) -> u8 {
-    let modded = index % 8;
-    return if modded < 4 { 0b01000000u8 } else if modded > 4 { 0b00100000u8 } else { 0b00000000u8 };
-}
 impl ChessGame {
-    pub fn init(fen: String) -> ChessGame {
-        let cg = ChessGame {
+    pub fn init(fen: &str) -> Result<ChessGame, FenError> {
+        return ChessGame::init_variant(fen, Variant::Standard);
+    }
+    pub fn init_variant(fen: &str, variant: Variant) -> Result<ChessGame, FenError> {
+        let state = State::from_variant_fen(fen, variant)?;
+        return Ok(ChessGame {
             selected: 65,
-            state: Arc::new(Mutex::new(State::default())),
+            state: Arc::new(Mutex::new(state)),
             state_history: Vec::new(),
-            game_over: false,
+            outcome: None,
             human_player: Parity::NONE,
             players: (None, None),
             tree: None,
             paused: false ,
-            visual_weights: Some([0i32; 64])
-        };
-        let mut locked = cg.state.lock().unwrap();
-
-
-        let mut b_index: usize = 0;
-        let mut index = 0;
-        for (i, c) in fen.chars().enumerate() {
-            index = i;
-            if c == ' ' { 
-                break;
-            };
-            if c == '/' { continue };
-            if c.is_digit(10) {
-                b_index += (c as i32 - '0' as i32) as usize;
-            } else {
-                match c {
-                    'r' => locked.board[b_index] = Parity::BLACK | PieceByte::ROOK | get_king_or_queenside(b_index),
-                    'R' => locked.board[b_index] = Parity::WHITE | PieceByte::ROOK | get_king_or_queenside(b_index),
-
-                    'n' => locked.board[b_index] = Parity::BLACK | PieceByte::KNIGHT | get_king_or_queenside(b_index),
-                    'N' => locked.board[b_index] = Parity::WHITE | PieceByte::KNIGHT | get_king_or_queenside(b_index),
-                    
-                    'b' => locked.board[b_index] = Parity::BLACK | PieceByte::BISHOP | get_king_or_queenside(b_index),
-                    'B' => locked.board[b_index] = Parity::WHITE | PieceByte::BISHOP | get_king_or_queenside(b_index),
-                    
-                    'q' => locked.board[b_index] = Parity::BLACK | PieceByte::QUEEN | get_king_or_queenside(b_index),
-                    'Q' => locked.board[b_index] = Parity::WHITE | PieceByte::QUEEN | get_king_or_queenside(b_index),
-                    
-                    'k' => locked.board[b_index] = Parity::BLACK | PieceByte::KING | get_king_or_queenside(b_index),
-                    
-                    'K' => locked.board[b_index] = Parity::WHITE | PieceByte::KING | get_king_or_queenside(b_index),
-                    
-                    'p' => locked.board[b_index] = Parity::BLACK | PieceByte::PAWN | get_king_or_queenside(b_index),
-                    'P' => locked.board[b_index] = Parity::WHITE | PieceByte::PAWN | get_king_or_queenside(b_index),
-
-                    _ => ()
-                    
-                }
-                b_index += 1;
-            }
-
-        }
-
-        locked.turn = if fen.chars().nth(index + 1).unwrap() == 'w' { Parity::WHITE } else { Parity::BLACK };
-        index += 3; // Skip space, turn char, and another space
-        while index < fen.len() && fen.chars().nth(index) != Some(' ') {
-            match fen.chars().nth(index).unwrap(){
-                'k' => locked.info.allowed_castles |= 0b00000001,
-                'q' => locked.info.allowed_castles |= 0b00000010,
-                'K' => locked.info.allowed_castles |= 0b00000100,
-                'Q' => locked.info.allowed_castles |= 0b00001000,
-                _ => ()
-            }
-            index += 1;
-        }
-        index += 1; // Skip space
-        if fen.chars().nth(index) == Some('-') {
-            index += 2;
-        } else {
-            locked.info.enpassant_mask = Mask::from_point(algebraic_to_point(&fen[index..(index + 2)]));
-            index += 3;
-        }
-        let mut move_counts = fen[index..].split(' ');
-        locked.info.halfmove_clock = move_counts.nth(0).unwrap_or("0").parse::<u64>().unwrap_or(0);
-        locked.info.fullmove_number = move_counts.nth(0).unwrap_or("1").parse::<u64>().unwrap_or(1);
-    
-        locked.init();
-        drop(locked);
-        return cg;
+            visual_weights: Some([0i32; 64]),
+            pending_promotion: None
+        });
     }
     pub fn register_players(&mut self, p1: Option<Arc<Mutex<dyn Player>>>, p2: Option<Arc<Mutex<dyn Player>>>) -> () {
         self.players.0 = p1;
@@ -126,7 +60,38 @@ impl ChessGame {
         };
 
     }
+    pub fn resign(&mut self, parity: Parity) -> () {
+        if self.outcome.is_none() {
+            self.outcome = Some(GameOutcome::Resignation(!parity));
+        }
+    }
+    pub fn choose_promotion(&mut self, piece: PieceByte) -> () {
+        if let Some(pending) = self.pending_promotion.take() {
+            let locked = &mut self.state.lock().unwrap();
+            let chosen = Motion { from: pending.from, to: pending.to, promotion: piece };
+            if locked.moves.parity_moves(locked.turn)[chosen.from].contains(&chosen) {
+                locked.make_motion(&chosen, true);
+            }
+        }
+    }
+    // A Crazyhouse piece dragged from the pocket onto a square, made if it is a legal drop
+    pub fn human_drop(&mut self, piece: PieceByte, pos: Point, player_parity: Parity) -> () {
+        if self.pending_promotion.is_some() {
+            return;
+        }
+        let locked = &mut self.state.lock().unwrap();
+        if player_parity == locked.turn || player_parity == Parity::BOTH {
+            let motion = Motion::drop(piece, pos.to_index());
+            if locked.moves.parity_vect(locked.turn).contains(&motion) {
+                locked.make_motion(&motion, true);
+                self.selected = 65;
+            }
+        }
+    }
     pub fn human_input(&mut self, pos: Point, player_parity: Parity) -> () {
+        if self.pending_promotion.is_some() {
+            return;
+        }
         let locked = &mut self.state.lock().unwrap();
         if player_parity == locked.turn || player_parity == Parity::BOTH {
             let pos_index = pos.to_index();
@@ -137,7 +102,12 @@ impl ChessGame {
                     let moves = if locked.turn == Parity::WHITE { locked.moves.white_moves[self.selected].clone() } else { locked.moves.black_moves[self.selected].clone() };
                     for m in moves.iter() {
                         if m.to == pos_index {
-                            locked.make_motion(m, true);
+                            if m.is_promotion() {
+                                // Wait for the piece picker before committing to anything
+                                self.pending_promotion = Some(Motion { from: m.from, to: m.to, promotion: PieceByte::NONE });
+                            } else {
+                                locked.make_motion(m, true);
+                            }
                             self.selected = 65;
                             break;
                         }
@@ -1,21 +1,12 @@
 use super::motion::Motion;
 
-#[derive(Copy, Debug)]
+#[derive(Clone, Copy, Debug)]
 pub struct EvaluatedMotion {
     pub evaluation: i32,
     pub motion: Motion,
     pub key: u64
 }
 impl Default for EvaluatedMotion { fn default() -> Self { Self { evaluation: 0, key: 0, motion: Motion::default() } } }
-impl Clone for EvaluatedMotion {
-    fn clone(&self) -> Self {
-        Self {
-            evaluation: self.evaluation,
-            motion: self.motion,
-            key: 0,
-        }
-    }
-}
 impl std::fmt::Display for EvaluatedMotion {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         return write!(f, "{} -> {}, ({})", self.motion.from, self.motion.to, self.evaluation);
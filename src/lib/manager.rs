@@ -1,18 +1,26 @@
 
 use eframe::egui;
 use voxell_rng::slice_methods::SelectRandom;
-use std::{collections::{HashMap, HashSet}, fs::OpenOptions, io::Write, sync::{Arc, Mutex}, thread::JoinHandle, time::{self, Duration}};
+use std::{fs::OpenOptions, io::Write, path::Path, sync::{Arc, Mutex}, thread::JoinHandle, time::{self, Duration}};
 use std::thread;
 use crate::lib::{
+    attacks, 
+    bitbase, 
     eval::{self, Evaluator}, 
+    fen, 
     game::ChessGame, 
+    outcome::GameOutcome, 
+    pgn::PgnGame, 
     piece::Parity, 
+    polyglot::{Book, BookSelection, BookSettings}, 
     player::Player, 
     searcher::Searcher, 
-    searchtree::SearchTree
+    searchtree::SearchTree,
+    syzygy::{Tablebase, TablebaseSettings},
+    transposition::TranspositionTable
 };
 
-use super::{heap::EvaluatedMotion, motion::Motion, searcher::{SearchCheckIn, SearchDriver}, state::State, ui::Input};
+use super::{crazyhouse::Pocket, heap::EvaluatedMotion, motion::Motion, searcher::SearchCheckIn, state::State, ui::Input, variant::Variant};
 pub struct VisualInfo {
     pub visual_weights: Option<[i32; 64]>,
     pub cache_saves: Option<usize>,
@@ -47,6 +55,17 @@ impl VisualInfo {
         }
     }
 }
+// Only Three-check games have checks to count
+fn checks_remaining(state: &State) -> Option<[u8; 2]> {
+    return (state.variant == Variant::ThreeCheck).then(|| state.remaining_checks());
+}
+// Only Crazyhouse games have pockets to show and drops to make
+fn pockets(state: &State) -> Option<[Pocket; 2]> {
+    return (state.variant == Variant::Crazyhouse).then_some(state.info.pockets);
+}
+fn drops(state: &State) -> Option<Vec<Motion>> {
+    return (state.variant == Variant::Crazyhouse).then(|| state.moves.parity_vect(state.turn).into_iter().filter(|m| m.is_drop()).collect());
+}
 pub struct SharedState {
     pub board: Option<[u8; 64]>,
     pub allowed_castles: Option<u8>,
@@ -54,8 +73,12 @@ pub struct SharedState {
     pub waiting_for_a_human_input: Option<bool>,
     pub turn: Option<Parity>,
     pub selected: Option<usize>,
+    pub promotion_square: Option<usize>,
     pub working: Option<bool>,
-    pub game_over: Option<bool>,
+    pub outcome: Option<GameOutcome>,
+    pub checks_remaining: Option<[u8; 2]>,
+    pub pockets: Option<[Pocket; 2]>,
+    pub drops: Option<Vec<Motion>>,
     pub visuals: VisualInfo
 }
 
@@ -72,22 +95,26 @@ pub struct Manager {
     last_worker_notice: Option<SearchCheckIn>,
     stable_board: [u8; 64],
     benchmode: bool,
-    asm: bool
+    asm: bool,
+    book: Option<BookSettings>,
+    tablebase: Option<TablebaseSettings>,
+    hash_mb: usize,
+    threads: usize
 }
 
-pub struct ManagerPlayer { parity: Parity, searcher: Searcher }
+pub struct ManagerPlayer { parity: Parity, searcher: Searcher, book: Option<BookSettings> }
 
 impl ManagerPlayer {
-    fn new(parity: Parity) -> Self {
+    fn new(parity: Parity, book: Option<BookSettings>, tablebase: Option<TablebaseSettings>, hash_mb: usize, threads: usize) -> Self {
         return Self {
             parity,
+            book,
             searcher: Searcher {
-                tree: Vec::new(),
                 time_limit: time::Duration::from_secs_f32(3.0),
-                tt: HashMap::new(),
-                driver: SearchDriver::default(),
-                mtm: Motion::default(),
-                echo: HashSet::default()
+                tablebase,
+                tt: Arc::new(TranspositionTable::new(hash_mb)),
+                threads,
+                ..Searcher::default()
             }
         };
     }
@@ -103,8 +130,14 @@ impl Player for ManagerPlayer {
         return self.parity;
     }
     fn your_turn(&mut self, state: Arc<Mutex<State>>, comms: crossbeam_channel::Sender<SearchCheckIn>) -> bool {
-        let lock = state.lock().unwrap();
+        let mut lock = state.lock().unwrap();
         self.searcher.echo.insert(lock.info.zkey);
+        if let Some(book_move) = self.book.as_ref().and_then(|b| b.probe(&lock)) {
+            println!("Book move {} (weight {} of {})", lock.to_uci(&book_move.motion), book_move.weight, book_move.total);
+            lock.make_motion(&book_move.motion, true);
+            self.searcher.echo.insert(lock.info.zkey);
+            return true;
+        }
         drop(lock);
         self.searcher.driver.communicate_on(comms);
         self.searcher.tree.clear();
@@ -126,17 +159,67 @@ impl Manager {
             println!("NOT using asm");
             false
         };
+        attacks::init();
         // let mut benchmode = true;
         let mut benchmode = false;
+        let mut book_path = None;
+        let mut book_selection = BookSelection::Weighted;
+        let mut book_depth = 16;
+        let mut syzygy_path = None;
+        let mut syzygy_depth = 1;
+        let mut hash_mb = TranspositionTable::DEFAULT_MB;
+        let mut threads = 1;
         for arg in std::env::args() {
             if arg == "bench" {
                 benchmode = true;
+            } else if arg == "book_best" {
+                book_selection = BookSelection::Best;
+            } else if let Some(path) = arg.strip_prefix("book=") {
+                book_path = Some(path.to_string());
+            } else if let Some(depth) = arg.strip_prefix("book_depth=") {
+                book_depth = depth.parse().unwrap_or(book_depth);
+            } else if let Some(path) = arg.strip_prefix("syzygy=") {
+                syzygy_path = Some(path.to_string());
+            } else if let Some(depth) = arg.strip_prefix("syzygy_depth=") {
+                syzygy_depth = depth.parse().unwrap_or(syzygy_depth);
+            } else if let Some(mb) = arg.strip_prefix("hash=") {
+                hash_mb = mb.parse().unwrap_or(hash_mb);
+            } else if let Some(count) = arg.strip_prefix("threads=") {
+                threads = count.parse::<usize>().unwrap_or(threads).max(1);
+            } else if let Some(dir) = arg.strip_prefix("bitbases=") {
+                // Solving KBNK takes a few seconds the first time, after that it's read from the directory
+                match bitbase::load_or_generate(Path::new(dir)) {
+                    Ok(()) => println!("Loaded endgame bitbases from {}", dir),
+                    Err(e) => eprintln!("Could not load bitbases from \"{}\": {}", dir, e)
+                }
             }
         }
+        let book = book_path.and_then(|path| match Book::open(Path::new(&path)) {
+            Ok(book) => {
+                println!("Loaded {} book entries from {}", book.len(), path);
+                Some(BookSettings { book: Arc::new(book), selection: book_selection, max_ply: book_depth })
+            },
+            Err(e) => {
+                eprintln!("Could not load book \"{}\": {}", path, e);
+                None
+            }
+        });
+        let tablebase = syzygy_path.and_then(|path| {
+            let tables = Tablebase::open(&path);
+            if tables.largest() == 0 {
+                eprintln!("No Syzygy tables found in \"{}\"", path);
+                return None;
+            }
+            println!("Found {} Syzygy tables in {}, up to {} pieces", tables.table_count(), path, tables.largest());
+            return Some(TablebaseSettings { tables: Arc::new(tables), probe_depth: syzygy_depth });
+        });
 
         let mut mgr = Manager {
             frame,
-            game: ChessGame::init(init_fen.to_string()),
+            game: ChessGame::init(&init_fen).unwrap_or_else(|e| {
+                eprintln!("Could not load FEN \"{}\": {}. Falling back to the starting position.", init_fen, e);
+                return ChessGame::init(fen::STARTING_FEN).unwrap();
+            }),
             game_rect: egui::Rect {
                 min: egui::Pos2 { x: 0.0, y: 0.0 },
                 max: egui::Pos2 { x: playing_area, y: playing_area }
@@ -153,14 +236,46 @@ impl Manager {
             last_worker_notice: None,
             stable_board: [0u8; 64],
             benchmode,
-            asm
+            asm,
+            book,
+            tablebase,
+            hash_mb,
+            threads
         };
-        mgr.game.register_players(None, Some(Arc::new(Mutex::new(ManagerPlayer::new(Parity::BLACK)))));
+        mgr.game.register_players(None, Some(Arc::new(Mutex::new(ManagerPlayer::new(Parity::BLACK, mgr.book.clone(), mgr.tablebase.clone(), mgr.hash_mb, mgr.threads)))));
         let tmplock = mgr.game.state.lock().unwrap();
         mgr.current_eval = eval::start_eval(&tmplock);
         drop(tmplock);
         mgr.begin();
     }
+    // Replaces the game with a fresh one from the FEN, seated the same way as at startup
+    fn new_game(&mut self, fen: &str, variant: Variant) -> () {
+        match ChessGame::init_variant(fen, variant) {
+            Ok(game) => {
+                self.game = game;
+                self.game.register_players(None, Some(Arc::new(Mutex::new(ManagerPlayer::new(Parity::BLACK, self.book.clone(), self.tablebase.clone(), self.hash_mb, self.threads)))));
+                let locked = self.game.state.lock().unwrap();
+                self.current_eval = eval::start_eval(&locked);
+                drop(locked);
+            },
+            Err(e) => eprintln!("Could not start a game from FEN \"{}\": {}", fen, e)
+        }
+    }
+    // Appends the finished game to games.pgn so it outlives the window
+    fn save_pgn(&self, state: &State) -> () {
+        if let Ok(mut pgn) = PgnGame::from_state(state, self.game.outcome) {
+            pgn.set_tag("White", if self.game.players.0.is_some() { "Engine" } else { "Human" });
+            pgn.set_tag("Black", if self.game.players.1.is_some() { "Engine" } else { "Human" });
+            let written = OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open("games.pgn")
+                .and_then(|mut file| writeln!(file, "{}", pgn.write()));
+            if let Err(e) = written {
+                eprintln!("Could not save the game to \"games.pgn\": {}", e);
+            }
+        }
+    }
     pub fn begin(&mut self) -> () {
         let loc = self.game.state.lock().unwrap();
         let mut last_turn = loc.turn;
@@ -168,11 +283,11 @@ impl Manager {
         drop(loc);
         loop {
             let tmplock = self.game.state.lock().unwrap();
-            if self.worker.is_none() && tmplock.turn == Parity::WHITE && tmplock.moves.white_vect.is_empty() {
-                self.game.game_over = true;
-            }
-            if self.worker.is_none() && tmplock.turn == Parity::BLACK && tmplock.moves.black_vect.is_empty() {
-                self.game.game_over = true;
+            if self.worker.is_none() && self.game.outcome.is_none() {
+                self.game.outcome = tmplock.outcome();
+                if self.game.outcome.is_some() {
+                    self.save_pgn(&tmplock);
+                }
             }
             if tmplock.turn != last_turn && self.worker.is_none() {
                 stale_eval = true;
@@ -183,7 +298,7 @@ impl Manager {
                 stale_eval = false;
             }
             drop(tmplock);
-            if self.game.game_over {
+            if let Some(outcome) = self.game.outcome {
                 let locked = self.game.state.lock().unwrap();
                 let _ = self.sender.send(SharedState {
                     board: Some(locked.board),
@@ -191,8 +306,12 @@ impl Manager {
                     waiting_for_a_human_input: Some(false),
                     allowed_castles: Some(locked.info.allowed_castles),
                     selected: Some(self.game.selected),
+                    promotion_square: Some(self.game.pending_promotion.map_or(65, |m| m.to)),
                     working: Some(false),
-                    game_over: Some(true),
+                    outcome: Some(outcome),
+                    checks_remaining: checks_remaining(&locked),
+                    pockets: pockets(&locked),
+                    drops: drops(&locked),
                     moves: Some(locked.moves.parity_moves(locked.turn)),
                     visuals: VisualInfo::weight_eval(
                         &self.game.visual_weights, 
@@ -200,6 +319,9 @@ impl Manager {
                     )
                 });
                 drop(locked);
+                if let Ok(Input { new_game: Some((fen, variant)), .. }) = self.receiver.try_recv() {
+                    self.new_game(&fen, variant);
+                }
                 thread::sleep(Duration::from_millis(16));
                 self.frame.request_repaint();
                 continue;
@@ -215,8 +337,12 @@ impl Manager {
                             moves: None,
                             allowed_castles: None,
                             working: Some(true),
-                            game_over: None,
+                            outcome: None,
+                            checks_remaining: None,
+                            pockets: None,
+                            drops: None,
                             selected: None,
+                            promotion_square: None,
                             visuals: VisualInfo::all(
                                 &self.game.visual_weights.unwrap(),
                                 self.current_eval.clone(),
@@ -235,7 +361,11 @@ impl Manager {
             } else {
                 if let Some(w) = self.worker.take() {
                     if !w.join().unwrap_or(false) {
-                        self.game.game_over = true;
+                        let state = Arc::clone(&self.game.state);
+                        let locked = state.lock().unwrap();
+                        self.game.resign(locked.turn);
+                        self.save_pgn(&locked);
+                        drop(locked);
                     }
                     if let Some(last) = &self.last_worker_notice {
                         let mut file = OpenOptions::new()
@@ -257,10 +387,14 @@ impl Manager {
                         turn: Some(self.game.human_player),
                         moves: Some(locked.moves.parity_moves(self.game.human_player)),
                         selected: Some(self.game.selected),
+                        promotion_square: Some(self.game.pending_promotion.map_or(65, |m| m.to)),
                         board: Some(locked.board),
                         allowed_castles: Some(locked.info.allowed_castles),
                         working: Some(false),
-                        game_over: Some(false),
+                        outcome: None,
+                        checks_remaining: checks_remaining(&locked),
+                        pockets: pockets(&locked),
+                        drops: drops(&locked),
                         visuals: VisualInfo::weight_eval(&self.game.visual_weights, self.current_eval.clone())
                     }).unwrap();
                     self.frame.request_repaint();
@@ -274,10 +408,14 @@ impl Manager {
                         turn: Some(self.game.human_player),
                         moves: Some(locked.moves.parity_moves(self.game.human_player)),
                         selected: Some(self.game.selected),
+                        promotion_square: Some(self.game.pending_promotion.map_or(65, |m| m.to)),
                         board: Some(locked.board),
                         allowed_castles: Some(locked.info.allowed_castles),
                         working: Some(false),
-                        game_over: Some(false),
+                        outcome: None,
+                        checks_remaining: checks_remaining(&locked),
+                        pockets: pockets(&locked),
+                        drops: drops(&locked),
                         visuals: VisualInfo::weight_eval(&self.game.visual_weights, self.current_eval.clone())
                     }).unwrap();
                     drop(locked);
@@ -288,15 +426,28 @@ impl Manager {
                         turn: Some(self.game.human_player),
                         moves: Some(locked.moves.parity_moves(self.game.human_player)),
                         selected: Some(self.game.selected),
+                        promotion_square: Some(self.game.pending_promotion.map_or(65, |m| m.to)),
                         board: Some(locked.board),
                         allowed_castles: Some(locked.info.allowed_castles),
                         working: Some(false),
-                        game_over: Some(false),
+                        outcome: None,
+                        checks_remaining: checks_remaining(&locked),
+                        pockets: pockets(&locked),
+                        drops: drops(&locked),
                         visuals: VisualInfo::weight_eval(&self.game.visual_weights, self.current_eval.clone())
                     }).unwrap();
                     match self.receiver.try_recv() {
                         Ok(x) => {
-                            if x.left {
+                            if let Some((fen, variant)) = x.new_game {
+                                drop(locked);
+                                self.new_game(&fen, variant);
+                            } else if let Some(piece) = x.promotion {
+                                drop(locked);
+                                self.game.choose_promotion(piece);
+                            } else if let Some((piece, pos)) = x.drop {
+                                drop(locked);
+                                self.game.human_drop(piece, pos, self.game.human_player);
+                            } else if x.left {
                                 drop(locked);
                                 self.game.human_input(x.pos.unwrap_or_default(), self.game.human_player);
                                 let locked = self.game.state.lock().unwrap();
@@ -305,10 +456,14 @@ impl Manager {
                                     turn: Some(locked.turn),
                                     moves: Some(locked.moves.parity_moves(locked.turn)),
                                     selected: Some(self.game.selected),
+                                    promotion_square: Some(self.game.pending_promotion.map_or(65, |m| m.to)),
                                     board: Some(locked.board),
                                     allowed_castles: Some(locked.info.allowed_castles),
                                     working: Some(false),
-                                    game_over: Some(false),
+                                    outcome: None,
+                                    checks_remaining: checks_remaining(&locked),
+                                    pockets: pockets(&locked),
+                                    drops: drops(&locked),
                                     visuals: VisualInfo::weight_eval(&self.game.visual_weights, self.current_eval.clone())
                                 }).unwrap();
                                 drop(locked);
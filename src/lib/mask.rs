@@ -202,6 +202,7 @@ impl Mask {
         return None;
     }
 
+    #[cfg(feature = "gui")]
     pub fn to_painter_rect(&self, sqsize: f32) -> eframe::egui::Rect {
         return eframe::egui::Rect {
             min: (self.to_point().unwrap_or(Point { x: -100, y: -100 }) * sqsize).into(),
@@ -1,36 +1,111 @@
 use crate::lib::{
+    attacks,
     chessbyte::ChessByte,
-    mask::Mask
+    mask::Mask,
+    piece::{Parity, PieceByte}
 };
 
 #[derive(Clone, Copy)]
 pub struct MaskSet {
     pub white: Mask,
     pub black: Mask,
-    pub all: Mask
+    pub all: Mask,
+    // [white, black], indexed by the PieceByte value. Slot 0 is unused.
+    pub pieces: [[Mask; 7]; 2]
 }
 impl Default for MaskSet {
     fn default() -> Self {
         Self {
             white: Mask::default(),
             black: Mask::default(),
-            all: Mask::default()
+            all: Mask::default(),
+            pieces: [[Mask::default(); 7]; 2]
         }
     }
 }
 
+#[inline(always)]
+fn side(parity: Parity) -> usize { if parity == Parity::WHITE { 0 } else { 1 } }
+
 impl MaskSet {
     pub fn from_board(board: &[u8; 64]) -> Self {
         let mut ms = Self::default();
         for (index, byte) in board.iter().enumerate() {
-            if byte.is_white() {
-                ms.white |= Mask::from_index(index);
-            } else if byte.is_black() {
-                ms.black |= Mask::from_index(index);
+            if byte.is_piece() {
+                ms.toggle(index, *byte);
             }
         }
-        ms.all |= ms.white | ms.black;
         return ms;
     }
-
+    // Adds the piece to its square if absent, removes it if present
+    #[inline(always)]
+    pub fn toggle(&mut self, index: usize, byte: u8) -> () {
+        let bit = Mask::from_index(index);
+        if byte.is_white() {
+            self.white ^= bit;
+        } else {
+            self.black ^= bit;
+        }
+        self.all ^= bit;
+        self.pieces[side(byte.get_parity())][byte.get_piece() as usize] ^= bit;
+    }
+    #[inline(always)]
+    pub fn parity(&self, parity: Parity) -> Mask {
+        return if parity == Parity::WHITE { self.white } else { self.black };
+    }
+    #[inline(always)]
+    pub fn piece(&self, parity: Parity, piece: PieceByte) -> Mask {
+        return self.pieces[side(parity)][piece as usize];
+    }
+    #[inline(always)]
+    pub fn count(&self, parity: Parity, piece: PieceByte) -> u32 {
+        return self.piece(parity, piece).raw.count_ones();
+    }
+    // The king's index, or 65 when that side has no king
+    #[inline(always)]
+    pub fn king(&self, parity: Parity) -> usize {
+        let kings = self.piece(parity, PieceByte::KING);
+        return if kings.any() { kings.raw.trailing_zeros() as usize } else { 65 };
+    }
+    #[inline(always)]
+    pub fn kings(&self) -> [usize; 2] {
+        return [self.king(Parity::WHITE), self.king(Parity::BLACK)];
+    }
+    #[inline(always)]
+    pub fn orthogonal_sliders(&self, parity: Parity) -> Mask {
+        return self.piece(parity, PieceByte::ROOK) | self.piece(parity, PieceByte::QUEEN);
+    }
+    #[inline(always)]
+    pub fn diagonal_sliders(&self, parity: Parity) -> Mask {
+        return self.piece(parity, PieceByte::BISHOP) | self.piece(parity, PieceByte::QUEEN);
+    }
+    // Every square attacked by the given side, with sliders stopped by the occupancy
+    pub fn attack_map(&self, of_parity: Parity, occupancy: &Mask) -> Mask {
+        let mut mask = Mask::default();
+        for iso in self.orthogonal_sliders(of_parity).isolated_bits() {
+            mask |= attacks::rook_attacks(iso.as_index(), occupancy);
+        }
+        for iso in self.diagonal_sliders(of_parity).isolated_bits() {
+            mask |= attacks::bishop_attacks(iso.as_index(), occupancy);
+        }
+        for iso in self.piece(of_parity, PieceByte::KNIGHT).isolated_bits() {
+            mask |= attacks::knight_attacks(iso.as_index());
+        }
+        for iso in self.piece(of_parity, PieceByte::PAWN).isolated_bits() {
+            mask |= attacks::pawn_attacks(iso.as_index(), of_parity);
+        }
+        for iso in self.piece(of_parity, PieceByte::KING).isolated_bits() {
+            mask |= attacks::king_attacks(iso.as_index());
+        }
+        return mask;
+    }
+    // The pieces of the other side that attack the given square
+    pub fn attackers_of(&self, index: usize, parity: Parity) -> Mask {
+        let enemy = !parity;
+        return (attacks::rook_attacks(index, &self.all) & self.orthogonal_sliders(enemy))
+            | (attacks::bishop_attacks(index, &self.all) & self.diagonal_sliders(enemy))
+            | (attacks::knight_attacks(index) & self.piece(enemy, PieceByte::KNIGHT))
+            | (attacks::pawn_attacks(index, parity) & self.piece(enemy, PieceByte::PAWN))
+            | (attacks::king_attacks(index) & self.piece(enemy, PieceByte::KING));
+    }
 }
@@ -1,23 +1,50 @@
-use super::{mask::Mask, piece::Parity};
+use super::{fen::square_name, mask::Mask, notation::{drop_letter, piece_letter}, piece::{Parity, PieceByte}};
+
+pub const PROMOTION_CHOICES: [PieceByte; 4] = [PieceByte::QUEEN, PieceByte::KNIGHT, PieceByte::ROOK, PieceByte::BISHOP];
+// The from square of a Crazyhouse drop, whose piece rides in the promotion field
+pub const DROP: usize = 64;
 
 #[derive(Copy)]
 pub struct Motion {
     pub from: usize,
-    pub to: usize
+    pub to: usize,
+    pub promotion: PieceByte
 }
 impl Clone for Motion {
-    fn clone(&self) -> Self { Self { to: self.to, from: self.from } }
+    fn clone(&self) -> Self { Self { to: self.to, from: self.from, promotion: self.promotion } }
+}
+impl Default for Motion { fn default() -> Self { Self { from: 65, to: 65, promotion: PieceByte::NONE } } }
+impl PartialEq for Motion {
+    fn eq(&self, other: &Motion) -> bool { self.from == other.from && self.to == other.to && self.promotion == other.promotion }
+}
+impl Eq for Motion {}
+
+impl Motion {
+    #[inline(always)]
+    pub fn drop(piece: PieceByte, to: usize) -> Self { Self { from: DROP, to, promotion: piece } }
+    #[inline(always)]
+    pub fn is_drop(&self) -> bool { self.from == DROP }
+    #[inline(always)]
+    pub fn is_promotion(&self) -> bool { self.promotion != PieceByte::NONE && !self.is_drop() }
 }
-impl Default for Motion { fn default() -> Self { Self { from: 65, to: 65 } } }
 
 impl std::fmt::Debug for Motion {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        return write!(f, "Motion from {}, `to {}", self.from, self.to);
+        return write!(f, "Motion from {}, `to {}, promoting to {}", self.from, self.to, self.promotion);
     }
 }
 impl std::fmt::Display for Motion {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        return write!(f, "{} -> {}", self.from, self.to);
+        if self.is_drop() && self.to < 64 {
+            return write!(f, "{}@{}", drop_letter(self.promotion), square_name(self.to));
+        }
+        if self.from > 63 || self.to > 63 {
+            return write!(f, "0000");
+        }
+        if self.is_promotion() {
+            return write!(f, "{}{}{}", square_name(self.from), square_name(self.to), piece_letter(self.promotion).to_ascii_lowercase());
+        }
+        return write!(f, "{}{}", square_name(self.from), square_name(self.to));
     }
 }
 
@@ -30,6 +57,7 @@ pub struct MotionSet {
     pub white_defensive_flat: Mask,
     pub white_defensive_piecewise_flat: [Mask; 64],
     pub white_vect: Vec<Motion>,
+    pub white_checkers: Mask,
 
     pub black_moves: [Vec<Motion>; 64],
     pub black_flat: Mask,
@@ -38,6 +66,7 @@ pub struct MotionSet {
     pub black_defensive_flat: Mask,
     pub black_defensive_piecewise_flat: [Mask; 64],
     pub black_vect: Vec<Motion>,
+    pub black_checkers: Mask,
 }
 
 impl MotionSet {
@@ -69,6 +98,15 @@ impl MotionSet {
             return self.black_vect.clone();
         }
     }
+    pub fn parity_checkers(&self, parity: Parity) -> Mask {
+        if parity == Parity::WHITE {
+            return self.white_checkers;
+        } else {
+            return self.black_checkers;
+        }
+    }
+    #[inline(always)]
+    pub fn parity_in_check(&self, parity: Parity) -> bool { self.parity_checkers(parity).any() }
     pub fn parity_defense_moves(&self, parity: Parity) -> [Vec<Motion>; 64]{
         if parity == Parity::WHITE {
             return self.white_defensive_moves.clone();
@@ -102,6 +140,7 @@ impl Default for MotionSet {
             white_defensive_flat: Mask::default(),
             white_defensive_piecewise_flat: [Mask::default(); 64],
             white_vect: Vec::new(),
+            white_checkers: Mask::default(),
 
             black_moves: [const { Vec::new() }; 64],
             black_flat: Mask::default(),
@@ -110,6 +149,7 @@ impl Default for MotionSet {
             black_defensive_flat: Mask::default(),
             black_defensive_piecewise_flat: [Mask::default(); 64],
             black_vect: Vec::new(),
+            black_checkers: Mask::default(),
         }
     }
 }
@@ -0,0 +1,238 @@
+use crate::lib::{
+    boardarray,
+    chessbyte::ChessByte,
+    fen::{square_index, square_name},
+    motion::Motion,
+    piece::PieceByte,
+    state::State
+};
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum NotationError {
+    Malformed(String),
+    Illegal(String),
+    Ambiguous(String)
+}
+
+impl std::fmt::Display for NotationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            Self::Malformed(s) => write!(f, "could not read move \"{}\"", s),
+            Self::Illegal(s) => write!(f, "illegal move \"{}\"", s),
+            Self::Ambiguous(s) => write!(f, "ambiguous move \"{}\"", s)
+        };
+    }
+}
+
+impl std::error::Error for NotationError {}
+
+pub fn piece_letter(piece: PieceByte) -> char {
+    return match piece {
+        PieceByte::ROOK => 'R',
+        PieceByte::KNIGHT => 'N',
+        PieceByte::BISHOP => 'B',
+        PieceByte::QUEEN => 'Q',
+        PieceByte::KING => 'K',
+        PieceByte::PAWN | PieceByte::NONE => ' '
+    };
+}
+
+// Drops name pawns too, as in P@e4
+pub fn drop_letter(piece: PieceByte) -> char {
+    return if piece == PieceByte::PAWN { 'P' } else { piece_letter(piece) };
+}
+
+pub fn letter_piece(letter: char) -> Option<PieceByte> {
+    return match letter.to_ascii_uppercase() {
+        'R' => Some(PieceByte::ROOK),
+        'N' => Some(PieceByte::KNIGHT),
+        'B' => Some(PieceByte::BISHOP),
+        'Q' => Some(PieceByte::QUEEN),
+        'K' => Some(PieceByte::KING),
+        _ => None
+    };
+}
+
+impl State {
+    // Castles are stored as king takes own rook
+    pub fn is_castle(&self, motion: &Motion) -> bool {
+        if motion.is_drop() {
+            return false;
+        }
+        let (from, to) = (self.board[motion.from], self.board[motion.to]);
+        return from.is_king() && to.is_rook() && from.same_parity(&to);
+    }
+    pub fn is_capture(&self, motion: &Motion) -> bool {
+        return boardarray::is_capture(&self.board, motion);
+    }
+
+    // Castles are written as the king's destination, or as king takes rook in Chess960 like UCI_Chess960 expects
+    pub fn to_uci(&self, motion: &Motion) -> String {
+        if motion.is_drop() {
+            return motion.to_string();
+        }
+        let mut to = motion.to;
+        if self.is_castle(motion) && !self.chess960 {
+            to = if motion.to > motion.from { motion.from + 2 } else { motion.from - 2 };
+        }
+        let mut uci = format!("{}{}", square_name(motion.from), square_name(to));
+        if motion.is_promotion() {
+            uci.push(piece_letter(motion.promotion).to_ascii_lowercase());
+        }
+        return uci;
+    }
+
+    pub fn parse_uci(&self, uci: &str) -> Result<Motion, NotationError> {
+        let malformed = || NotationError::Malformed(uci.to_string());
+        if uci.len() != 4 && uci.len() != 5 {
+            return Err(malformed());
+        }
+        if let Some((letter, square)) = uci.split_once('@') {
+            return self.find_drop(letter, square, uci);
+        }
+        let from = square_index(uci.get(0..2).ok_or_else(malformed)?).ok_or_else(malformed)?;
+        let to = square_index(uci.get(2..4).ok_or_else(malformed)?).ok_or_else(malformed)?;
+        let promotion = match uci.chars().nth(4) {
+            Some(c) => letter_piece(c).ok_or_else(malformed)?,
+            None => PieceByte::NONE
+        };
+        let legal = self.moves.parity_vect(self.turn);
+        // Accept both the king's destination and the king-takes-rook form for castling.
+        // In Chess960 to_uci already writes king takes rook, so only that form is read.
+        let found = legal.iter().find(|m| {
+            m.from == from && m.promotion == promotion && (m.to == to || (self.is_castle(m) && self.to_uci(m)[2..4] == uci[2..4]))
+        });
+        return found.copied().ok_or(NotationError::Illegal(uci.to_string()));
+    }
+
+    pub fn to_san(&mut self, motion: &Motion) -> String {
+        let mut san = String::new();
+        let piece = if motion.is_drop() { motion.promotion } else { self.board[motion.from].get_piece() };
+        if motion.is_drop() {
+            san.push_str(&motion.to_string());
+        } else if self.is_castle(motion) {
+            san.push_str(if motion.to > motion.from { "O-O" } else { "O-O-O" });
+        } else {
+            let capture = self.is_capture(motion);
+            if piece == PieceByte::PAWN {
+                if capture {
+                    san.push(square_name(motion.from).chars().nth(0).unwrap());
+                }
+            } else {
+                san.push(piece_letter(piece));
+                let rivals: Vec<Motion> = self.moves.parity_vect(self.turn).into_iter().filter(|m| {
+                    m.to == motion.to && m.from != motion.from && !m.is_drop() && self.board[m.from].get_piece() == piece && !self.is_castle(m)
+                }).collect();
+                if !rivals.is_empty() {
+                    let from = square_name(motion.from);
+                    if rivals.iter().all(|m| m.from % 8 != motion.from % 8) {
+                        san.push_str(&from[0..1]);
+                    } else if rivals.iter().all(|m| m.from / 8 != motion.from / 8) {
+                        san.push_str(&from[1..2]);
+                    } else {
+                        san.push_str(&from);
+                    }
+                }
+            }
+            if capture {
+                san.push('x');
+            }
+            san.push_str(&square_name(motion.to));
+            if motion.is_promotion() {
+                san.push('=');
+                san.push(piece_letter(motion.promotion));
+            }
+        }
+        self.make_motion(motion, false);
+        if self.moves.parity_in_check(self.turn) {
+            san.push(if self.moves.parity_vect(self.turn).is_empty() { '#' } else { '+' });
+        }
+        self.unmake_last(true);
+        return san;
+    }
+
+    pub fn parse_san(&self, san: &str) -> Result<Motion, NotationError> {
+        let malformed = || NotationError::Malformed(san.to_string());
+        let trimmed = san.trim_end_matches(['+', '#', '!', '?']);
+        let legal = self.moves.parity_vect(self.turn);
+
+        let castle = match trimmed {
+            "O-O" | "0-0" => Some(true),
+            "O-O-O" | "0-0-0" => Some(false),
+            _ => None
+        };
+        if let Some(kingside) = castle {
+            let found: Vec<&Motion> = legal.iter().filter(|m| self.is_castle(m) && (m.to > m.from) == kingside).collect();
+            return found.first().map(|m| **m).ok_or(NotationError::Illegal(san.to_string()));
+        }
+
+        if let Some((letter, square)) = trimmed.split_once('@') {
+            return self.find_drop(letter, square, san);
+        }
+
+        let mut body = trimmed;
+        let mut promotion = PieceByte::NONE;
+        if let Some(at) = body.find('=') {
+            let letter = body[at + 1..].chars().next().ok_or_else(malformed)?;
+            promotion = letter_piece(letter).ok_or_else(malformed)?;
+            body = &body[..at];
+        } else if body.len() > 2 && body.starts_with(|c: char| c.is_ascii_lowercase()) {
+            // Promotions are sometimes written without '=' such as e8Q
+            if let Some(p) = body.chars().last().and_then(letter_piece) {
+                promotion = p;
+                body = &body[..body.len() - 1];
+            }
+        }
+
+        let mut piece = PieceByte::PAWN;
+        if let Some(p) = body.chars().next().filter(|c| c.is_ascii_uppercase()).and_then(letter_piece) {
+            piece = p;
+            body = &body[1..];
+        }
+        if body.len() < 2 || !body.is_char_boundary(body.len() - 2) {
+            return Err(malformed());
+        }
+        let to = square_index(&body[body.len() - 2..]).ok_or_else(malformed)?;
+        let hint = body[..body.len() - 2].trim_end_matches('x');
+        let mut from_file = None;
+        let mut from_rank = None;
+        for c in hint.chars() {
+            match c {
+                'a'..='h' => from_file = Some(c as usize - 'a' as usize),
+                '1'..='8' => from_rank = Some(8 - (c as usize - '0' as usize)),
+                _ => return Err(malformed())
+            }
+        }
+
+        let found: Vec<&Motion> = legal.iter().filter(|m| {
+            m.to == to
+                && !m.is_drop()
+                && m.promotion == promotion
+                && self.board[m.from].get_piece() == piece
+                && !self.is_castle(m)
+                && from_file.is_none_or(|f| m.from % 8 == f)
+                && from_rank.is_none_or(|r| m.from / 8 == r)
+        }).collect();
+        return match found.len() {
+            0 => Err(NotationError::Illegal(san.to_string())),
+            1 => Ok(*found[0]),
+            _ => Err(NotationError::Ambiguous(san.to_string()))
+        };
+    }
+
+    // Drops read the same in UCI and SAN, as N@f3. Pawn drops may leave out the P.
+    fn find_drop(&self, letter: &str, square: &str, written: &str) -> Result<Motion, NotationError> {
+        let malformed = || NotationError::Malformed(written.to_string());
+        let piece = match letter {
+            "" | "P" | "p" => PieceByte::PAWN,
+            _ if letter.len() == 1 => letter_piece(letter.chars().next().unwrap()).ok_or_else(malformed)?,
+            _ => return Err(malformed())
+        };
+        let to = square_index(square).ok_or_else(malformed)?;
+        let motion = Motion::drop(piece, to);
+        if !self.moves.parity_vect(self.turn).contains(&motion) {
+            return Err(NotationError::Illegal(written.to_string()));
+        }
+        return Ok(motion);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::lib::{
+    chessbyte::ChessByte,
+    piece::{Parity, PieceByte},
+    state::State,
+    variant::Variant
+};
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum GameOutcome {
+    Checkmate(Parity),
+    Stalemate,
+    ThreefoldRepetition,
+    FiftyMoveRule,
+    InsufficientMaterial,
+    Resignation(Parity),
+    Timeout(Parity),
+    ThreeChecks(Parity),
+    KingOfTheHill(Parity),
+    Explosion(Parity),
+    OutOfMoves(Parity)
+}
+
+impl GameOutcome {
+    // The side that won, or None for a draw
+    pub fn winner(&self) -> Option<Parity> {
+        return match self {
+            Self::Checkmate(p) | Self::Resignation(p) | Self::Timeout(p) | Self::ThreeChecks(p) | Self::KingOfTheHill(p) | Self::Explosion(p) | Self::OutOfMoves(p) => Some(*p),
+            _ => None
+        };
+    }
+    pub fn reason(&self) -> &'static str {
+        return match self {
+            Self::Checkmate(_) => "checkmate",
+            Self::Stalemate => "stalemate",
+            Self::ThreefoldRepetition => "threefold repetition",
+            Self::FiftyMoveRule => "the fifty-move rule",
+            Self::InsufficientMaterial => "insufficient material",
+            Self::Resignation(_) => "resignation",
+            Self::Timeout(_) => "timeout",
+            Self::ThreeChecks(_) => "three checks",
+            Self::KingOfTheHill(_) => "king of the hill",
+            Self::Explosion(_) => "explosion",
+            Self::OutOfMoves(_) => "running out of moves"
+        };
+    }
+    pub fn headline(&self) -> &'static str {
+        return match self.winner() {
+            Some(Parity::WHITE) => "WHITE WINS",
+            Some(_) => "BLACK WINS",
+            None => "DRAW"
+        };
+    }
+    pub fn result(&self) -> &'static str {
+        return match self.winner() {
+            Some(Parity::WHITE) => "1-0",
+            Some(_) => "0-1",
+            None => "1/2-1/2"
+        };
+    }
+}
+
+impl std::fmt::Display for GameOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return write!(f, "{} by {}", self.headline(), self.reason());
+    }
+}
+
+pub fn insufficient_material(board: &[u8; 64]) -> bool {
+    let mut minors = [0u32; 2];
+    let mut bishop_colors = [false; 2];
+    for (index, byte) in board.iter().enumerate() {
+        match byte.get_piece() {
+            PieceByte::NONE | PieceByte::KING => (),
+            PieceByte::KNIGHT => minors[if byte.is_white() { 0 } else { 1 }] += 1,
+            PieceByte::BISHOP => {
+                minors[if byte.is_white() { 0 } else { 1 }] += 1;
+                bishop_colors[((index / 8) + (index % 8)) % 2] = true;
+            },
+            _ => return false
+        }
+    }
+    if minors[0] + minors[1] <= 1 {
+        return true;
+    }
+    // Any number of bishops that all live on the same colour can never mate
+    let only_bishops = board.iter().all(|b| !b.is_knight());
+    return only_bishops && !(bishop_colors[0] && bishop_colors[1]);
+}
+
+impl State {
+    // Checks the rules that end a game on the board. Resignation and timeout are never produced here.
+    pub fn outcome(&self) -> Option<GameOutcome> {
+        if let Some(winner) = self.variant_winner() {
+            return Some(match self.variant {
+                Variant::KingOfTheHill => GameOutcome::KingOfTheHill(winner),
+                Variant::Atomic => GameOutcome::Explosion(winner),
+                Variant::Antichess => GameOutcome::OutOfMoves(winner),
+                _ => GameOutcome::ThreeChecks(winner)
+            });
+        }
+        if self.moves.parity_vect(self.turn).is_empty() {
+            if self.moves.parity_in_check(self.turn) {
+                return Some(GameOutcome::Checkmate(!self.turn));
+            }
+            return Some(GameOutcome::Stalemate);
+        }
+        if self.repetitions() >= 3 {
+            return Some(GameOutcome::ThreefoldRepetition);
+        }
+        if self.info.halfmove_clock >= 100 {
+            return Some(GameOutcome::FiftyMoveRule);
+        }
+        // A king can always walk to the hill, while checks and blasts need something other than a king
+        let dead = match self.variant {
+            Variant::Standard => insufficient_material(&self.board),
+            Variant::ThreeCheck | Variant::Atomic => self.board.iter().all(|b| b.get_piece() == PieceByte::NONE || b.is_king()),
+            Variant::KingOfTheHill | Variant::Antichess => false,
+            // Anything taken comes back as a drop, so only bare kings with nothing in hand are stuck
+            Variant::Crazyhouse => self.info.maskset.all.bit_count() == 2 && self.info.pockets == [[0u8; 5]; 2]
+        };
+        if dead {
+            return Some(GameOutcome::InsufficientMaterial);
+        }
+        return None;
+    }
+}
@@ -0,0 +1,37 @@
+use crate::lib::{
+    motion::Motion,
+    state::State
+};
+
+impl State {
+    // Counts the leaf nodes of the legal move tree. The last ply is counted without being made.
+    pub fn perft(&mut self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let motions = self.moves.parity_vect(self.turn);
+        if depth == 1 {
+            return motions.len() as u64;
+        }
+        let mut nodes = 0u64;
+        for motion in motions.iter() {
+            self.make_motion(motion, false);
+            nodes += self.perft(depth - 1);
+            self.unmake_last(true);
+        }
+        return nodes;
+    }
+    // Perft split by root motion, for finding which branch disagrees with a reference
+    pub fn divide(&mut self, depth: u32) -> Vec<(Motion, u64)> {
+        let mut split = Vec::new();
+        if depth == 0 {
+            return split;
+        }
+        for motion in self.moves.parity_vect(self.turn).iter() {
+            self.make_motion(motion, false);
+            split.push((*motion, self.perft(depth - 1)));
+            self.unmake_last(true);
+        }
+        return split;
+    }
+}
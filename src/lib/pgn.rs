@@ -0,0 +1,415 @@
+use crate::lib::{
+    fen::{FenError, STARTING_FEN},
+    motion::Motion,
+    notation::NotationError,
+    outcome::GameOutcome,
+    piece::Parity,
+    state::State,
+    variant::Variant
+};
+
+pub const SEVEN_TAG_ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+const LINE_WIDTH: usize = 80;
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum PgnError {
+    BadTag(String),
+    BadFen(FenError),
+    UnterminatedComment,
+    UnbalancedVariation,
+    IllegalMove { move_number: u64, parity: Parity, error: NotationError },
+    NoGame
+}
+
+impl std::fmt::Display for PgnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            Self::BadTag(s) => write!(f, "bad tag pair {}", s),
+            Self::BadFen(e) => write!(f, "bad FEN tag: {}", e),
+            Self::UnterminatedComment => write!(f, "comment is never closed"),
+            Self::UnbalancedVariation => write!(f, "variation parentheses do not match"),
+            Self::IllegalMove { move_number, parity, error } => {
+                write!(f, "{}{} {}", move_number, if *parity == Parity::WHITE { "." } else { "..." }, error)
+            },
+            Self::NoGame => write!(f, "no game found")
+        };
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct PgnMove {
+    pub motion: Motion,
+    pub san: String,
+    pub nags: Vec<u8>,
+    pub comment_before: Option<String>,
+    pub comment: Option<String>,
+    // Alternatives to this move, each starting from the position before it
+    pub variations: Vec<Vec<PgnMove>>
+}
+
+impl PgnMove {
+    fn new(motion: Motion, san: String) -> PgnMove {
+        return PgnMove { motion, san, nags: Vec::new(), comment_before: None, comment: None, variations: Vec::new() };
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<PgnMove>
+}
+
+impl Default for PgnGame {
+    fn default() -> Self {
+        let tags = SEVEN_TAG_ROSTER.iter().map(|name| {
+            let value = match *name {
+                "Date" => "????.??.??",
+                "Result" => "*",
+                _ => "?"
+            };
+            (name.to_string(), value.to_string())
+        }).collect();
+        return PgnGame { tags, moves: Vec::new() };
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Nag(u8),
+    Open,
+    Close,
+    Result(String),
+    San(String)
+}
+
+fn suffix_nag(suffix: &str) -> Option<u8> {
+    return match suffix {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None
+    };
+}
+
+fn read_tag(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<Token, PgnError> {
+    let mut raw = String::from("[");
+    let mut name = String::new();
+    let mut value = String::new();
+    let mut in_value = false;
+    let mut closed_value = false;
+    while let Some(c) = chars.next() {
+        raw.push(c);
+        if in_value {
+            match c {
+                '\\' => if let Some(escaped) = chars.next() { raw.push(escaped); value.push(escaped) },
+                '"' => { in_value = false; closed_value = true },
+                _ => value.push(c)
+            }
+            continue;
+        }
+        match c {
+            ']' => {
+                if name.is_empty() || !closed_value {
+                    return Err(PgnError::BadTag(raw));
+                }
+                return Ok(Token::Tag(name, value));
+            },
+            '"' if !closed_value => in_value = true,
+            c if c.is_whitespace() => (),
+            c if !closed_value && (c.is_alphanumeric() || c == '_') => name.push(c),
+            _ => return Err(PgnError::BadTag(raw))
+        }
+    }
+    return Err(PgnError::BadTag(raw));
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, PgnError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '[' => tokens.push(read_tag(&mut chars)?),
+            '{' => {
+                let mut comment = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => comment.push(c),
+                        None => return Err(PgnError::UnterminatedComment)
+                    }
+                }
+                tokens.push(Token::Comment(comment.split_whitespace().collect::<Vec<_>>().join(" ")));
+            },
+            ';' => {
+                let comment: String = chars.by_ref().take_while(|c| *c != '\n').collect();
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            },
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '$' => {
+                let mut digits = String::new();
+                while let Some(d) = chars.peek().copied().filter(|d| d.is_ascii_digit()) {
+                    digits.push(d);
+                    chars.next();
+                }
+                tokens.push(Token::Nag(digits.parse().unwrap_or(0)));
+            },
+            c if c.is_whitespace() => (),
+            c => {
+                let mut word = String::from(c);
+                while let Some(n) = chars.peek().copied().filter(|n| !n.is_whitespace() && !"[]{}();$".contains(*n)) {
+                    word.push(n);
+                    chars.next();
+                }
+                // Move numbers may be glued to the move, as in "12.Nf3" or "12...Nf3"
+                let unnumbered = word.trim_start_matches(|c: char| c.is_ascii_digit());
+                let san = if unnumbered.starts_with('.') { unnumbered.trim_start_matches('.') } else { word.as_str() };
+                if RESULTS.contains(&word.as_str()) {
+                    tokens.push(Token::Result(word));
+                } else if !san.is_empty() {
+                    let stripped = san.trim_end_matches(['!', '?']);
+                    tokens.push(Token::San(stripped.to_string()));
+                    if let Some(nag) = suffix_nag(&san[stripped.len()..]) {
+                        tokens.push(Token::Nag(nag));
+                    }
+                }
+            }
+        }
+    }
+    return Ok(tokens);
+}
+
+fn read_line(state: &mut State, tokens: &[Token], pos: &mut usize, depth: usize) -> Result<Vec<PgnMove>, PgnError> {
+    let mut line: Vec<PgnMove> = Vec::new();
+    let mut pending_comment: Option<String> = None;
+    while *pos < tokens.len() {
+        match &tokens[*pos] {
+            Token::San(san) => {
+                let motion = state.parse_san(san).map_err(|error| PgnError::IllegalMove {
+                    move_number: state.info.fullmove_number,
+                    parity: state.turn,
+                    error
+                })?;
+                let mut pgn_move = PgnMove::new(motion, state.to_san(&motion));
+                pgn_move.comment_before = pending_comment.take();
+                state.make_motion(&motion, false);
+                line.push(pgn_move);
+            },
+            Token::Nag(nag) => if let Some(last) = line.last_mut() { last.nags.push(*nag) },
+            Token::Comment(comment) => {
+                match line.last_mut() {
+                    Some(last) if pending_comment.is_none() => {
+                        last.comment = Some(match last.comment.take() {
+                            Some(existing) => format!("{} {}", existing, comment),
+                            None => comment.clone()
+                        });
+                    },
+                    _ => pending_comment = Some(comment.clone())
+                }
+            },
+            Token::Open => {
+                let last = line.last().ok_or(PgnError::UnbalancedVariation)?.motion;
+                state.unmake_last(true);
+                *pos += 1;
+                let variation = read_line(state, tokens, pos, depth + 1)?;
+                for _ in 0..variation.len() {
+                    state.unmake_last(true);
+                }
+                state.make_motion(&last, false);
+                line.last_mut().unwrap().variations.push(variation);
+            },
+            Token::Close => {
+                if depth == 0 {
+                    return Err(PgnError::UnbalancedVariation);
+                }
+                return Ok(line);
+            },
+            Token::Result(_) | Token::Tag(_, _) => break
+        }
+        *pos += 1;
+    }
+    if depth > 0 {
+        return Err(PgnError::UnbalancedVariation);
+    }
+    return Ok(line);
+}
+
+fn push_line(words: &mut Vec<String>, line: &[PgnMove], mut fullmove: u64, mut turn: Parity) -> () {
+    let mut needs_number = true;
+    for pgn_move in line {
+        if let Some(comment) = &pgn_move.comment_before {
+            words.push(format!("{{{}}}", comment));
+            needs_number = true;
+        }
+        if turn == Parity::WHITE {
+            words.push(format!("{}.", fullmove));
+        } else if needs_number {
+            words.push(format!("{}...", fullmove));
+        }
+        words.push(pgn_move.san.clone());
+        needs_number = false;
+        for nag in &pgn_move.nags {
+            words.push(format!("${}", nag));
+        }
+        if let Some(comment) = &pgn_move.comment {
+            words.push(format!("{{{}}}", comment));
+            needs_number = true;
+        }
+        for variation in &pgn_move.variations {
+            words.push(String::from("("));
+            push_line(words, variation, fullmove, turn);
+            words.push(String::from(")"));
+            needs_number = true;
+        }
+        if turn == Parity::BLACK {
+            fullmove += 1;
+        }
+        turn = !turn;
+    }
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        return self.tags.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str());
+    }
+    pub fn set_tag(&mut self, name: &str, value: &str) -> () {
+        match self.tags.iter_mut().find(|(n, _)| n == name) {
+            Some(tag) => tag.1 = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string()))
+        }
+    }
+    pub fn start_fen(&self) -> &str {
+        return self.tag("FEN").unwrap_or(STARTING_FEN);
+    }
+    pub fn result(&self) -> &str {
+        return self.tag("Result").unwrap_or("*");
+    }
+    // The main line, ready to be replayed with State::make_motion
+    pub fn motions(&self) -> Vec<Motion> {
+        return self.moves.iter().map(|m| m.motion).collect();
+    }
+    pub fn is_chess960(&self) -> bool {
+        return self.tag("Variant").is_some_and(|v| {
+            let v = v.to_ascii_lowercase();
+            v.contains("960") || v.contains("fischer")
+        });
+    }
+    // The position the game starts from, before any of its moves
+    pub fn start_state(&self) -> Result<State, FenError> {
+        let variant = self.tag("Variant").and_then(Variant::from_name).unwrap_or_default();
+        let mut state = State::from_variant_fen(self.start_fen(), variant)?;
+        state.chess960 |= self.is_chess960();
+        return Ok(state);
+    }
+    pub fn replay(&self) -> Result<State, PgnError> {
+        let mut state = self.start_state().map_err(PgnError::BadFen)?;
+        for motion in self.motions() {
+            state.make_motion(&motion, false);
+        }
+        return Ok(state);
+    }
+
+    // Records every motion made on the state, from the position it was set up with
+    pub fn from_state(state: &State, outcome: Option<GameOutcome>) -> Result<PgnGame, FenError> {
+        let mut game = PgnGame::default();
+        let initial = state.initial_fen();
+        if state.chess960 {
+            game.set_tag("Variant", "Chess960");
+        } else if state.variant != Variant::Standard {
+            game.set_tag("Variant", state.variant.name());
+        }
+        // Variants that add fields to the FEN, like pockets or checks, start from their own form of the usual position
+        let usual = State::from_variant_fen(STARTING_FEN, state.variant)?.to_fen();
+        if initial != usual || state.chess960 {
+            game.set_tag("SetUp", "1");
+            game.set_tag("FEN", &initial);
+        }
+        let mut replay = State::from_variant_fen(&initial, state.variant)?;
+        replay.chess960 = state.chess960;
+        for motion in state.played_motions() {
+            let san = replay.to_san(motion);
+            replay.make_motion(motion, false);
+            game.moves.push(PgnMove::new(*motion, san));
+        }
+        if let Some(outcome) = outcome {
+            game.set_tag("Result", outcome.result());
+            if let Some(last) = game.moves.last_mut() {
+                last.comment = Some(match outcome.winner() {
+                    Some(parity) => format!("{} wins by {}", parity, outcome.reason()),
+                    None => format!("Draw by {}", outcome.reason())
+                });
+            }
+        }
+        return Ok(game);
+    }
+
+    pub fn write(&self) -> String {
+        let mut pgn = String::new();
+        for (name, value) in &self.tags {
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, value.replace('\\', "\\\\").replace('"', "\\\"")));
+        }
+        pgn.push('\n');
+
+        let (fullmove, turn) = match self.start_state() {
+            Ok(state) => (state.info.fullmove_number, state.turn),
+            Err(_) => (1, Parity::WHITE)
+        };
+        let mut words = Vec::new();
+        push_line(&mut words, &self.moves, fullmove, turn);
+        words.push(self.result().to_string());
+
+        let mut line = String::new();
+        for word in words {
+            if !line.is_empty() && word != ")" && line.len() + 1 + word.len() >= LINE_WIDTH {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() && !line.ends_with('(') && word != ")" {
+                line.push(' ');
+            }
+            line.push_str(&word);
+        }
+        pgn.push_str(&line);
+        pgn.push('\n');
+        return pgn;
+    }
+
+    pub fn parse(text: &str) -> Result<PgnGame, PgnError> {
+        return PgnGame::parse_all(text)?.into_iter().next().ok_or(PgnError::NoGame);
+    }
+
+    pub fn parse_all(text: &str) -> Result<Vec<PgnGame>, PgnError> {
+        let tokens = tokenize(text)?;
+        let mut games = Vec::new();
+        let mut pos = 0;
+        while pos < tokens.len() {
+            let mut game = PgnGame { tags: Vec::new(), moves: Vec::new() };
+            while let Some(Token::Tag(name, value)) = tokens.get(pos) {
+                game.set_tag(name, value);
+                pos += 1;
+            }
+            let mut state = game.start_state().map_err(PgnError::BadFen)?;
+            game.moves = read_line(&mut state, &tokens, &mut pos, 0)?;
+            if let Some(Token::Result(result)) = tokens.get(pos) {
+                if game.tag("Result").is_none() {
+                    game.set_tag("Result", result);
+                }
+                pos += 1;
+            }
+            if game.tags.is_empty() && game.moves.is_empty() {
+                continue;
+            }
+            games.push(game);
+        }
+        return Ok(games);
+    }
+}
@@ -1,3 +1,4 @@
+#[cfg(feature = "gui")]
 use eframe::egui;
 
 #[derive(Clone,Copy)]
@@ -63,6 +64,7 @@ impl std::cmp::PartialEq<Point> for Point {
 
 impl std::cmp::Eq for Point {}
 
+#[cfg(feature = "gui")]
 impl Into<egui::Pos2> for Point {
     fn into(self) -> egui::Pos2 {
         return egui::Pos2 {
@@ -72,6 +74,7 @@ impl Into<egui::Pos2> for Point {
     }
 }
 
+#[cfg(feature = "gui")]
 impl From<egui::Pos2> for Point {
     fn from(value: egui::Pos2) -> Self {
         let v = value.floor();
@@ -1,8 +1,8 @@
-use std::{collections::{HashMap, HashSet}, sync::{Arc, Mutex, MutexGuard}, time};
+use std::{collections::HashSet, sync::{atomic::{AtomicBool, AtomicU64, Ordering}, Arc, Mutex, MutexGuard}, thread, time};
 use crate::lib::{
-    chessbyte::ChessByte, eval, motion::Motion, piece::Parity, searchtree::SearchTree
+    bitbase::{self, BitbaseValue}, chessbyte::ChessByte, eval, motion::Motion, piece::{Parity, PieceByte}, searchtree::SearchTree
 };
-use super::{heap::{EvaluatedMotion, Heap}, mask::Mask, state::State};
+use super::{heap::{EvaluatedMotion, Heap}, mask::Mask, state::State, syzygy::{TablebaseSettings, Wdl}, transposition::{Bound, TranspositionTable}, variant::Variant};
 
 #[derive(Debug)]
 pub struct SearchCheckIn {
@@ -24,6 +24,51 @@ impl Default for SearchCheckIn {
     }
 }
 
+// What a protocol front end shows after each finished depth
+#[derive(Debug, Clone)]
+pub struct SearchReport {
+    pub depth: u8,
+    pub score: i32,
+    pub nodes: u64,
+    pub tb_hits: u64,
+    // Per mille of the transposition table filled by this search
+    pub hashfull: usize,
+    pub elapsed: time::Duration,
+    pub best: Motion,
+    // The best move in UCI notation, as the front ends print it
+    pub pv: String
+}
+impl SearchReport {
+    // Whole moves until mate, negative when it's the side to move getting mated
+    pub fn mate_in(&self) -> Option<i32> {
+        let plies = i32::MAX - self.score.abs();
+        if plies > Searcher::ABSOLUTELY_MAX_DEPTH as i32 * 2 {
+            return None;
+        }
+        return Some(self.score.signum() * (plies + 2) / 2);
+    }
+}
+
+// Lets another thread end a search early, or give a pondering search its deadline once the move is played
+#[derive(Debug, Default)]
+pub struct SearchControl {
+    pub stop: AtomicBool,
+    // Milliseconds after the search started, 0 when only the searcher's time limit counts
+    pub deadline: AtomicU64
+}
+impl SearchControl {
+    pub fn reset(&self) -> () {
+        self.stop.store(false, Ordering::SeqCst);
+        self.deadline.store(0, Ordering::SeqCst);
+    }
+    pub fn stop(&self) -> () {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+    pub fn stopped(&self) -> bool {
+        return self.stop.load(Ordering::SeqCst);
+    }
+}
+
 pub struct SearchDriver {
     pub parity: Parity,
     pub depth: u8,
@@ -35,7 +80,11 @@ pub struct SearchDriver {
     pub tree: SearchTree,
     pub time_remaining: time::Duration,
     pub time_start: time::Instant,
-    pub considerations: Vec<EvaluatedMotion>
+    pub considerations: Vec<EvaluatedMotion>,
+    pub tb_hits: u64,
+    // When not empty, the only root moves worth searching
+    pub root_moves: Vec<Motion>,
+    pub reports: Option<crossbeam_channel::Sender<SearchReport>>
 }
 
 impl SearchDriver {
@@ -50,6 +99,8 @@ impl SearchDriver {
         self.time_remaining = time_limit.clone();
         self.time_start = time::Instant::now();
         self.considerations.clear();
+        self.tb_hits = 0;
+        self.root_moves.clear();
     }
     pub fn communicate_on(&mut self, comms: crossbeam_channel::Sender<SearchCheckIn>) -> () {
         self.comm = Some(comms);
@@ -67,38 +118,144 @@ impl SearchDriver {
         }
     }
 }
-impl Default for SearchDriver { fn default() -> Self { Self { cache_saves: 0, positions_looked_at: 0, depth: 0, nodes: 0, q_nodes: 0, parity: Parity::WHITE, comm: None, tree: SearchTree::default(), time_remaining: time::Duration::default(), time_start: time::Instant::now(), considerations: Vec::new() } } }
+impl Default for SearchDriver { fn default() -> Self { Self { cache_saves: 0, positions_looked_at: 0, depth: 0, nodes: 0, q_nodes: 0, parity: Parity::WHITE, comm: None, tree: SearchTree::default(), time_remaining: time::Duration::default(), time_start: time::Instant::now(), considerations: Vec::new(), tb_hits: 0, root_moves: Vec::new(), reports: None } } }
 
 pub struct Searcher {
     pub tree: Vec<Arc<Mutex<SearchTree>>>,
-    pub tt: HashMap<u64, EvaluatedMotion>,
+    // Shared with the helper threads, which is how their work reaches the main search
+    pub tt: Arc<TranspositionTable>,
     pub driver: SearchDriver,
     pub mtm: Motion,
     pub echo: HashSet<u64>,
-    pub time_limit: time::Duration
+    pub time_limit: time::Duration,
+    pub tablebase: Option<TablebaseSettings>,
+    pub depth_limit: Option<u8>,
+    pub node_limit: Option<u64>,
+    pub control: Arc<SearchControl>,
+    // Searching threads, the main one included. The others are Lazy SMP helpers.
+    pub threads: usize,
+    // 0 for the main thread. A helper's number picks the depths it skips, so helpers spread over different depths.
+    pub helper: usize
 }
+impl Default for Searcher {
+    fn default() -> Self {
+        Self {
+            tree: Vec::new(),
+            tt: Arc::new(TranspositionTable::default()),
+            driver: SearchDriver::default(),
+            mtm: Motion::default(),
+            echo: HashSet::default(),
+            time_limit: time::Duration::from_secs_f32(3.0),
+            tablebase: None,
+            depth_limit: None,
+            node_limit: None,
+            control: Arc::new(SearchControl::default()),
+            threads: 1,
+            helper: 0
+        }
+    }
+}
+// Which depths Lazy SMP helpers skip, as in Stockfish. Helper n skips a depth when
+// (depth + SKIP_PHASE[n]) / SKIP_SIZE[n] is odd, taking n round the table when there are more than 20.
+const SKIP_SIZE: [u8; 20] = [1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4];
+const SKIP_PHASE: [u8; 20] = [0, 1, 0, 1, 2, 3, 0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5, 6, 7];
+
 impl Searcher {
     const ABSOLUTELY_MAX_DEPTH: u8 = 100;
     const ASPIRATION_ADJUSTMENT: i32 = 50;
     const MATERIAL_EVAL_CUTOFF: i32 = 1300;
     const CONTEMPT_VAL: i32 = -10;
+    const TABLEBASE_WIN: i32 = 100_000;
     pub fn run(&mut self, state: Arc<Mutex<State>>) -> Motion {
         let lock = state.lock().unwrap();
         let parity = lock.turn;
         drop(lock);
         self.driver.clear(parity, &self.time_limit);
+        self.tt.new_search();
         // calc_movetime();
         // age_history_table();
         self.driver.communicate(&self.mtm);
 
-        let result = self.iterate(state.clone());
+        let result = if self.threads > 1 { self.iterate_with_helpers(state.clone()) } else { self.iterate(state.clone()) };
         self.driver.communicate(&self.mtm);
         return result;
     }
+    // Lazy SMP. The helpers search the same position on their own copies and share only the transposition
+    // table, so what they store there cuts the main thread's search short. Only the main thread's move counts.
+    fn iterate_with_helpers(&mut self, state: Arc<Mutex<State>>) -> Motion {
+        let lock = state.lock().unwrap();
+        let parity = lock.turn;
+        let mut helpers = Vec::new();
+        // Helpers have no limits of their own and run until the main thread is done
+        let helper_control = Arc::new(SearchControl::default());
+        for id in 1..self.threads {
+            helpers.push((self.helper(id, helper_control.clone()), Arc::new(Mutex::new(lock.thread_copy()))));
+        }
+        drop(lock);
+        return thread::scope(|scope| {
+            for (mut helper, copy) in helpers {
+                scope.spawn(move || {
+                    helper.driver.clear(parity, &helper.time_limit);
+                    helper.iterate(copy);
+                });
+            }
+            let result = self.iterate(state.clone());
+            helper_control.stop();
+            return result;
+        });
+    }
+    // Built field by field, since Searcher::default would allocate a table only to throw it away
+    fn helper(&self, id: usize, control: Arc<SearchControl>) -> Searcher {
+        return Searcher {
+            tree: Vec::new(),
+            tt: self.tt.clone(),
+            driver: SearchDriver::default(),
+            mtm: Motion::default(),
+            echo: self.echo.clone(),
+            time_limit: self.time_limit,
+            tablebase: self.tablebase.clone(),
+            depth_limit: None,
+            node_limit: None,
+            control,
+            threads: 1,
+            helper: id
+        };
+    }
+    fn skips(&self, depth: u8) -> bool {
+        if self.helper == 0 {
+            return false;
+        }
+        let n = (self.helper - 1) % SKIP_SIZE.len();
+        return ((depth + SKIP_PHASE[n]) / SKIP_SIZE[n]) % 2 == 1;
+    }
+    // Updates the time left, which runs out early when the search is stopped or past its node limit
+    fn out_of_time(&mut self) -> bool {
+        let elapsed = time::Instant::now().duration_since(self.driver.time_start);
+        self.driver.time_remaining = self.time_limit.checked_sub(elapsed).unwrap_or(time::Duration::ZERO);
+        let deadline = self.control.deadline.load(Ordering::Relaxed);
+        if self.control.stop.load(Ordering::Relaxed) || (deadline > 0 && elapsed.as_millis() as u64 >= deadline) || self.node_limit.is_some_and(|n| self.driver.nodes >= n) {
+            self.driver.time_remaining = time::Duration::ZERO;
+        }
+        return self.driver.time_remaining.is_zero();
+    }
+    fn report(&self, state: &Arc<Mutex<State>>, score: i32) -> () {
+        if let Some(channel) = &self.driver.reports {
+            let lock = state.lock().unwrap();
+            let _ = channel.send(SearchReport {
+                depth: self.driver.depth,
+                score,
+                nodes: self.driver.nodes,
+                tb_hits: self.driver.tb_hits,
+                hashfull: self.tt.hashfull(),
+                elapsed: time::Instant::now().duration_since(self.driver.time_start),
+                best: self.mtm,
+                pv: lock.to_uci(&self.mtm)
+            });
+        }
+    }
     fn analyze(&mut self, state: Arc<Mutex<State>>, mut depth: u8, ply: usize, mut alpha: i32, mut beta: i32, null: bool, pv: bool) -> i32 {
 
-        self.driver.time_remaining = self.time_limit.checked_sub(time::Instant::now().duration_since(self.driver.time_start)).unwrap_or(time::Duration::ZERO);
-        if self.driver.time_remaining.is_zero() {
+        if self.out_of_time() {
             return 0;
         }
         self.driver.positions_looked_at += 1;
@@ -114,8 +271,16 @@ impl Searcher {
         if alpha >= beta {
             return alpha;
         }
+        let alpha_start = alpha;
         let mut lock = state.lock().unwrap();
-        let in_check = (lock.moves.parity_flat(!lock.turn) & Mask::from_index(lock.get_king(lock.turn))).any();
+        let key = lock.info.zkey;
+        if let Some(winner) = lock.variant_winner() {
+            // Usually the previous move reached the variant's goal, but an Antichess side with no moves has won
+            let won = winner == lock.turn;
+            drop(lock);
+            return if won { mate } else { -mate };
+        }
+        let in_check = lock.moves.parity_in_check(lock.turn);
         if in_check { depth += 1 };
         if depth == 0 {
             drop(lock);
@@ -123,12 +288,13 @@ impl Searcher {
         }
         self.driver.nodes += 1;
 
-        let scalar = if lock.turn == self.driver.parity { -1 } else { 1 };
+        // The evaluation is from White's side, and every score in the search is from the side to move's
+        let scalar = if lock.turn == Parity::WHITE { 1 } else { -1 };
 
         if self.echo.contains(&lock.info.zkey) {
             // Consider drawing. Have we seen this position before?
             self.driver.cache_saves += 1;
-            if scalar * eval::material::price_parity(&lock.board, self.driver.parity) < Self::MATERIAL_EVAL_CUTOFF {
+            if eval::material::price_parity(&lock.board, self.driver.parity) < Self::MATERIAL_EVAL_CUTOFF {
                 drop(lock);
                 return 0;
             } else {
@@ -142,14 +308,46 @@ impl Searcher {
             }
         }
 
-        if !pv || (val > alpha && val < beta) {
-            // Try and save time by caching moves
-            if let Some(saved) = self.tt.get(&lock.info.zkey) {
+        if let Some(tb) = &self.tablebase {
+            // Right after a capture or pawn move the tables know the result better than any search
+            if depth >= tb.probe_depth && lock.info.halfmove_clock == 0 && tb.tables.can_probe(&lock) {
+                if let Some(wdl) = tb.tables.probe_wdl(&mut lock) {
+                    self.driver.tb_hits += 1;
+                    drop(lock);
+                    return match wdl {
+                        Wdl::Win => Self::TABLEBASE_WIN - ply as i32,
+                        Wdl::Loss => -Self::TABLEBASE_WIN + ply as i32,
+                        _ => wdl as i32
+                    };
+                }
+            }
+        }
+
+        if let Some(value) = bitbase::probe(&lock) {
+            self.driver.tb_hits += 1;
+            drop(lock);
+            return match value {
+                BitbaseValue::Win(plies) => Self::TABLEBASE_WIN - ply as i32 - plies as i32,
+                BitbaseValue::Loss(plies) => -Self::TABLEBASE_WIN + ply as i32 + plies as i32,
+                BitbaseValue::Draw => 0
+            };
+        }
+
+        let saved = self.tt.probe(key, ply);
+        if let Some(saved) = saved.filter(|s| !pv && s.depth >= depth) {
+            // A search at least this deep already settled the position, or enough of it for this window
+            let settled = match saved.bound {
+                Bound::Exact => true,
+                Bound::Lower => saved.score >= beta,
+                Bound::Upper => saved.score <= alpha
+            };
+            if settled {
                 self.driver.cache_saves += 1;
                 drop(lock);
-                return saved.evaluation;
+                return saved.score;
             }
         }
+        let tt_move = saved.map(|s| s.motion);
         if depth < 3 && !pv && !in_check {
             // Reverse futility prune 
             // When at a low depth, if the motion doens't do much for us (margin), then just estimate
@@ -161,11 +359,11 @@ impl Searcher {
                 return eval_static - margin;
             }
         }
-        if depth > 2 && null && !pv && !in_check && scalar * eval::start_eval(&lock).eval >= beta && scalar * eval::material::price_parity(&lock.board, lock.turn) > Self::MATERIAL_EVAL_CUTOFF {
+        if depth > 2 && null && !pv && !in_check && scalar * eval::start_eval(&lock).eval >= beta && eval::material::price_parity(&lock.board, lock.turn) > Self::MATERIAL_EVAL_CUTOFF {
             // Null move
             // If allowing the opponent to move twice in a row isn't horrible for us, then we can
             // assume there is no point in searching further.
-            lock.make_motion(&Motion { from: 65, to: 65 }, false);
+            lock.make_motion(&Motion::default(), false);
             drop(lock);
             val = -self.analyze(state.clone(), depth - if depth > 6 { 4 } else { 3 }, ply, -beta, -beta + 1, false, false);
             lock = state.lock().unwrap();
@@ -188,12 +386,18 @@ impl Searcher {
         let mut moves_tried = 0;
 
         let moves = lock.moves.parity_vect(lock.turn);
-        // Evaluate moves
+        if moves.is_empty() {
+            // Checkmate, or a stalemate that's only a draw however far ahead we are
+            let score = if in_check { -mate } else { 0 };
+            self.tt.store(key, Motion::default(), score, depth, Bound::Exact, ply);
+            drop(lock);
+            return score;
+        }
+        // Evaluate moves, trying the best one from an earlier search first
         for m in &moves {
             lock.make_motion(m, false);
-            if (lock.moves.parity_flat(lock.turn) & Mask::from_index(lock.get_king(!lock.turn))).none() {
-                heap.push(EvaluatedMotion { evaluation: scalar * eval::start_eval(&lock).eval, motion: *m, key: lock.info.zkey });
-            }
+            let evaluation = if tt_move == Some(*m) { i32::MAX } else { scalar * eval::start_eval(&lock).eval };
+            heap.push(EvaluatedMotion { evaluation, motion: *m, key: lock.info.zkey });
             lock.unmake_last(true);
         }
         // Sort state's vector
@@ -259,8 +463,7 @@ impl Searcher {
                         }
                         */
                         alpha = beta;
-                        self.driver.time_remaining = self.time_limit.checked_sub(time::Instant::now().duration_since(self.driver.time_start)).unwrap_or(time::Duration::ZERO);
-                        if self.driver.time_remaining.is_zero() {
+                        if self.out_of_time() {
                             return 0;
                         }
                         break 'outer;
@@ -270,25 +473,24 @@ impl Searcher {
                 }
                 break 'research;
             }
-            self.driver.time_remaining = self.time_limit.checked_sub(time::Instant::now().duration_since(self.driver.time_start)).unwrap_or(time::Duration::ZERO);
-            if self.driver.time_remaining.is_zero() {
+            if self.out_of_time() {
                 return 0;
             }
         }
-        self.tt.insert(best.key, best);
+        let bound = if alpha >= beta { Bound::Lower } else if alpha > alpha_start { Bound::Exact } else { Bound::Upper };
+        self.tt.store(key, best.motion, alpha, depth, bound, ply);
 
         return alpha;
     }
     fn is_promotion(&self, state: &MutexGuard<'_, State>, m: &Motion) -> bool {
-        return state.board[m.from].is_pawn() && (m.to < 8 || m.to > 55);
+        return m.is_promotion() && state.board[m.from].is_pawn();
     }
     fn is_capture(&self, state: &MutexGuard<'_, State>, m: &Motion) -> bool {
         return state.board[m.to].is_piece() && state.board[m.to].is_parity(!state.board[m.from].get_parity());
     }
     fn is_capture_bad(&self, state: &MutexGuard<'_, State>, m: &Motion) -> bool {
         if state.board[m.from].is_pawn() { return false };
-        let scalar = if state.turn == self.driver.parity { -1 } else { 1 };
-        if scalar * eval::material::price_piece(state.board[m.to]) >= scalar * eval::material::price_piece(state.board[m.from]) - 50 {
+        if eval::material::price_piece(state.board[m.to]) >= eval::material::price_piece(state.board[m.from]) - 50 {
             return false;
         }
         let mask = Mask::from_index(m.to);
@@ -307,8 +509,7 @@ impl Searcher {
     }
     // fn is_repetition(&self, state)
     fn quiescence(&mut self, state: Arc<Mutex<State>>, mut alpha: i32, beta: i32) -> i32 {
-        self.driver.time_remaining = self.time_limit.checked_sub(time::Instant::now().duration_since(self.driver.time_start)).unwrap_or(time::Duration::ZERO);
-        if self.driver.time_remaining.is_zero() {
+        if self.out_of_time() {
             return 0;
         }
         self.driver.positions_looked_at += 1;
@@ -321,9 +522,13 @@ impl Searcher {
         self.driver.q_nodes += 1;
 
         let mut lock = state.lock().unwrap();
-        let scalar = if lock.turn == self.driver.parity { -1 } else { 1 };
+        let scalar = if lock.turn == Parity::WHITE { 1 } else { -1 };
         let mut val = scalar * eval::start_eval(&lock).eval;
         let standing = val;
+        if lock.variant_winner().is_some() {
+            drop(lock);
+            return val;
+        }
 
         if val >= beta {
             drop(lock);
@@ -335,16 +540,18 @@ impl Searcher {
         let moves = lock.moves.parity_vect(lock.turn);
         let mut heap = Heap::default();
         for m in &moves {
-            if !lock.board[m.to].is_piece() || lock.board[m.to].is_parity(lock.board[m.from].get_parity()) {
+            // Quiet promotions are tactical too, but only the queen and knight are worth resolving here
+            let is_promo = self.is_promotion(&lock, &m) && (m.promotion == PieceByte::QUEEN || m.promotion == PieceByte::KNIGHT);
+            if !is_promo && (!lock.board[m.to].is_piece() || lock.board[m.to].is_parity(lock.board[m.from].get_parity())) {
                 continue;
             }
             if lock.board[m.to].is_king() {
                 drop(lock);
                 return i32::MAX - 1;
             }
-            let is_promo = self.is_promotion(&lock, &m);
-            if standing - scalar * eval::material::price_piece(lock.board[m.to]) + 200 < alpha &&
-                scalar * eval::material::price_parity(&lock.board, !lock.turn) - scalar * eval::material::price_piece(lock.board[m.to]) > Self::MATERIAL_EVAL_CUTOFF && 
+            // Even winning the piece outright wouldn't lift the score to alpha
+            if standing + eval::material::price_piece(lock.board[m.to]) + 200 < alpha &&
+                eval::material::price_parity(&lock.board, !lock.turn) - eval::material::price_piece(lock.board[m.to]) > Self::MATERIAL_EVAL_CUTOFF && 
                     !is_promo {
                         continue;
             }
@@ -352,9 +559,7 @@ impl Searcher {
                 continue;
             }
             lock.make_motion(m, false);
-            if (lock.moves.parity_flat(lock.turn) & Mask::from_index(lock.get_king(!lock.turn))).none() {
-                heap.push(EvaluatedMotion { evaluation: scalar * eval::start_eval(&lock).eval, motion: *m, key: lock.info.zkey });
-            }
+            heap.push(EvaluatedMotion { evaluation: scalar * eval::start_eval(&lock).eval, motion: *m, key: lock.info.zkey });
             lock.unmake_last(true);
         }
         drop(lock);
@@ -370,8 +575,7 @@ impl Searcher {
             lock.unmake_last(true);
             SearchTree::back(&mut self.driver.tree, false);
             drop(lock);
-            self.driver.time_remaining = self.time_limit.checked_sub(time::Instant::now().duration_since(self.driver.time_start)).unwrap_or(time::Duration::ZERO);
-            if self.driver.time_remaining.is_zero() {
+            if self.out_of_time() {
                 return 0;
             }
             if val > alpha {
@@ -385,18 +589,24 @@ impl Searcher {
         self.driver.considerations.clear();
         self.driver.communicate(&self.mtm);
         let mut val = 0;
-        let mut best = EvaluatedMotion::default();
         let mut lock = state.lock().unwrap();
-        let in_check = (lock.moves.parity_flat(!lock.turn) & Mask::from_index(lock.get_king(lock.turn))).any();
+        let in_check = lock.moves.parity_in_check(lock.turn);
         if in_check { depth += 1 };
-        let moves = lock.moves.parity_vect(lock.turn);
+        let mut moves = lock.moves.parity_vect(lock.turn);
+        if moves.is_empty() {
+            let score = if in_check { -i32::MAX } else { 0 };
+            self.tt.store(lock.info.zkey, Motion::default(), score, depth, Bound::Exact, 0);
+            drop(lock);
+            return score;
+        }
+        if !self.driver.root_moves.is_empty() {
+            moves.retain(|m| self.driver.root_moves.contains(m));
+        }
         let mut heap = Heap::default();
-        let scalar = if lock.turn == self.driver.parity { -1 } else { 1 };
+        let scalar = if lock.turn == Parity::WHITE { 1 } else { -1 };
         for m in &moves {
             lock.make_motion(m, false);
-            if (lock.moves.parity_flat(lock.turn) & Mask::from_index(lock.get_king(!lock.turn))).none() {
-                heap.push(EvaluatedMotion { evaluation: scalar * eval::start_eval(&lock).eval, motion: *m, key: lock.info.zkey });
-            }
+            heap.push(EvaluatedMotion { evaluation: scalar * eval::start_eval(&lock).eval, motion: *m, key: lock.info.zkey });
             lock.unmake_last(true);
         }
         lock.set_sorted_motions(heap.to_sorted_motions());
@@ -408,9 +618,9 @@ impl Searcher {
             if heap.empty() { break };
             let motion = heap.pop();
             lock = state.lock().unwrap();
-            if lock.board[motion.motion.to].is_king() {
+            if lock.variant != Variant::Antichess && lock.board[motion.motion.to].is_king() {
               alpha = i32::MAX - 1;
-              best = motion;
+              self.mtm = motion.motion;
             }
             lock.make_motion(&motion.motion, false);
             SearchTree::leaf(&mut self.driver.tree, lock.turn);
@@ -421,22 +631,18 @@ impl Searcher {
             lock = state.lock().unwrap();
             lock.unmake_last(true);
             drop(lock);
-            self.driver.time_remaining = self.time_limit.checked_sub(time::Instant::now().duration_since(self.driver.time_start)).unwrap_or(time::Duration::ZERO);
-            if self.driver.time_remaining.is_zero() {
+            if self.out_of_time() {
                 break;
             }
             
             if val > alpha {
-                best = motion;
                 self.mtm = motion.motion;
                 SearchTree::highlight_last(&mut self.driver.tree);
                 SearchTree::back(&mut self.driver.tree, false);
                 if val >= beta {
-                    self.tt.insert(motion.key, motion);
                     return beta;
                 }
                 alpha = val;
-                self.tt.insert(motion.key, motion);
 
             } else {
                 SearchTree::back(&mut self.driver.tree, false);
@@ -448,7 +654,6 @@ impl Searcher {
             
             i += 1;
         }
-        self.tt.insert(best.key, best);
         
         return alpha;
     }
@@ -456,20 +661,37 @@ impl Searcher {
     fn iterate(&mut self, state: Arc<Mutex<State>>) -> Motion {
         
         
-        let lock = state.lock().unwrap();
+        let mut lock = state.lock().unwrap();
         
-        let moves = lock.moves.parity_vect(lock.turn);
+        let mut moves = lock.moves.parity_vect(lock.turn);
+        // Keep to the moves the bitbases or tables rank best, and let the search pick between them
+        let known = match bitbase::best_moves(&mut lock) {
+            Some(best) => Some(best),
+            None => self.tablebase.as_ref().and_then(|tb| tb.tables.best_root_moves(&mut lock))
+        };
+        if let Some(best) = known {
+            self.driver.tb_hits += 1;
+            moves = best;
+            self.driver.root_moves = moves.clone();
+        }
         let move_count = moves.len();
         drop(lock);
+        // Never hand back last turn's move, even if every move here loses
+        if let Some(first) = moves.first() {
+            self.mtm = *first;
+        }
         
         self.driver.depth = 1;
         let mut val = self.sroot(state.clone(), self.driver.depth, i32::MIN + 1, i32::MAX - 1);
-        for i in 2..=Self::ABSOLUTELY_MAX_DEPTH {
-            self.driver.time_remaining = self.time_limit.checked_sub(time::Instant::now().duration_since(self.driver.time_start)).unwrap_or(time::Duration::ZERO);
-            if self.driver.time_remaining.is_zero() { break };
+        if !self.out_of_time() { self.report(&state, val) };
+        for i in 2..=self.depth_limit.unwrap_or(Self::ABSOLUTELY_MAX_DEPTH).min(Self::ABSOLUTELY_MAX_DEPTH) {
+            if self.out_of_time() { break };
             if move_count == 1 && self.driver.depth > 4 { break };
+            if self.skips(i) { continue };
             self.driver.depth = i;
             val = self.widen(state.clone(), val);
+            // A depth cut short by the clock hasn't finished, so it has nothing to report
+            if !self.out_of_time() { self.report(&state, val) };
         }
         return self.mtm;
     }
@@ -1,6 +1,7 @@
 use core::f32;
 use std::ops::IndexMut;
 
+#[cfg(feature = "gui")]
 use eframe::egui;
 
 
@@ -29,10 +30,13 @@ impl Clone for SearchTree {
     }
 }
 
+#[cfg(feature = "gui")]
 fn remap(v: f32, imin: f32, imax: f32, omin: f32, omax: f32) -> f32 {
     return omin + (v - imin) * (omax - omin) / (imax - imin);
 }
+#[cfg(feature = "gui")]
 pub const ROOT_C: f32 = 3.0;
+#[cfg(feature = "gui")]
 pub const ROOT_S: f32 = 0.25;
 impl SearchTree {
     pub fn new(value: Parity) -> Self {
@@ -84,7 +88,9 @@ impl SearchTree {
             target.children.remove(last.unwrap_or(0));
         }
     }
-
+}
+#[cfg(feature = "gui")]
+impl SearchTree {
     fn remap_xs(root: &mut SearchTree, imin: f32, imax: f32, omin: f32, omax: f32) {
         root.__x = remap(root.__x, imin, imax, omin, omax);
         for child in &mut root.children {
@@ -2,12 +2,13 @@ use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
 use crate::lib::{
-    boardarray::BoardArray, chessbyte::ChessByte, mask::Mask, maskset::MaskSet, piece::Parity,
+    boardarray::{BoardArray, MoveType, Undo}, chessbyte::ChessByte, crazyhouse::{self, Pocket}, fen, mask::Mask, maskset::MaskSet, piece::{Parity, PieceByte},
     zobrist::Zobrist,
     motion::Motion
 };
 
 use super::motion::MotionSet;
+use super::variant::Variant;
 use super::searchtree::SearchTree;
 
 pub struct RetainedStateInfo {
@@ -18,12 +19,22 @@ pub struct RetainedStateInfo {
     pub maskset: MaskSet,
     pub halfmove_clock: u64,
     pub fullmove_number: u64,
+    // The home square of the rook for each castling bit, [k, q, K, Q]. Only Chess960 moves them off the corners.
+    pub castle_rooks: [usize; 4],
+    // Checks given so far by [white, black], only counted in Three-check
+    pub checks: [u8; 2],
+    // Pieces in hand for [white, black], only filled in Crazyhouse
+    pub pockets: [Pocket; 2],
 }
+pub const STANDARD_CASTLE_ROOKS: [usize; 4] = [7, 0, 63, 56];
 impl Default for RetainedStateInfo {
     fn default() -> Self { Self { 
         king_indices: [65usize; 2],
         enpassant_mask: Mask::default(),
         allowed_castles: 0u8,
+        castle_rooks: STANDARD_CASTLE_ROOKS,
+        checks: [0u8; 2],
+        pockets: [[0u8; 5]; 2],
         zkey: 0u64,
         maskset: MaskSet::default(),
         fullmove_number: 0u64,
@@ -39,13 +50,17 @@ impl Clone for RetainedStateInfo {
             king_indices: self.king_indices.clone(),
             maskset: self.maskset,
             halfmove_clock: self.halfmove_clock.clone(),
-            fullmove_number: self.fullmove_number.clone()
+            fullmove_number: self.fullmove_number.clone(),
+            castle_rooks: self.castle_rooks,
+            checks: self.checks,
+            pockets: self.pockets
         };
     }
 }
 pub struct PartialState {
     pub board: [u8; 64],
     pub allowed_castles: u8,
+    pub castle_rooks: [usize; 4],
     pub enpassant_mask: Mask,
     pub maskset: MaskSet,
     pub king_indices: [usize; 2]
@@ -62,8 +77,14 @@ pub struct State {
     pub tree_root: Option<Arc<Mutex<SearchTree>>>,
     pub num_cached: usize,
     pub num_analyzed: usize,
-    held_info: Vec<RetainedStateInfo>,
-    held_boards: Vec<[u8; 64]>
+    pub chess960: bool,
+    pub variant: Variant,
+    held_undos: Vec<Undo>,
+    held_motions: Vec<Motion>,
+    // The motions of the position before each held motion, moved back into place on unmake
+    held_moves: Vec<MotionSet>,
+    // The position before the first held motion, kept for initial_fen
+    held_start: Option<([u8; 64], RetainedStateInfo, Parity)>
 }
 pub const ARRAY_REPEAT_VALUE: Vec<Motion> = Vec::new();
 impl Default for State {
@@ -74,11 +95,15 @@ impl Default for State {
             board: [0u8; 64],
             zobrist: Arc::new(Mutex::new(Zobrist::init())),
             tree_root: None,
-            held_info: Vec::new(),
+            held_undos: Vec::new(),
             info: RetainedStateInfo::default(),
-            held_boards: Vec::new(),
+            held_motions: Vec::new(),
+            held_moves: Vec::new(),
+            held_start: None,
             num_analyzed: 0,
             num_cached: 0,
+            chess960: false,
+            variant: Variant::default(),
             cached_moves: HashMap::default()
         }
     }
@@ -89,36 +114,58 @@ impl State {
     pub fn get_piece_at_index(&self, index: usize) -> u8 { return if index < 64 { self.board[index] } else { 0u8 } }
 
     pub fn make_motion(&mut self, motion: &Motion, debugging_enabled: bool) {
-        let held = self.board.make(motion.from, motion.to, self.zobrist.clone(), &mut self.info, debugging_enabled);
-        self.held_boards.push(held.0);
-        self.held_info.push(held.1);
+        if self.held_undos.is_empty() {
+            self.held_start = Some((self.board, self.info.clone(), self.turn));
+        }
+        let mut undo = if motion.is_drop() {
+            self.board.make_drop(motion, self.turn, self.zobrist.clone(), &mut self.info)
+        } else {
+            self.board.make(motion, self.zobrist.clone(), &mut self.info, debugging_enabled)
+        };
+        if self.variant == Variant::Crazyhouse && undo.captured.is_piece() && undo.kind != MoveType::CASTLE {
+            let zrist = self.zobrist.lock().unwrap();
+            let side = if self.turn == Parity::WHITE { 0 } else { 1 };
+            let slot = crazyhouse::pocket_slot(crazyhouse::pocketed(undo.captured)).unwrap();
+            let held = self.info.pockets[side][slot];
+            self.info.zkey ^= zrist.pocket(side, slot, held) ^ zrist.pocket(side, slot, held + 1);
+            self.info.pockets[side][slot] = held + 1;
+        }
+        if self.variant == Variant::Atomic && undo.captured.is_piece() && undo.kind != MoveType::CASTLE {
+            self.board.explode(&mut undo, self.zobrist.clone(), &mut self.info);
+        }
+        if self.variant == Variant::Antichess {
+            // Kings can be taken or promoted to, so make's bookkeeping of them can't be trusted
+            self.info.king_indices = self.info.maskset.kings();
+        }
+        self.held_undos.push(undo);
+        self.held_motions.push(*motion);
+        // Hydrate replaces the motions anyway, so they can be taken rather than copied
+        self.held_moves.push(std::mem::take(&mut self.moves));
         self.turn = !self.turn;
+        if self.variant == Variant::ThreeCheck {
+            let king = self.info.maskset.king(self.turn);
+            if king != 65 && self.info.maskset.attackers_of(king, self.turn).any() {
+                let zrist = self.zobrist.lock().unwrap();
+                let side = if self.turn == Parity::WHITE { 1 } else { 0 };
+                self.info.zkey ^= zrist.checks(side, self.info.checks[side]) ^ zrist.checks(side, self.info.checks[side] + 1);
+                self.info.checks[side] += 1;
+            }
+        }
         self.hydrate(debugging_enabled);
     }
     pub fn make_move(&mut self, from: usize, to: &Mask, debugging_enabled: bool) {
-        let held = self.board.make(from, to.as_index(), self.zobrist.clone(), &mut self.info, debugging_enabled);
-        self.held_boards.push(held.0);
-        self.held_info.push(held.1);
-        self.turn = !self.turn;
-        self.hydrate(debugging_enabled);
+        let motion = Motion { from, to: to.as_index(), promotion: PieceByte::NONE };
+        self.make_motion(&motion, debugging_enabled);
     }
+    // Restores the position from the last make's undo record, and its motions from the stack beside it
     pub fn unmake_last(&mut self, do_turn_switch: bool) {
-        if let Some(argsinfo) = self.held_info.pop() {
-            if let Some(argsboard) = self.held_boards.pop() {
-                let zrist = self.zobrist.lock().unwrap();
-                if do_turn_switch { self.turn = !self.turn };
-                self.board.unmake(&argsboard, &argsinfo, &mut self.info);
-                let cached_option = zrist.pull(self.info.zkey);
-                drop(zrist);
-                if let Some(cached) = cached_option {
-                    self.moves = cached.1;
-                } else {
-                    self.hydrate(false);
-                }
-                return;
-            }
-        }
-        panic!("No held state when expected!");
+        let (Some(undo), Some(moves)) = (self.held_undos.pop(), self.held_moves.pop()) else {
+            panic!("No held state when expected!");
+        };
+        self.held_motions.pop();
+        if do_turn_switch { self.turn = !self.turn };
+        self.board.unmake(&undo, &mut self.info);
+        self.moves = moves;
     }
     pub fn set_sorted_motions(&mut self, sorted: Vec<Motion>) -> () {
         if self.turn == Parity::WHITE {
@@ -128,6 +175,52 @@ impl State {
         }
         self.cached_moves.insert(self.info.zkey, self.moves.clone());
     }
+    // How many times the current position has appeared, counting this occurrence.
+    // Nothing before the last irreversible move can repeat, so the search stops at the halfmove clock.
+    pub fn repetitions(&self) -> usize {
+        let reversible = (self.info.halfmove_clock as usize).min(self.held_undos.len());
+        return 1 + self.held_undos.iter().rev().take(reversible).filter(|held| held.zkey == self.info.zkey).count();
+    }
+    // The key of every position since the state was set up, oldest first and ending with the current one
+    pub fn position_keys(&self) -> Vec<u64> {
+        return self.held_undos.iter().map(|held| held.zkey).chain([self.info.zkey]).collect();
+    }
+    // Every motion made since the state was set up, oldest first
+    pub fn played_motions(&self) -> &[Motion] {
+        return &self.held_motions;
+    }
+    // The FEN of the position before any of the played motions were made
+    pub fn initial_fen(&self) -> String {
+        return match &self.held_start {
+            Some((board, info, turn)) if !self.held_undos.is_empty() => fen::write_fen(board, *turn, info, self.variant, false),
+            _ => self.to_fen()
+        };
+    }
+    // A copy for another thread to search on. It gets its own zobrist tables, which hold the evaluation cache,
+    // so the two never wait on each other's lock. The keys come from a fixed seed and still match.
+    // The move cache starts empty too, and fills as the copy searches.
+    pub fn thread_copy(&self) -> State {
+        return State {
+            board: self.board,
+            cached_moves: HashMap::new(),
+            moves: self.moves.clone(),
+            turn: self.turn,
+            zobrist: Arc::new(Mutex::new(Zobrist::init())),
+            info: self.info.clone(),
+            tree_root: None,
+            num_cached: self.num_cached,
+            num_analyzed: self.num_analyzed,
+            chess960: self.chess960,
+            variant: self.variant,
+            held_undos: self.held_undos.clone(),
+            held_motions: self.held_motions.clone(),
+            held_moves: self.held_moves.clone(),
+            held_start: self.held_start.clone()
+        };
+    }
+    pub fn pocket(&self, parity: Parity) -> Pocket {
+        return self.info.pockets[if parity == Parity::WHITE { 0 } else { 1 }];
+    }
     pub fn get_king(&self, parity: Parity) -> usize {
         return self.info.king_indices[if parity == Parity::WHITE { 0 } else { 1 }];
     }
@@ -136,16 +229,8 @@ impl State {
         self.info.zkey = zrist.kof_board(self);
         self.info.maskset = MaskSet::from_board(&self.board);
 
-        for i in 0..64 {
-            if self.board[i].is_king() {
-                if self.board[i].is_white() {
-                    self.info.king_indices[0] = i;
-                } else if self.board[i].is_black() {
-                    self.info.king_indices[1] = i;
-                }
-            }
-        }
-        if self.info.king_indices[0] == 65 || self.info.king_indices[1] == 65 {
+        self.info.king_indices = self.info.maskset.kings();
+        if self.variant != Variant::Antichess && (self.info.king_indices[0] == 65 || self.info.king_indices[1] == 65) {
             panic!("Could not find kings in board! Attempted white index: {}, attempted black index: {}", self.info.king_indices[0], self.info.king_indices[1]);
         }
         drop(zrist);
@@ -153,6 +238,7 @@ impl State {
     }
     pub fn partial_flipped(&self) -> PartialState {
         let flip = self.board.flipped();
+        let maskset = MaskSet::from_board(&flip);
         return PartialState {
             board: flip,
             enpassant_mask: self.info.enpassant_mask.flipped(),
@@ -161,10 +247,17 @@ impl State {
                 let high = self.info.allowed_castles & 0b0000_1100;
                 (low << 2) | (high >> 2)
             },
-            maskset: MaskSet::from_board(&flip),
-            king_indices: flip.get_kings()
+            castle_rooks: {
+                let r = self.info.castle_rooks;
+                [r[2] ^ 56, r[3] ^ 56, r[0] ^ 56, r[1] ^ 56]
+            },
+            maskset: maskset,
+            king_indices: maskset.kings()
         };
     }
+    fn generate_motions(&self) -> MotionSet {
+        return self.variant.motions(&self.board, &self.info.maskset, &self.info.enpassant_mask, Some((self.info.allowed_castles, self.info.castle_rooks)), &self.info.pockets);
+    }
     pub fn hydrate(&mut self, debug_log: bool){
         if debug_log {
             // pretty_print_board("Hydrating board", &self.board);
@@ -173,7 +266,7 @@ impl State {
         if let Some(cached) = self.cached_moves.get(&self.info.zkey) {
             self.moves = cached.clone();
         } else {
-            self.moves = self.board.get_motions(&self.info.maskset, &self.info.enpassant_mask, Some(self.info.allowed_castles));
+            self.moves = self.generate_motions();
             self.cached_moves.insert(self.info.zkey, self.moves.clone());
         }
         if debug_log {
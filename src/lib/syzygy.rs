@@ -0,0 +1,961 @@
+use std::{collections::HashMap, fs::File, ops::Neg, path::{Path, PathBuf}, sync::{Arc, Mutex, OnceLock}};
+
+use memmap2::Mmap;
+
+use crate::lib::{
+    chessbyte::ChessByte,
+    motion::Motion,
+    piece::{Parity, PieceByte},
+    state::State,
+    variant::Variant
+};
+
+// Syzygy WDL (.rtbw) and DTZ (.rtbz) tables, read the way the reference prober by Ronald de Man reads them.
+// The files number squares from a1 = 0 to h8 = 63, which is our index with the rank flipped (index ^ 56),
+// and pieces as 1-6 for white P, N, B, R, Q, K and 9-14 for black.
+
+const MAX_PIECES: usize = 7;
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+// Per-table flags
+const STM: u8 = 1;
+const MAPPED: u8 = 2;
+const WIN_PLIES: u8 = 4;
+const LOSS_PLIES: u8 = 8;
+const WIDE: u8 = 16;
+const SINGLE_VALUE: u8 = 128;
+// Rank given to a certain win at the root, as in the reference prober
+const MAX_DTZ: i32 = 1 << 18;
+// Letters in the order table names list them
+const NAME_ORDER: [(char, usize); 6] = [('K', 5), ('Q', 4), ('R', 3), ('B', 2), ('N', 1), ('P', 0)];
+
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub enum Wdl {
+    Loss = -2,
+    // Lost, but the fifty-move rule saves it
+    BlessedLoss = -1,
+    Draw = 0,
+    // Won, but not within the fifty-move rule
+    CursedWin = 1,
+    Win = 2
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Wdl {
+        return match value {
+            i32::MIN..=-2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            _ => Wdl::Win
+        };
+    }
+    fn sign(self) -> i32 {
+        return (self as i32).signum();
+    }
+}
+
+impl Neg for Wdl {
+    type Output = Wdl;
+    fn neg(self) -> Wdl { Wdl::from_value(-(self as i32)) }
+}
+
+struct Maps {
+    binomial: [[u64; 64]; MAX_PIECES],
+    map_a1d1d4: [usize; 64],
+    map_b1h1h7: [usize; 64],
+    map_kk: [[u64; 64]; 10],
+    map_pawns: [usize; 64],
+    lead_pawn_idx: [[u64; 64]; 6],
+    lead_pawns_size: [[u64; 4]; 6]
+}
+
+static MAPS: OnceLock<Maps> = OnceLock::new();
+
+#[inline(always)]
+fn off_a1h8(square: usize) -> i32 {
+    return (square / 8) as i32 - (square % 8) as i32;
+}
+
+fn build_maps() -> Maps {
+    let mut m = Maps {
+        binomial: [[0; 64]; MAX_PIECES],
+        map_a1d1d4: [0; 64],
+        map_b1h1h7: [0; 64],
+        map_kk: [[0; 64]; 10],
+        map_pawns: [0; 64],
+        lead_pawn_idx: [[0; 64]; 6],
+        lead_pawns_size: [[0; 4]; 6]
+    };
+    let mut code = 0;
+    for s in 0..64 {
+        if off_a1h8(s) < 0 {
+            m.map_b1h1h7[s] = code;
+            code += 1;
+        }
+    }
+    // The a1-d1-d4 triangle, with the diagonal squares last
+    let mut diagonal = Vec::new();
+    code = 0;
+    for s in 0..28 {
+        if off_a1h8(s) < 0 && s % 8 <= 3 {
+            m.map_a1d1d4[s] = code;
+            code += 1;
+        } else if off_a1h8(s) == 0 && s % 8 <= 3 {
+            diagonal.push(s);
+        }
+    }
+    for s in diagonal {
+        m.map_a1d1d4[s] = code;
+        code += 1;
+    }
+    // The 462 ways to place two kings with the first in the triangle, both-on-the-diagonal last
+    let mut both_on_diagonal = Vec::new();
+    let mut code = 0u64;
+    for idx in 0..10 {
+        for s1 in 0..28 {
+            if m.map_a1d1d4[s1] != idx || (idx == 0 && s1 != 1) {
+                continue;
+            }
+            for s2 in 0..64 {
+                let touching = (s1 / 8).abs_diff(s2 / 8) <= 1 && (s1 % 8).abs_diff(s2 % 8) <= 1;
+                if touching || (off_a1h8(s1) == 0 && off_a1h8(s2) > 0) {
+                    continue;
+                }
+                if off_a1h8(s1) == 0 && off_a1h8(s2) == 0 {
+                    both_on_diagonal.push((idx, s2));
+                } else {
+                    m.map_kk[idx][s2] = code;
+                    code += 1;
+                }
+            }
+        }
+    }
+    for (idx, s2) in both_on_diagonal {
+        m.map_kk[idx][s2] = code;
+        code += 1;
+    }
+    m.binomial[0][0] = 1;
+    for n in 1..64 {
+        for k in 0..MAX_PIECES.min(n + 1) {
+            m.binomial[k][n] = if k > 0 { m.binomial[k - 1][n - 1] } else { 0 } + if k < n { m.binomial[k][n - 1] } else { 0 };
+        }
+    }
+    // Pawn squares a2-h7 from the edges in, so the leading pawn is the one with the highest value
+    let mut available = 47;
+    for lead in 1..6 {
+        for file in 0..4 {
+            let mut idx = 0;
+            for rank in 1..7 {
+                let s = rank * 8 + file;
+                if lead == 1 {
+                    m.map_pawns[s] = available;
+                    m.map_pawns[s ^ 7] = available - 1;
+                    available = available.saturating_sub(2);
+                }
+                m.lead_pawn_idx[lead][s] = idx;
+                idx += m.binomial[lead - 1][m.map_pawns[s]];
+            }
+            m.lead_pawns_size[lead][file] = idx;
+        }
+    }
+    return m;
+}
+
+fn maps() -> &'static Maps {
+    return MAPS.get_or_init(build_maps);
+}
+
+// A piece as the tables write it
+fn table_piece(byte: u8) -> u8 {
+    let kind = match byte.get_piece() {
+        PieceByte::PAWN => 1,
+        PieceByte::KNIGHT => 2,
+        PieceByte::BISHOP => 3,
+        PieceByte::ROOK => 4,
+        PieceByte::QUEEN => 5,
+        _ => 6
+    };
+    return kind | if byte.is_white() { 0 } else { 8 };
+}
+
+// [white, black][pawn, knight, bishop, rook, queen, king]
+type Material = [[usize; 6]; 2];
+
+fn material(board: &[u8; 64]) -> Material {
+    let mut counts = [[0; 6]; 2];
+    for byte in board.iter().filter(|b| b.is_piece()) {
+        counts[if byte.is_white() { 0 } else { 1 }][(table_piece(*byte) & 7) as usize - 1] += 1;
+    }
+    return counts;
+}
+
+// Like KRvK, with the first side's pieces before the v
+fn material_name(counts: &Material, first: usize) -> String {
+    let mut name = String::new();
+    for side in [first, 1 - first] {
+        for (letter, kind) in NAME_ORDER {
+            for _ in 0..counts[side][kind] {
+                name.push(letter);
+            }
+        }
+        if side == first {
+            name.push('v');
+        }
+    }
+    return name;
+}
+
+fn parse_name(name: &str) -> Option<Material> {
+    let (white, black) = name.split_once('v')?;
+    let mut counts = [[0; 6]; 2];
+    for (side, pieces) in [white, black].iter().enumerate() {
+        for c in pieces.chars() {
+            let (_, kind) = NAME_ORDER.iter().find(|(letter, _)| *letter == c)?;
+            counts[side][*kind] += 1;
+        }
+    }
+    return Some(counts);
+}
+
+#[derive(Clone, Default)]
+struct PairsData {
+    flags: u8,
+    min_sym_len: u8,
+    block_size: u64,
+    span: u64,
+    num_blocks: u64,
+    block_length_size: u64,
+    sparse_index_size: u64,
+    // Offsets into the file
+    lowest_sym: usize,
+    btree: usize,
+    sparse_index: usize,
+    block_length: usize,
+    data: usize,
+    base64: Vec<u64>,
+    // How many values, less one, each symbol expands to
+    symlen: Vec<u8>,
+    pieces: [u8; MAX_PIECES],
+    group_idx: [u64; MAX_PIECES + 1],
+    group_len: [usize; MAX_PIECES + 1],
+    // For DTZ tables, where the value maps for win, loss, cursed win and blessed loss start
+    map_idx: [usize; 4]
+}
+
+struct Table {
+    bytes: Mmap,
+    // The name with the stronger side first, as the file is called
+    name: String,
+    dtz: bool,
+    // Both sides have the same pieces
+    symmetric: bool,
+    has_pawns: bool,
+    // Pawns of the leading side first
+    pawn_count: [usize; 2],
+    piece_count: usize,
+    has_unique_pieces: bool,
+    // [side to move][leading pawn's file]
+    pairs: Vec<Vec<PairsData>>,
+    map: usize
+}
+
+enum Lookup {
+    Value(i32),
+    // DTZ tables only hold one side to move, and this isn't it
+    ChangeStm
+}
+
+impl Table {
+    fn u8_at(&self, at: usize) -> u8 {
+        return self.bytes.get(at).copied().unwrap_or(0);
+    }
+    fn le_u16(&self, at: usize) -> u16 {
+        return u16::from_le_bytes([self.u8_at(at), self.u8_at(at + 1)]);
+    }
+    fn le_u32(&self, at: usize) -> u32 {
+        return u32::from_le_bytes([self.u8_at(at), self.u8_at(at + 1), self.u8_at(at + 2), self.u8_at(at + 3)]);
+    }
+    fn be_u32(&self, at: usize) -> u32 {
+        return u32::from_be_bytes([self.u8_at(at), self.u8_at(at + 1), self.u8_at(at + 2), self.u8_at(at + 3)]);
+    }
+    fn be_u64(&self, at: usize) -> u64 {
+        return ((self.be_u32(at) as u64) << 32) | self.be_u32(at + 4) as u64;
+    }
+    // A pair tree entry packs two 12-bit symbols into three bytes
+    fn left(&self, d: &PairsData, sym: usize) -> usize {
+        let at = d.btree + 3 * sym;
+        return (((self.u8_at(at + 1) & 0xF) as usize) << 8) | self.u8_at(at) as usize;
+    }
+    fn right(&self, d: &PairsData, sym: usize) -> usize {
+        let at = d.btree + 3 * sym;
+        return ((self.u8_at(at + 2) as usize) << 4) | (self.u8_at(at + 1) >> 4) as usize;
+    }
+
+    fn open(path: &Path, name: &str, dtz: bool) -> Option<Table> {
+        let file = File::open(path).ok()?;
+        // The tables are never written while we run, which is what mapping them needs
+        let bytes = unsafe { Mmap::map(&file) }.ok()?;
+        if bytes.get(0..4)? != if dtz { DTZ_MAGIC } else { WDL_MAGIC } {
+            return None;
+        }
+        let counts = parse_name(name)?;
+        let pawns = [counts[0][0], counts[1][0]];
+        // The side with fewer pawns leads, as that compresses better
+        let white_leads = pawns[1] == 0 || (pawns[0] > 0 && pawns[1] >= pawns[0]);
+        let mut table = Table {
+            bytes,
+            name: name.to_string(),
+            dtz,
+            symmetric: material_name(&counts, 0) == material_name(&counts, 1),
+            has_pawns: pawns[0] + pawns[1] > 0,
+            pawn_count: if white_leads { pawns } else { [pawns[1], pawns[0]] },
+            piece_count: counts.iter().flatten().sum(),
+            has_unique_pieces: counts.iter().any(|side| side[0..5].contains(&1)),
+            pairs: Vec::new(),
+            map: 0
+        };
+        if table.piece_count > MAX_PIECES || (table.u8_at(4) & 2 != 0) != table.has_pawns {
+            return None;
+        }
+        table.setup()?;
+        return Some(table);
+    }
+
+    fn sides(&self) -> usize {
+        return if !self.dtz && !self.symmetric { 2 } else { 1 };
+    }
+    fn files(&self) -> usize {
+        return if self.has_pawns { 4 } else { 1 };
+    }
+    fn get(&self, stm: usize, file: usize) -> &PairsData {
+        return &self.pairs[stm % self.sides()][if self.has_pawns { file } else { 0 }];
+    }
+
+    fn setup(&mut self) -> Option<()> {
+        let (sides, files) = (self.sides(), self.files());
+        let both_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut pairs = vec![vec![PairsData::default(); files]; sides];
+        let mut data = 5;
+        for f in 0..files {
+            let (first, second) = (self.u8_at(data), if both_pawns { self.u8_at(data + 1) } else { 0xFF });
+            let order = [[first & 0xF, second & 0xF], [first >> 4, second >> 4]];
+            data += 1 + both_pawns as usize;
+            for k in 0..self.piece_count {
+                for (i, side) in pairs.iter_mut().enumerate() {
+                    side[f].pieces[k] = if i == 1 { self.u8_at(data) >> 4 } else { self.u8_at(data) & 0xF };
+                }
+                data += 1;
+            }
+            for (i, side) in pairs.iter_mut().enumerate() {
+                self.set_groups(&mut side[f], order[i], f)?;
+            }
+        }
+        data += data & 1;
+        for f in 0..files {
+            for side in pairs.iter_mut() {
+                data = self.set_sizes(&mut side[f], data)?;
+            }
+        }
+        if self.dtz {
+            self.map = data;
+            for d in pairs[0].iter_mut() {
+                if d.flags & MAPPED == 0 {
+                    continue;
+                }
+                for i in 0..4 {
+                    if d.flags & WIDE != 0 {
+                        data += data & 1;
+                        // Counted in 16-bit words from the start of the map, past each list's length
+                        d.map_idx[i] = (data - self.map) / 2 + 1;
+                        data += 2 * self.le_u16(data) as usize + 2;
+                    } else {
+                        d.map_idx[i] = data - self.map + 1;
+                        data += self.u8_at(data) as usize + 1;
+                    }
+                }
+            }
+            data += data & 1;
+        }
+        for f in 0..files {
+            for side in pairs.iter_mut() {
+                side[f].sparse_index = data;
+                data += side[f].sparse_index_size as usize * 6;
+            }
+        }
+        for f in 0..files {
+            for side in pairs.iter_mut() {
+                side[f].block_length = data;
+                data += side[f].block_length_size as usize * 2;
+            }
+        }
+        // Tables holding a single value end before the padding their empty blocks would start after
+        let mut end = data;
+        for f in 0..files {
+            for side in pairs.iter_mut() {
+                data = (data + 0x3F) & !0x3F;
+                side[f].data = data;
+                data += (side[f].num_blocks * side[f].block_size) as usize;
+                if side[f].num_blocks > 0 {
+                    end = data;
+                }
+            }
+        }
+        if end > self.bytes.len() {
+            return None;
+        }
+        self.pairs = pairs;
+        return Some(());
+    }
+
+    // Splits the pieces into groups that are encoded together, and works out each group's multiplier in the index
+    fn set_groups(&self, d: &mut PairsData, order: [u8; 2], file: usize) -> Option<()> {
+        let m = maps();
+        let mut n = 0;
+        let mut first_len: i32 = if self.has_pawns { 0 } else if self.has_unique_pieces { 3 } else { 2 };
+        d.group_len[0] = 1;
+        for i in 1..self.piece_count {
+            first_len -= 1;
+            if first_len > 0 || d.pieces[i] == d.pieces[i - 1] {
+                d.group_len[n] += 1;
+            } else {
+                n += 1;
+                d.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        d.group_len[n] = 0;
+
+        let both_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = if both_pawns { 2 } else { 1 };
+        let mut free_squares = 64 - d.group_len[0] - if both_pawns { d.group_len[1] } else { 0 };
+        let mut idx = 1u64;
+        let mut k = 0;
+        while next < n || k == order[0] as usize || k == order[1] as usize {
+            if k == order[0] as usize {
+                d.group_idx[0] = idx;
+                idx *= if self.has_pawns {
+                    *m.lead_pawns_size.get(d.group_len[0])?.get(file)?
+                } else if self.has_unique_pieces { 31332 } else { 462 };
+            } else if k == order[1] as usize {
+                d.group_idx[1] = idx;
+                idx *= m.binomial.get(d.group_len[1])?[48usize.checked_sub(d.group_len[0])?];
+            } else {
+                d.group_idx[next] = idx;
+                idx *= m.binomial.get(d.group_len[next])?[free_squares];
+                free_squares = free_squares.checked_sub(d.group_len[next])?;
+                next += 1;
+            }
+            k += 1;
+            if k > 16 {
+                return None;
+            }
+        }
+        d.group_idx[n] = idx;
+        return Some(());
+    }
+
+    // Reads the sizes of a table's Huffman code and pair tree
+    fn set_sizes(&self, d: &mut PairsData, mut data: usize) -> Option<usize> {
+        d.flags = self.u8_at(data);
+        data += 1;
+        if d.flags & SINGLE_VALUE != 0 {
+            // Every position has the same value, kept where the code length would be
+            d.min_sym_len = self.u8_at(data);
+            return Some(data + 1);
+        }
+        let groups = d.group_len.iter().position(|len| *len == 0)?;
+        let tb_size = d.group_idx[groups];
+        d.block_size = 1u64.checked_shl(self.u8_at(data) as u32)?;
+        d.span = 1u64.checked_shl(self.u8_at(data + 1) as u32)?;
+        d.sparse_index_size = tb_size.div_ceil(d.span);
+        let padding = self.u8_at(data + 2) as u64;
+        d.num_blocks = self.le_u32(data + 3) as u64;
+        d.block_length_size = d.num_blocks + padding;
+        let max_sym_len = self.u8_at(data + 7);
+        d.min_sym_len = self.u8_at(data + 8);
+        data += 9;
+        if max_sym_len < d.min_sym_len || d.min_sym_len == 0 {
+            return None;
+        }
+        d.lowest_sym = data;
+        let lengths = (max_sym_len - d.min_sym_len + 1) as usize;
+        // Longer codes have lower values, so base64[i] is the lowest code of length min + i, padded to 64 bits
+        d.base64 = vec![0u64; lengths];
+        for i in (0..lengths - 1).rev() {
+            let lowest = self.le_u16(d.lowest_sym + 2 * i) as u64;
+            let next_lowest = self.le_u16(d.lowest_sym + 2 * (i + 1)) as u64;
+            d.base64[i] = (d.base64[i + 1] + lowest).wrapping_sub(next_lowest) / 2;
+        }
+        for (i, base) in d.base64.iter_mut().enumerate() {
+            *base = base.checked_shl(64 - i as u32 - d.min_sym_len as u32).unwrap_or(0);
+        }
+        data += lengths * 2;
+        let symbols = self.le_u16(data) as usize;
+        data += 2;
+        d.btree = data;
+        d.symlen = vec![0u8; symbols];
+        let mut visited = vec![false; symbols];
+        for sym in 0..symbols {
+            if !visited[sym] {
+                self.set_symlen(d, sym, &mut visited);
+            }
+        }
+        return Some(data + symbols * 3 + (symbols & 1));
+    }
+
+    fn set_symlen(&self, d: &mut PairsData, sym: usize, visited: &mut Vec<bool>) -> () {
+        visited[sym] = true;
+        let right = self.right(d, sym);
+        if right == 0xFFF {
+            d.symlen[sym] = 0;
+            return;
+        }
+        let left = self.left(d, sym);
+        for child in [left, right] {
+            if child < visited.len() && !visited[child] {
+                self.set_symlen(d, child, visited);
+            }
+        }
+        let length = |s: usize| d.symlen.get(s).copied().unwrap_or(0);
+        d.symlen[sym] = length(left).wrapping_add(length(right)).wrapping_add(1);
+    }
+
+    // The value stored at the index, found by walking to its block and decoding symbols until we reach it
+    fn decompress(&self, d: &PairsData, idx: u64) -> i32 {
+        if d.flags & SINGLE_VALUE != 0 {
+            return d.min_sym_len as i32;
+        }
+        let symlen = |s: usize| d.symlen.get(s).copied().unwrap_or(0) as i64;
+        let k = idx / d.span;
+        let mut block = self.le_u32(d.sparse_index + 6 * k as usize) as i64;
+        let mut offset = self.le_u16(d.sparse_index + 6 * k as usize + 4) as i64;
+        offset += (idx % d.span) as i64 - (d.span / 2) as i64;
+        let block_length = |b: i64| self.le_u16(d.block_length + 2 * b.max(0) as usize) as i64;
+        while offset < 0 && block > 0 {
+            block -= 1;
+            offset += block_length(block) + 1;
+        }
+        while offset > block_length(block) && (block as u64) < d.block_length_size {
+            offset -= block_length(block) + 1;
+            block += 1;
+        }
+
+        let mut ptr = d.data + block as usize * d.block_size as usize;
+        let mut buf = self.be_u64(ptr);
+        ptr += 8;
+        let mut buf_size = 64;
+        let min = d.min_sym_len as usize;
+        let mut sym;
+        loop {
+            let mut len = 0;
+            while len + 1 < d.base64.len() && buf < d.base64[len] {
+                len += 1;
+            }
+            sym = ((buf - d.base64[len]) >> (64 - len - min)) as usize;
+            sym += self.le_u16(d.lowest_sym + 2 * len) as usize;
+            if offset < symlen(sym) + 1 {
+                break;
+            }
+            offset -= symlen(sym) + 1;
+            len += min;
+            buf = buf.checked_shl(len as u32).unwrap_or(0);
+            buf_size -= len as i32;
+            if buf_size <= 32 {
+                buf_size += 32;
+                buf |= (self.be_u32(ptr) as u64) << (64 - buf_size);
+                ptr += 4;
+            }
+        }
+        // The symbol stands for a run of values built by pairing, so descend to the one we want
+        while symlen(sym) != 0 {
+            let left = self.left(d, sym);
+            if offset < symlen(left) + 1 {
+                sym = left;
+            } else {
+                offset -= symlen(left) + 1;
+                sym = self.right(d, sym);
+            }
+        }
+        return self.left(d, sym) as i32;
+    }
+
+    fn dtz_has_stm(&self, stm: usize, file: usize) -> bool {
+        return (self.get(0, file).flags & STM) as usize == stm || (self.symmetric && !self.has_pawns);
+    }
+
+    // DTZ tables store moves or plies, sometimes through a map. This gives plies, plus one.
+    fn map_score(&self, file: usize, value: i32, wdl: Wdl) -> i32 {
+        if !self.dtz {
+            return value - 2;
+        }
+        const WDL_MAP: [usize; 5] = [1, 3, 0, 2, 0];
+        let d = self.get(0, file);
+        let mut value = value;
+        if d.flags & MAPPED != 0 {
+            let idx = d.map_idx[WDL_MAP[(wdl as i32 + 2) as usize]];
+            value = if d.flags & WIDE != 0 {
+                self.le_u16(self.map + 2 * (idx + value as usize)) as i32
+            } else {
+                self.u8_at(self.map + idx + value as usize) as i32
+            };
+        }
+        if (wdl == Wdl::Win && d.flags & WIN_PLIES == 0) || (wdl == Wdl::Loss && d.flags & LOSS_PLIES == 0) || wdl == Wdl::CursedWin || wdl == Wdl::BlessedLoss {
+            value *= 2;
+        }
+        return value + 1;
+    }
+
+    // Turns the position into the table's index and reads it
+    fn lookup(&self, state: &State, wdl: Wdl) -> Option<Lookup> {
+        let m = maps();
+        let black_to_move = state.turn == Parity::BLACK;
+        // Tables are stored with the stronger side as white, and symmetric ones only with white to move
+        let flip = if self.symmetric { black_to_move } else { material_name(&material(&state.board), 0) != self.name };
+        let flip_color = if flip { 8 } else { 0 };
+        let flip_squares = if flip { 56 } else { 0 };
+        let stm = (flip ^ black_to_move) as usize;
+
+        let mut squares = [0usize; MAX_PIECES];
+        let mut pieces = [0u8; MAX_PIECES];
+        let mut size = 0;
+        let mut lead_pawns = 0u64;
+        let mut file = 0;
+        if self.has_pawns {
+            let lead = self.get(0, 0).pieces[0] ^ flip_color;
+            for s in 0..64 {
+                let byte = state.board[s ^ 56];
+                if byte.is_piece() && table_piece(byte) == lead {
+                    squares[size] = s ^ flip_squares;
+                    size += 1;
+                    lead_pawns |= 1 << s;
+                }
+            }
+            let leading = (0..size).max_by_key(|i| m.map_pawns[squares[*i]])?;
+            squares.swap(0, leading);
+            file = (squares[0] % 8).min(7 - squares[0] % 8);
+        }
+        let lead_count = size;
+        if self.dtz && !self.dtz_has_stm(stm, file) {
+            return Some(Lookup::ChangeStm);
+        }
+        for s in 0..64 {
+            let byte = state.board[s ^ 56];
+            if byte.is_piece() && lead_pawns & (1 << s) == 0 {
+                if size == self.piece_count {
+                    return None;
+                }
+                squares[size] = s ^ flip_squares;
+                pieces[size] = table_piece(byte) ^ flip_color;
+                size += 1;
+            }
+        }
+        if size != self.piece_count {
+            return None;
+        }
+        let d = self.get(stm, file);
+        // Put the pieces in the table's order
+        for i in lead_count..size.saturating_sub(1) {
+            if let Some(j) = (i + 1..size).find(|j| d.pieces[i] == pieces[*j]) {
+                pieces.swap(i, j);
+                squares.swap(i, j);
+            }
+        }
+        // Mirror so the first piece is on files a-d
+        if squares[0] % 8 > 3 {
+            for s in squares.iter_mut().take(size) {
+                *s ^= 7;
+            }
+        }
+
+        let mut idx;
+        if self.has_pawns {
+            idx = m.lead_pawn_idx[lead_count][squares[0]];
+            squares[1..lead_count].sort_by_key(|s| m.map_pawns[*s]);
+            for (i, square) in squares.iter().enumerate().take(lead_count).skip(1) {
+                idx += m.binomial[i][m.map_pawns[*square]];
+            }
+        } else {
+            // Without pawns the board can also be flipped top to bottom and along the a1-h8 diagonal
+            if squares[0] / 8 > 3 {
+                for s in squares.iter_mut().take(size) {
+                    *s ^= 56;
+                }
+            }
+            for i in 0..d.group_len[0] {
+                let off = off_a1h8(squares[i]);
+                if off == 0 {
+                    continue;
+                }
+                if off > 0 {
+                    for s in squares.iter_mut().take(size).skip(i) {
+                        *s = ((*s >> 3) | (*s << 3)) & 63;
+                    }
+                }
+                break;
+            }
+            if self.has_unique_pieces {
+                let (s0, s1, s2) = (squares[0], squares[1], squares[2]);
+                let adjust1 = (s1 > s0) as usize;
+                let adjust2 = (s2 > s0) as usize + (s2 > s1) as usize;
+                idx = if off_a1h8(s0) != 0 {
+                    ((m.map_a1d1d4[s0] * 63 + (s1 - adjust1)) * 62 + s2 - adjust2) as u64
+                } else if off_a1h8(s1) != 0 {
+                    ((6 * 63 + (s0 / 8) * 28 + m.map_b1h1h7[s1]) * 62 + s2 - adjust2) as u64
+                } else if off_a1h8(s2) != 0 {
+                    (6 * 63 * 62 + 4 * 28 * 62 + (s0 / 8) * 7 * 28 + (s1 / 8 - adjust1) * 28 + m.map_b1h1h7[s2]) as u64
+                } else {
+                    (6 * 63 * 62 + 4 * 28 * 62 + 4 * 7 * 28 + (s0 / 8) * 7 * 6 + (s1 / 8 - adjust1) * 6 + (s2 / 8 - adjust2)) as u64
+                };
+            } else {
+                idx = m.map_kk[m.map_a1d1d4[squares[0]]][squares[1]];
+            }
+        }
+
+        // The rest of the groups, each as a combination of the squares the earlier groups left free
+        idx *= d.group_idx[0];
+        let mut start = d.group_len[0];
+        let mut remaining_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = 1;
+        while d.group_len[next] != 0 {
+            let len = d.group_len[next];
+            squares[start..start + len].sort();
+            let mut n = 0;
+            for i in 0..len {
+                let square = squares[start + i];
+                let adjust = squares[..start].iter().filter(|s| square > **s).count();
+                n += m.binomial[i + 1][square - adjust - if remaining_pawns { 8 } else { 0 }];
+            }
+            remaining_pawns = false;
+            idx += n * d.group_idx[next];
+            start += len;
+            next += 1;
+        }
+        return Some(Lookup::Value(self.map_score(file, self.decompress(d, idx), wdl)));
+    }
+}
+
+fn is_capture(state: &State, motion: &Motion) -> bool {
+    let (mover, target) = (state.board[motion.from], state.board[motion.to]);
+    // A pawn moving sideways onto an empty square is taking en passant
+    return (target.is_piece() && target.is_white() != mover.is_white()) || (mover.is_pawn() && motion.from % 8 != motion.to % 8);
+}
+
+fn dtz_before_zeroing(wdl: Wdl) -> i32 {
+    return match wdl {
+        Wdl::Win => 1,
+        Wdl::CursedWin => 101,
+        Wdl::BlessedLoss => -101,
+        Wdl::Loss => -1,
+        Wdl::Draw => 0
+    };
+}
+
+fn mated(state: &State) -> bool {
+    return state.moves.parity_in_check(state.turn) && state.moves.parity_vect(state.turn).is_empty();
+}
+
+// Every table found in a set of directories, opened the first time a position needs them
+pub struct Tablebase {
+    files: HashMap<String, PathBuf>,
+    largest: usize,
+    tables: Mutex<HashMap<String, Option<Arc<Table>>>>
+}
+
+impl Tablebase {
+    // Takes directories separated the way PATH is
+    pub fn open(paths: &str) -> Tablebase {
+        let mut files = HashMap::new();
+        let mut largest = 0;
+        for dir in std::env::split_paths(paths) {
+            let Ok(entries) = std::fs::read_dir(&dir) else { continue };
+            for entry in entries.flatten() {
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                let Some((name, extension)) = file_name.rsplit_once('.') else { continue };
+                if (extension != "rtbw" && extension != "rtbz") || parse_name(name).is_none() {
+                    continue;
+                }
+                if extension == "rtbw" {
+                    largest = largest.max(name.len() - 1);
+                }
+                files.entry(file_name).or_insert(entry.path());
+            }
+        }
+        return Tablebase { files, largest, tables: Mutex::new(HashMap::new()) };
+    }
+    // The most pieces any WDL table found covers
+    pub fn largest(&self) -> usize {
+        return self.largest;
+    }
+    pub fn table_count(&self) -> usize {
+        return self.files.len();
+    }
+    // Syzygy tables know nothing of castling or other variants
+    pub fn can_probe(&self, state: &State) -> bool {
+        return state.variant == Variant::Standard && state.info.allowed_castles == 0
+            && state.board.iter().filter(|b| b.is_piece()).count() <= self.largest.max(2);
+    }
+
+    fn table(&self, state: &State, dtz: bool) -> Option<Arc<Table>> {
+        let counts = material(&state.board);
+        let extension = if dtz { "rtbz" } else { "rtbw" };
+        let mut tables = self.tables.lock().unwrap();
+        for first in 0..2 {
+            let name = material_name(&counts, first);
+            let file_name = format!("{}.{}", name, extension);
+            if let Some(table) = tables.get(&file_name) {
+                return table.clone();
+            }
+            if let Some(path) = self.files.get(&file_name) {
+                let table = Table::open(path, &name, dtz).map(Arc::new);
+                tables.insert(file_name, table.clone());
+                return table;
+            }
+        }
+        return None;
+    }
+
+    fn probe_table(&self, state: &State, dtz: bool, wdl: Wdl) -> Option<Lookup> {
+        if state.board.iter().filter(|b| b.is_piece()).count() == 2 {
+            return Some(Lookup::Value(0));
+        }
+        return self.table(state, dtz)?.lookup(state, wdl);
+    }
+
+    // Tables leave positions with a winning capture as "don't care", so captures are always searched.
+    // With zeroing set, pawn moves are searched too, as DTZ tables don't cover a winning zeroing move.
+    // Returns the value and whether the best move resets the fifty-move count.
+    fn search(&self, state: &mut State, zeroing: bool) -> Option<(Wdl, bool)> {
+        let moves = state.moves.parity_vect(state.turn);
+        let mut best = Wdl::Loss;
+        let mut tried = 0;
+        for motion in &moves {
+            if !is_capture(state, motion) && (!zeroing || !state.board[motion.from].is_pawn()) {
+                continue;
+            }
+            tried += 1;
+            state.make_motion(motion, false);
+            let result = self.search(state, false);
+            state.unmake_last(true);
+            let value = -result?.0;
+            if value > best {
+                best = value;
+                if value >= Wdl::Win {
+                    return Some((value, true));
+                }
+            }
+        }
+        let no_more_moves = tried > 0 && tried == moves.len();
+        let value = if no_more_moves {
+            best
+        } else {
+            match self.probe_table(state, false, Wdl::Draw)? {
+                Lookup::Value(v) => Wdl::from_value(v),
+                Lookup::ChangeStm => return None
+            }
+        };
+        if best >= value {
+            return Some((best, best > Wdl::Draw || no_more_moves));
+        }
+        return Some((value, false));
+    }
+
+    // Win, draw or loss for the side to move, or None if a table is missing
+    pub fn probe_wdl(&self, state: &mut State) -> Option<Wdl> {
+        if !self.can_probe(state) {
+            return None;
+        }
+        return Some(self.search(state, false)?.0);
+    }
+
+    // Plies to the next capture or pawn move that keeps the result, signed like the result. Zero is a draw.
+    pub fn probe_dtz(&self, state: &mut State) -> Option<i32> {
+        if !self.can_probe(state) {
+            return None;
+        }
+        let (wdl, zeroing) = self.search(state, true)?;
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        if zeroing {
+            return Some(dtz_before_zeroing(wdl));
+        }
+        if let Lookup::Value(dtz) = self.probe_table(state, true, wdl)? {
+            let cursed = wdl == Wdl::CursedWin || wdl == Wdl::BlessedLoss;
+            return Some((dtz + if cursed { 100 } else { 0 }) * wdl.sign());
+        }
+        // The table holds the other side to move, so look one ply ahead for the best of our moves
+        let mut min_dtz = i32::MAX;
+        for motion in state.moves.parity_vect(state.turn) {
+            let zeroing = is_capture(state, &motion) || state.board[motion.from].is_pawn();
+            state.make_motion(&motion, false);
+            let result = if zeroing {
+                self.search(state, false).map(|(wdl, _)| -dtz_before_zeroing(wdl))
+            } else {
+                self.probe_dtz(state).map(|dtz| -dtz)
+            };
+            let mates = mated(state);
+            state.unmake_last(true);
+            let mut dtz = result?;
+            if dtz == 1 && mates {
+                min_dtz = 1;
+            }
+            if !zeroing {
+                dtz += dtz.signum();
+            }
+            if dtz < min_dtz && dtz.signum() == wdl.sign() {
+                min_dtz = dtz;
+            }
+        }
+        return Some(if min_dtz == i32::MAX { -1 } else { min_dtz });
+    }
+
+    // The root moves worth searching: the quickest wins that beat the fifty-move rule if there are any,
+    // otherwise the draws, otherwise the losses that hold out longest
+    pub fn best_root_moves(&self, state: &mut State) -> Option<Vec<Motion>> {
+        if !self.can_probe(state) {
+            return None;
+        }
+        let halfmove = state.info.halfmove_clock as i32;
+        let repeated = state.repetitions() > 1;
+        let mut ranked = Vec::new();
+        for motion in state.moves.parity_vect(state.turn) {
+            state.make_motion(&motion, false);
+            let result = if state.info.halfmove_clock == 0 {
+                self.probe_wdl(state).map(|wdl| dtz_before_zeroing(-wdl))
+            } else if state.repetitions() > 1 {
+                Some(0)
+            } else {
+                self.probe_dtz(state).map(|dtz| -dtz + (-dtz).signum())
+            };
+            let mates = mated(state);
+            state.unmake_last(true);
+            let mut dtz = result?;
+            if mates && dtz == 2 {
+                dtz = 1;
+            }
+            // Certain wins rank equally, and so do losses unless the fifty-move rule could still save us
+            let rank = if dtz > 0 {
+                if dtz + halfmove <= 99 && !repeated { MAX_DTZ } else { MAX_DTZ - (dtz + halfmove) }
+            } else if dtz < 0 {
+                if -dtz * 2 + halfmove < 100 { -MAX_DTZ } else { -MAX_DTZ + (-dtz + halfmove) }
+            } else { 0 };
+            ranked.push((motion, rank, dtz));
+        }
+        let best = ranked.iter().map(|(_, rank, _)| *rank).max()?;
+        ranked.retain(|(_, rank, _)| *rank == best);
+        if best != 0 {
+            // Among equal wins head for the next zeroing move soonest, among equal losses put it off longest
+            let quickest = ranked.iter().map(|(_, _, dtz)| *dtz).min()?;
+            ranked.retain(|(_, _, dtz)| *dtz == quickest);
+        }
+        return Some(ranked.into_iter().map(|(motion, _, _)| motion).collect());
+    }
+}
+
+// Tables and how deep a search has to be before it probes them away from the root
+#[derive(Clone)]
+pub struct TablebaseSettings {
+    pub tables: Arc<Tablebase>,
+    pub probe_depth: u8
+}
@@ -0,0 +1,190 @@
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use crate::lib::{motion::Motion, piece::PieceByte};
+
+// Scores this far out are mates or tablebase wins counted from the root, so they're stored counted
+// from the position instead, and put back relative to whichever root finds them
+const WIN_SCORE: i32 = 100_000 - 1000;
+const SLOTS: usize = 4;
+const AGES: u8 = 32;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Bound {
+    // The search saw every move, so the score is the position's value
+    Exact = 1,
+    // A move failed high, so the value is at least the score
+    Lower = 2,
+    // Nothing raised alpha, so the value is at most the score
+    Upper = 3
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TtEntry {
+    pub motion: Motion,
+    pub score: i32,
+    pub depth: u8,
+    pub bound: Bound,
+    pub age: u8
+}
+
+impl TtEntry {
+    // From the lowest bit: from 7, to 7, promotion 3, depth 8, bound 2, age 5 and the score in the top 32.
+    // The bound is never 0, so neither is a stored entry.
+    fn pack(&self) -> u64 {
+        return (self.motion.from as u64 & 0x7F)
+            | (self.motion.to as u64 & 0x7F) << 7
+            | (self.motion.promotion as u64 & 0x7) << 14
+            | (self.depth as u64) << 17
+            | (self.bound as u64) << 25
+            | (self.age as u64 % AGES as u64) << 27
+            | (self.score as u32 as u64) << 32;
+    }
+    fn unpack(data: u64) -> Option<TtEntry> {
+        let bound = match (data >> 25) & 0x3 {
+            1 => Bound::Exact,
+            2 => Bound::Lower,
+            3 => Bound::Upper,
+            _ => return None
+        };
+        return Some(TtEntry {
+            motion: Motion { from: (data & 0x7F) as usize, to: ((data >> 7) & 0x7F) as usize, promotion: PieceByte::from(((data >> 14) & 0x7) as u8) },
+            score: (data >> 32) as u32 as i32,
+            depth: (data >> 17) as u8,
+            bound,
+            age: ((data >> 27) & 0x1F) as u8
+        });
+    }
+}
+
+// A slot keeps the key xor the data beside the data, so a slot torn by two threads writing at once
+// fails the key check instead of handing back another position's entry
+#[derive(Default)]
+struct Slot {
+    check: AtomicU64,
+    data: AtomicU64
+}
+
+#[derive(Default)]
+#[repr(align(64))]
+struct Bucket {
+    slots: [Slot; SLOTS]
+}
+
+pub struct TranspositionTable {
+    buckets: Vec<Bucket>,
+    age: AtomicU8
+}
+
+fn to_table(score: i32, ply: usize) -> i32 {
+    if score >= WIN_SCORE {
+        return score.saturating_add(ply as i32);
+    }
+    if score <= -WIN_SCORE {
+        return score.saturating_sub(ply as i32);
+    }
+    return score;
+}
+
+fn from_table(score: i32, ply: usize) -> i32 {
+    if score >= WIN_SCORE {
+        return score - ply as i32;
+    }
+    if score <= -WIN_SCORE {
+        return score + ply as i32;
+    }
+    return score;
+}
+
+impl TranspositionTable {
+    pub const DEFAULT_MB: usize = 16;
+    pub fn new(megabytes: usize) -> Self {
+        let count = (megabytes.max(1) << 20) / std::mem::size_of::<Bucket>();
+        return Self {
+            buckets: (0..count).map(|_| Bucket::default()).collect(),
+            age: AtomicU8::new(0)
+        };
+    }
+    pub fn megabytes(&self) -> usize {
+        return (self.buckets.len() * std::mem::size_of::<Bucket>()) >> 20;
+    }
+    pub fn capacity(&self) -> usize {
+        return self.buckets.len() * SLOTS;
+    }
+    pub fn clear(&self) -> () {
+        for bucket in &self.buckets {
+            for slot in &bucket.slots {
+                slot.check.store(0, Ordering::Relaxed);
+                slot.data.store(0, Ordering::Relaxed);
+            }
+        }
+        self.age.store(0, Ordering::Relaxed);
+    }
+    // Entries from earlier searches are the first to be replaced
+    pub fn new_search(&self) -> () {
+        let age = self.age.load(Ordering::Relaxed);
+        self.age.store((age + 1) % AGES, Ordering::Relaxed);
+    }
+    fn bucket(&self, key: u64) -> &Bucket {
+        return &self.buckets[((key as u128 * self.buckets.len() as u128) >> 64) as usize];
+    }
+    // The entry for the position, with its score seen from this ply
+    pub fn probe(&self, key: u64, ply: usize) -> Option<TtEntry> {
+        for slot in &self.bucket(key).slots {
+            let data = slot.data.load(Ordering::Relaxed);
+            if data != 0 && slot.check.load(Ordering::Relaxed) ^ data == key {
+                let mut entry = TtEntry::unpack(data)?;
+                entry.score = from_table(entry.score, ply);
+                return Some(entry);
+            }
+        }
+        return None;
+    }
+    // Replaces the position's own entry unless that came from a deeper search this time round.
+    // Otherwise the slot given up is an empty one, or the shallowest and oldest.
+    pub fn store(&self, key: u64, motion: Motion, score: i32, depth: u8, bound: Bound, ply: usize) -> () {
+        let age = self.age.load(Ordering::Relaxed);
+        let bucket = self.bucket(key);
+        let mut victim = 0;
+        let mut victim_worth = i32::MAX;
+        for (i, slot) in bucket.slots.iter().enumerate() {
+            let data = slot.data.load(Ordering::Relaxed);
+            let Some(old) = TtEntry::unpack(data) else {
+                if victim_worth > i32::MIN {
+                    victim = i;
+                    victim_worth = i32::MIN;
+                }
+                continue;
+            };
+            if slot.check.load(Ordering::Relaxed) ^ data == key {
+                if bound != Bound::Exact && old.age == age && depth.saturating_add(2) < old.depth {
+                    return;
+                }
+                // Keep the old best move when this search didn't find one
+                let motion = if motion == Motion::default() { old.motion } else { motion };
+                return self.write(&bucket.slots[i], key, TtEntry { motion, score: to_table(score, ply), depth, bound, age });
+            }
+            let staleness = (AGES + age - old.age) % AGES;
+            let worth = old.depth as i32 - 8 * staleness as i32;
+            if worth < victim_worth {
+                victim = i;
+                victim_worth = worth;
+            }
+        }
+        self.write(&bucket.slots[victim], key, TtEntry { motion, score: to_table(score, ply), depth, bound, age });
+    }
+    fn write(&self, slot: &Slot, key: u64, entry: TtEntry) -> () {
+        let data = entry.pack();
+        slot.check.store(key ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+    // Per mille of a sample of slots holding entries from this search, as UCI's hashfull wants
+    pub fn hashfull(&self) -> usize {
+        let age = self.age.load(Ordering::Relaxed);
+        let sample = self.buckets.iter().take(1000 / SLOTS).flat_map(|b| b.slots.iter());
+        let used = sample.filter(|s| TtEntry::unpack(s.data.load(Ordering::Relaxed)).is_some_and(|e| e.age == age)).count();
+        return used * 1000 / (self.buckets.len().min(1000 / SLOTS) * SLOTS);
+    }
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self { Self::new(Self::DEFAULT_MB) }
+}
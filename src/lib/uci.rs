@@ -0,0 +1,213 @@
+use std::{io::BufRead, path::Path, time::Duration};
+use crate::lib::{
+    bitbase,
+    engine::{send, Clock, Engine, Output, SearchLimits},
+    fen,
+    piece::Parity,
+    polyglot::BookSelection,
+    searcher::SearchReport
+};
+
+const OPTIONS: [&str; 8] = [
+    "option name Hash type spin default 16 min 1 max 65536",
+    "option name Threads type spin default 1 min 1 max 256",
+    "option name Ponder type check default false",
+    "option name BookFile type string default <empty>",
+    "option name BookBestMove type check default false",
+    "option name SyzygyPath type string default <empty>",
+    "option name SyzygyProbeDepth type spin default 1 min 1 max 100",
+    "option name BitbasePath type string default <empty>"
+];
+
+pub struct Uci {
+    engine: Engine,
+    out: Output,
+    // Kept from a pondering go, for when the ponder move is played
+    ponder_budget: Option<Duration>,
+    book_path: Option<String>,
+    book_selection: BookSelection,
+    syzygy_depth: u8
+}
+
+// Reads commands until quit or the end of the input
+pub fn run(input: impl BufRead, out: Output) -> () {
+    let mut uci = Uci::new(out);
+    for line in input.lines() {
+        let Ok(line) = line else { break };
+        if !uci.command(&line) {
+            return;
+        }
+    }
+    // Let a search that's still going finish and give its move
+    uci.engine.wait();
+}
+
+pub fn info_line(report: &SearchReport) -> String {
+    let score = match report.mate_in() {
+        Some(moves) => format!("mate {}", moves),
+        None => format!("cp {}", report.score)
+    };
+    let millis = report.elapsed.as_millis() as u64;
+    let nps = report.nodes * 1000 / millis.max(1);
+    return format!("info depth {} score {} nodes {} nps {} hashfull {} tbhits {} time {} pv {}", report.depth, score, report.nodes, nps, report.hashfull, report.tb_hits, millis, report.pv);
+}
+
+fn millis(value: Option<&&str>) -> Duration {
+    return Duration::from_millis(value.and_then(|v| v.parse::<i64>().ok()).unwrap_or(0).max(0) as u64);
+}
+
+impl Uci {
+    pub fn new(out: Output) -> Self {
+        return Self { engine: Engine::new(), out, ponder_budget: None, book_path: None, book_selection: BookSelection::Weighted, syzygy_depth: 1 };
+    }
+    // Returns false once it's time to quit
+    pub fn command(&mut self, line: &str) -> bool {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some(first) = words.first() else {
+            return true;
+        };
+        match *first {
+            "uci" => {
+                send(&self.out, &format!("id name chess {}", env!("CARGO_PKG_VERSION")));
+                send(&self.out, "id author the chess authors");
+                for option in OPTIONS {
+                    send(&self.out, option);
+                }
+                send(&self.out, "uciok");
+            },
+            "isready" => send(&self.out, "readyok"),
+            "ucinewgame" => self.engine.new_game(),
+            "position" => self.position(&words[1..]),
+            "go" => self.go(&words[1..]),
+            "stop" => self.engine.stop(),
+            "ponderhit" => self.engine.ponderhit(self.ponder_budget),
+            "setoption" => self.set_option(&words[1..]),
+            "debug" | "register" => (),
+            "quit" => {
+                self.engine.stop();
+                return false;
+            },
+            _ => send(&self.out, &format!("info string Unknown command: {}", line.trim()))
+        }
+        return true;
+    }
+    fn position(&mut self, args: &[&str]) -> () {
+        let moves_at = args.iter().position(|w| *w == "moves").unwrap_or(args.len());
+        let moves = args.get(moves_at + 1..).unwrap_or(&[]);
+        let fen = match args.first() {
+            Some(&"startpos") => fen::STARTING_FEN.to_string(),
+            Some(&"fen") => args[1..moves_at].join(" "),
+            _ => {
+                send(&self.out, "info string Expected startpos or fen after position");
+                return;
+            }
+        };
+        if let Err(e) = self.engine.set_position(&fen, moves) {
+            send(&self.out, &format!("info string Could not set up the position: {}", e));
+        }
+    }
+    fn go(&mut self, args: &[&str]) -> () {
+        let mut limits = SearchLimits::default();
+        let mut clocks = [Clock::default(); 2];
+        let mut timed = false;
+        let mut movetime = None;
+        let mut infinite = false;
+        let mut ponder = false;
+        for (i, word) in args.iter().enumerate() {
+            let value = args.get(i + 1);
+            match *word {
+                "wtime" => { clocks[0].remaining = millis(value); timed = true },
+                "btime" => { clocks[1].remaining = millis(value); timed = true },
+                "winc" => clocks[0].increment = millis(value),
+                "binc" => clocks[1].increment = millis(value),
+                "movestogo" => {
+                    let moves = value.and_then(|v| v.parse().ok());
+                    clocks[0].moves_to_go = moves;
+                    clocks[1].moves_to_go = moves;
+                },
+                "depth" => limits.depth = value.and_then(|v| v.parse().ok()),
+                "nodes" => limits.nodes = value.and_then(|v| v.parse().ok()),
+                "movetime" => movetime = Some(millis(value)),
+                "infinite" => infinite = true,
+                "ponder" => ponder = true,
+                _ => ()
+            }
+        }
+        let turn = self.engine.state.lock().unwrap().turn;
+        let clock = clocks[if turn == Parity::WHITE { 0 } else { 1 }];
+        let budget = movetime.or(timed.then(|| clock.budget()));
+        self.ponder_budget = budget;
+        limits.time = if infinite || ponder { None } else { budget };
+        limits.wait_for_stop = infinite || ponder;
+
+        let info = self.out.clone();
+        let out = self.out.clone();
+        self.engine.go(limits, move |report| send(&info, &info_line(report)), move |state, best| {
+            // With no legal moves there's nothing to play, which UCI writes as the null move
+            let best = best.map(|m| state.to_uci(&m)).unwrap_or("0000".to_string());
+            send(&out, &format!("bestmove {}", best));
+        });
+    }
+    fn set_option(&mut self, args: &[&str]) -> () {
+        let value_at = args.iter().position(|w| *w == "value").unwrap_or(args.len());
+        let name = args.get(1..value_at).unwrap_or(&[]).join(" ").to_lowercase();
+        let value = args.get(value_at + 1..).unwrap_or(&[]).join(" ");
+        let value = if value == "<empty>" { String::new() } else { value };
+        match name.as_str() {
+            "ponder" => (),
+            "hash" => match value.parse() {
+                Ok(megabytes) => self.engine.set_hash(megabytes),
+                Err(_) => send(&self.out, &format!("info string Bad hash size: {}", value))
+            },
+            "threads" => match value.parse() {
+                Ok(threads) => self.engine.set_threads(threads),
+                Err(_) => send(&self.out, &format!("info string Bad thread count: {}", value))
+            },
+            "bookfile" => {
+                self.book_path = (!value.is_empty()).then_some(value);
+                self.load_book();
+            },
+            "bookbestmove" => {
+                self.book_selection = if value == "true" { BookSelection::Best } else { BookSelection::Weighted };
+                self.load_book();
+            },
+            "syzygypath" => {
+                if value.is_empty() {
+                    self.engine.set_syzygy("", self.syzygy_depth);
+                    return;
+                }
+                match self.engine.set_syzygy(&value, self.syzygy_depth) {
+                    0 => send(&self.out, &format!("info string No Syzygy tables found in {}", value)),
+                    largest => send(&self.out, &format!("info string Found Syzygy tables up to {} pieces", largest))
+                }
+            },
+            "syzygyprobedepth" => {
+                self.syzygy_depth = value.parse().unwrap_or(self.syzygy_depth);
+                self.engine.set_syzygy_depth(self.syzygy_depth);
+            },
+            "bitbasepath" => {
+                if value.is_empty() {
+                    return;
+                }
+                match bitbase::load_or_generate(Path::new(&value)) {
+                    Ok(()) => send(&self.out, &format!("info string Loaded endgame bitbases from {}", value)),
+                    Err(e) => send(&self.out, &format!("info string Could not load bitbases from {}: {}", value, e))
+                }
+            },
+            _ => send(&self.out, &format!("info string Unknown option: {}", name))
+        }
+    }
+    fn load_book(&mut self) -> () {
+        let Some(path) = self.book_path.clone() else {
+            self.engine.book = None;
+            return;
+        };
+        match self.engine.set_book(&path, self.book_selection) {
+            Ok(entries) => send(&self.out, &format!("info string Loaded {} book entries from {}", entries, path)),
+            Err(e) => {
+                self.engine.book = None;
+                send(&self.out, &format!("info string Could not load book {}: {}", path, e));
+            }
+        }
+    }
+}
@@ -2,6 +2,9 @@
 use std::thread;
 
 use crate::lib::{
+    chess960,
+    fen::STARTING_FEN,
+    variant::Variant,
     cutil::draw::{visual_weight_remap_table, MID_COLOR_VALUE},
     cutil::draw,
     cutil::pretty_print::pretty_string_evaluator,
@@ -26,7 +29,9 @@ pub struct ChessApp {
     pub game_over: bool,
     pub game_rect: egui::Rect,
     pub info_rect: egui::Rect,
-    pub saved: SharedState
+    pub saved: SharedState,
+    // The Crazyhouse piece being dragged out of a pocket, if any
+    pub dragging: Option<PieceByte>
 }
 
 impl ChessApp {
@@ -52,14 +57,19 @@ impl ChessApp {
             saved: SharedState{
                 working: Some(false),
                 selected: Some(65),
-                game_over: Some(false),
+                promotion_square: Some(65),
+                outcome: None,
+                checks_remaining: None,
+                pockets: None,
+                drops: None,
                 allowed_castles: Some(0),
                 waiting_for_a_human_input: Some(false),
                 turn: Some(Parity::WHITE),
                 moves: Some([const { Vec::new() }; 64]),
                 visuals: VisualInfo::none(),
                 board: Some([0u8; 64])
-            }
+            },
+            dragging: None
         };
 
     }
@@ -78,7 +88,12 @@ pub struct Input {
     pub left: bool,
     pub right: bool,
     pub pos: Option<Point>,
-    pub wants_unpause: bool
+    pub wants_unpause: bool,
+    pub promotion: Option<PieceByte>,
+    // The FEN of a game to start in place of the current one
+    pub new_game: Option<(String, Variant)>,
+    // A Crazyhouse piece let go over a square after being dragged out of the pocket
+    pub drop: Option<(PieceByte, Point)>
 }
 impl Input {
     pub fn from_tuple(tuple: (bool, bool, bool, Option<egui::Pos2>, bool)) -> Input {
@@ -91,7 +106,10 @@ impl Input {
             left: tuple.1,
             right: tuple.2,
             pos: po,
-            wants_unpause: tuple.4
+            wants_unpause: tuple.4,
+            promotion: None,
+            new_game: None,
+            drop: None
         };
     }
 }
@@ -114,6 +132,10 @@ fn collect_input(ctx: &egui::Context) -> Input {
 const INFO_LINE_HEIGHT: f32 = 16.0;
 const WEIGHT_VIS_SIZE: f32 = 100.0;
 
+fn sqsize_of(game_rect: &egui::Rect) -> f32 {
+    return game_rect.width().min(game_rect.height()) / (BOARD_SIZE as f32);
+}
+
 fn remap(v: i32, inpair: (i32, i32), outpair: (i32, i32)) -> i32 {
     return outpair.0 + (v - inpair.0) * (outpair.1 - outpair.0) / (inpair.1 - inpair.0);
 }
@@ -131,8 +153,14 @@ impl eframe::App for ChessApp {
             if recvd.moves.is_some() { self.saved.moves = recvd.moves.clone() };
             if recvd.allowed_castles.is_some() { self.saved.allowed_castles = recvd.allowed_castles };
             if recvd.working.is_some() { self.saved.working = recvd.working };
-            if recvd.game_over.is_some() { self.saved.game_over = recvd.game_over };
+            // Anything carrying a board is a full snapshot, so it can also clear the outcome when a new game starts
+            if recvd.outcome.is_some() || recvd.board.is_some() { self.saved.outcome = recvd.outcome };
+            // Full snapshots carry a turn, and only Three-check snapshots carry a count
+            if recvd.turn.is_some() { self.saved.checks_remaining = recvd.checks_remaining };
+            if recvd.turn.is_some() { self.saved.pockets = recvd.pockets };
+            if recvd.turn.is_some() { self.saved.drops = recvd.drops.clone() };
             if recvd.selected.is_some() { self.saved.selected = recvd.selected };
+            if recvd.promotion_square.is_some() { self.saved.promotion_square = recvd.promotion_square };
             if recvd.visuals.visual_weights.is_some() { self.saved.visuals.visual_weights = recvd.visuals.visual_weights };
             if recvd.visuals.cache_saves.is_some() { self.saved.visuals.cache_saves = recvd.visuals.cache_saves };
             if recvd.visuals.analyzed.is_some() { self.saved.visuals.analyzed = recvd.visuals.analyzed };
@@ -143,7 +171,7 @@ impl eframe::App for ChessApp {
         }
 
 
-        let input = collect_input(ctx);
+        let mut input = collect_input(ctx);
         if input.wants_escape {
             ctx.send_viewport_cmd(egui::ViewportCommand::Close);
         }
@@ -175,17 +203,38 @@ impl eframe::App for ChessApp {
                             if self.saved.working.is_some_and(|x| x) {
                                 draw::highlight_considerations(&dbg_painter, self.saved.visuals.considerations.as_ref(), sqsize);
                             }
+                            let promotion_square = self.saved.promotion_square.unwrap_or(65);
+                            if promotion_square != 65 {
+                                // Offer whatever the legal moves allow, which includes a king in Antichess
+                                let mut choices: Vec<PieceByte> = Vec::new();
+                                for m in self.saved.moves.as_ref().unwrap().iter().flatten() {
+                                    if m.to == promotion_square && m.is_promotion() && !choices.contains(&m.promotion) {
+                                        choices.push(m.promotion);
+                                    }
+                                }
+                                input.promotion = draw::promotion_picker(ctx, promotion_square, self.saved.turn.unwrap_or(Parity::WHITE), &choices, sqsize);
+                            }
                         });
+                        if let Some(piece) = self.dragging {
+                            let byte = self.saved.turn.unwrap_or(Parity::WHITE) | piece;
+                            game_ui.with_layer_id(egui::LayerId::new(egui::Order::Tooltip, egui::Id::new("dragged_drop")), |uui| {
+                                draw::draw_dragged_drop(uui, byte, self.saved.drops.as_deref().unwrap_or(&[]), sqsize_of(&self.game_rect));
+                            });
+                            if ctx.input(|i| i.pointer.primary_released()) {
+                                self.dragging = None;
+                                input.drop = input.pos.filter(|p| p.valid()).map(|p| (piece, p));
+                            }
+                        }
 
-                        if self.saved.game_over.unwrap() {
+                        if let Some(outcome) = self.saved.outcome {
                             game_ui.with_layer_id(egui::LayerId::new(egui::Order::TOP, egui::Id::new("top")), |uui| {
-                                if self.saved.turn.unwrap() == Parity::WHITE {
-                                    let rich = egui::RichText::new("BLACK WINS").monospace().size(48.0).color(Color32::BLACK).background_color(draw::BOARD_W_COLOR);
-                                    uui.put(self.game_rect, egui::Label::new(rich));
-                                } else {
-                                    let rich = egui::RichText::new("WHITE WINS").monospace().size(48.0).color(Color32::WHITE).background_color(draw::BOARD_B_COLOR);
-                                    uui.put(self.game_rect, egui::Label::new(rich));
-                                }
+                                let (fg, bg) = match outcome.winner() {
+                                    Some(Parity::WHITE) => (Color32::WHITE, draw::BOARD_B_COLOR),
+                                    Some(_) => (Color32::BLACK, draw::BOARD_W_COLOR),
+                                    None => (Color32::GRAY, Color32::from_black_alpha(200))
+                                };
+                                let rich = egui::RichText::new(format!("{}\nby {}", outcome.headline(), outcome.reason())).monospace().size(48.0).color(fg).background_color(bg);
+                                uui.put(self.game_rect, egui::Label::new(rich));
                             });
                         }
                     });
@@ -214,6 +263,19 @@ impl eframe::App for ChessApp {
                             ui.add_space(ui.painter().round_to_pixel(INFO_LINE_HEIGHT) / 2.0 + 7.0);
                         }
                         ui.add_space(ui.painter().round_to_pixel(INFO_LINE_HEIGHT) * (4.0 - castles.len() as f32));
+                        if let Some([white, black]) = self.saved.checks_remaining {
+                            ui.label(egui::RichText::new(format!("Checks left: White {}, Black {}", white, black)).monospace());
+                        }
+                        if let Some(pockets) = self.saved.pockets {
+                            ui.label(egui::RichText::new("In hand, drag onto the board to drop").monospace());
+                            let waiting = self.saved.waiting_for_a_human_input.unwrap_or(false);
+                            for (side, parity) in [(0, Parity::WHITE), (1, Parity::BLACK)] {
+                                let draggable = waiting && self.saved.turn == Some(parity);
+                                if let Some(piece) = draw::pocket_panel(ui, parity, &pockets[side], draggable, sqsize_of(&self.game_rect)) {
+                                    self.dragging = Some(piece);
+                                }
+                            }
+                        }
                         ui.label(egui::RichText::new("Evaluation").monospace());
                     
                         // ui.label(egui::RichText::new(pretty_string_evaluator(state.visuals.evaluation.as_ref().unwrap())));
@@ -243,6 +305,25 @@ impl eframe::App for ChessApp {
                         ui.spacing_mut().item_spacing = egui::Vec2 { x: 15.0, y: 2.5 };
                         ui.label(egui::RichText::new(format!("Searched {} positions", self.saved.visuals.analyzed.unwrap_or(0))));
                         ui.label(egui::RichText::new(format!("Saved {} searches with caching", self.saved.visuals.cache_saves.unwrap_or(0))));
+                        ui.add_space(15.0);
+                        if ui.button("New Chess960 game").clicked() {
+                            input.new_game = Some((chess960::random_start_fen(), Variant::Standard));
+                        }
+                        if ui.button("New Three-check game").clicked() {
+                            input.new_game = Some((STARTING_FEN.to_string(), Variant::ThreeCheck));
+                        }
+                        if ui.button("New King of the Hill game").clicked() {
+                            input.new_game = Some((STARTING_FEN.to_string(), Variant::KingOfTheHill));
+                        }
+                        if ui.button("New Atomic game").clicked() {
+                            input.new_game = Some((STARTING_FEN.to_string(), Variant::Atomic));
+                        }
+                        if ui.button("New Antichess game").clicked() {
+                            input.new_game = Some((STARTING_FEN.to_string(), Variant::Antichess));
+                        }
+                        if ui.button("New Crazyhouse game").clicked() {
+                            input.new_game = Some((STARTING_FEN.to_string(), Variant::Crazyhouse));
+                        }
                     });
                     let (_, tree_rect) = ui.allocate_space(egui::Vec2 { x: ui.available_width(), y: ui.available_height() * 2.0 });
                     let tree_painter = Painter::new(ctx.clone(), egui::LayerId::new(egui::Order::Debug, egui::Id::new("tree_painter")), tree_rect);
@@ -252,7 +333,7 @@ impl eframe::App for ChessApp {
                 });
 
             });
-            if self.saved.waiting_for_a_human_input.unwrap() && input.left {
+            if input.new_game.is_some() || (self.saved.waiting_for_a_human_input.unwrap() && (input.left || input.promotion.is_some() || input.drop.is_some())) {
                 let _ = self.sender.send(input).unwrap();
             }
         });
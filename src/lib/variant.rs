@@ -0,0 +1,96 @@
+use crate::lib::{
+    boardarray::BoardArray,
+    crazyhouse::Pocket,
+    mask::Mask,
+    maskset::MaskSet,
+    motion::MotionSet,
+    piece::{Parity, PieceByte},
+    state::State
+};
+
+// Rule sets layered on top of ordinary chess. Chess960 is separate since it only changes the setup and castling.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum Variant {
+    #[default]
+    Standard,
+    ThreeCheck,
+    KingOfTheHill,
+    Atomic,
+    Antichess,
+    Crazyhouse
+}
+
+pub const CHECKS_TO_WIN: u8 = 3;
+// d5, e5, d4 and e4
+pub const HILL: Mask = Mask { raw: (1u64 << 27) | (1u64 << 28) | (1u64 << 35) | (1u64 << 36) };
+
+impl Variant {
+    // The name used in the PGN Variant tag
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Self::Standard => "Standard",
+            Self::ThreeCheck => "Three-check",
+            Self::KingOfTheHill => "King of the Hill",
+            Self::Atomic => "Atomic",
+            Self::Antichess => "Antichess",
+            Self::Crazyhouse => "Crazyhouse"
+        };
+    }
+    // Accepts the tag names along with the usual shorthands, ignoring case, spaces and dashes
+    pub fn from_name(name: &str) -> Option<Variant> {
+        let squashed: String = name.chars().filter(|c| c.is_alphanumeric()).collect::<String>().to_ascii_lowercase();
+        return match squashed.as_str() {
+            "standard" | "chess" | "normal" => Some(Self::Standard),
+            "threecheck" | "3check" => Some(Self::ThreeCheck),
+            "kingofthehill" | "koth" => Some(Self::KingOfTheHill),
+            "atomic" => Some(Self::Atomic),
+            "antichess" | "losingchess" | "losers" => Some(Self::Antichess),
+            "crazyhouse" | "zh" => Some(Self::Crazyhouse),
+            _ => None
+        };
+    }
+    // The legal motions of both sides under this variant's rules
+    pub fn motions(&self, board: &[u8; 64], maskset: &MaskSet, enpassant: &Mask, castles: Option<(u8, [usize; 4])>, pockets: &[Pocket; 2]) -> MotionSet {
+        return match self {
+            Self::Atomic => board.get_atomic_motions(maskset, enpassant, castles),
+            Self::Antichess => board.get_antichess_motions(maskset, enpassant),
+            Self::Crazyhouse => board.get_motions(maskset, enpassant, castles, Some(pockets)),
+            _ => board.get_motions(maskset, enpassant, castles, None)
+        };
+    }
+}
+
+impl std::fmt::Display for Variant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return write!(f, "{}", self.name());
+    }
+}
+
+impl State {
+    // The side that has won by the variant's own rule. That is always the side that just moved,
+    // except in Antichess where the side to move wins by having no move left.
+    pub fn variant_winner(&self) -> Option<Parity> {
+        let mover = !self.turn;
+        let side = if mover == Parity::WHITE { 0 } else { 1 };
+        return match self.variant {
+            Variant::Standard | Variant::Crazyhouse => None,
+            Variant::ThreeCheck => (self.info.checks[side] >= CHECKS_TO_WIN).then_some(mover),
+            Variant::KingOfTheHill => (self.info.maskset.piece(mover, PieceByte::KING) & HILL).any().then_some(mover),
+            Variant::Atomic => (self.info.maskset.king(self.turn) == 65).then_some(mover),
+            Variant::Antichess => self.moves.parity_vect(self.turn).is_empty().then_some(self.turn)
+        };
+    }
+    // Switches the rules of a set up position. Cached motions were generated under the old rules, so they go.
+    pub fn set_variant(&mut self, variant: Variant) -> () {
+        self.variant = variant;
+        self.cached_moves.clear();
+        self.hydrate(false);
+    }
+    // How many more checks each side needs to win, [white, black]
+    pub fn remaining_checks(&self) -> [u8; 2] {
+        return [
+            CHECKS_TO_WIN.saturating_sub(self.info.checks[0]),
+            CHECKS_TO_WIN.saturating_sub(self.info.checks[1])
+        ];
+    }
+}
@@ -0,0 +1,233 @@
+use std::{io::BufRead, time::Duration};
+use crate::lib::{
+    engine::{send, Clock, Engine, Output, SearchLimits},
+    piece::Parity,
+    searcher::SearchReport,
+    state::State
+};
+
+const FEATURES: &str = "feature ping=1 setboard=1 usermove=1 playother=1 san=0 time=1 draw=0 sigint=0 sigterm=0 memory=1 smp=1 reuse=1 analyze=0 colors=0 variants=\"normal\"";
+
+pub struct Xboard {
+    engine: Engine,
+    out: Output,
+    // The side the engine plays, None in force mode
+    engine_side: Option<Parity>,
+    post: bool,
+    // From level: moves per time control (0 for the whole game), base time and increment
+    moves_per_session: u32,
+    base: Duration,
+    increment: Duration,
+    // From st and sd
+    move_time: Option<Duration>,
+    depth_limit: Option<u8>,
+    // The engine's clock, as last told by time
+    own_time: Duration,
+    // The opponent's, as last told by otim. Only our own clock goes into the budget.
+    opponent_time: Duration
+}
+
+// Reads commands until quit or the end of the input
+pub fn run(input: impl BufRead, out: Output) -> () {
+    let mut xboard = Xboard::new(out);
+    for line in input.lines() {
+        let Ok(line) = line else { break };
+        if !xboard.command(&line) {
+            return;
+        }
+    }
+    // Let a search that's still going finish and play its move
+    xboard.engine.wait();
+}
+
+// Thinking output is ply, score in centipawns, time in centiseconds, nodes and the line
+pub fn thinking_line(report: &SearchReport) -> String {
+    // XBoard reads 100000 and up as mate, with the number of moves in the last digits
+    let score = match report.mate_in() {
+        Some(moves) => moves.signum() * 100_000 + moves,
+        None => report.score
+    };
+    return format!("{} {} {} {} {}", report.depth, score, report.elapsed.as_millis() / 10, report.nodes, report.pv);
+}
+
+// The result command for a finished game, as XBoard wants engines to claim it
+pub fn result_line(state: &State) -> Option<String> {
+    return state.outcome().map(|outcome| format!("{} {{{}}}", outcome.result(), outcome));
+}
+
+// Times in the level command are minutes, or minutes and seconds
+fn minutes(text: &str) -> Option<Duration> {
+    let (minutes, seconds) = text.split_once(':').unwrap_or((text, "0"));
+    return Some(Duration::from_secs(minutes.parse::<u64>().ok()? * 60 + seconds.parse::<u64>().ok()?));
+}
+
+fn seconds(text: &str) -> Option<Duration> {
+    return text.parse::<f64>().ok().filter(|s| *s >= 0.0).map(Duration::from_secs_f64);
+}
+
+fn centiseconds(text: &str) -> Option<Duration> {
+    return text.parse::<i64>().ok().map(|cs| Duration::from_millis(cs.max(0) as u64 * 10));
+}
+
+impl Xboard {
+    const DEFAULT_BASE: Duration = Duration::from_secs(300);
+    pub fn new(out: Output) -> Self {
+        return Self {
+            engine: Engine::new(),
+            out,
+            engine_side: Some(Parity::BLACK),
+            post: false,
+            moves_per_session: 40,
+            base: Self::DEFAULT_BASE,
+            increment: Duration::ZERO,
+            move_time: None,
+            depth_limit: None,
+            own_time: Self::DEFAULT_BASE,
+            opponent_time: Self::DEFAULT_BASE
+        };
+    }
+    // Returns false once it's time to quit
+    pub fn command(&mut self, line: &str) -> bool {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some(first) = words.first() else {
+            return true;
+        };
+        let arg = words.get(1).copied().unwrap_or("");
+        match *first {
+            "xboard" | "accepted" | "rejected" | "random" | "hard" | "easy" | "computer" | "name" | "rating" | "ics" | "draw" | "white" | "black" => (),
+            "protover" => {
+                send(&self.out, &format!("{} myname=\"chess {}\"", FEATURES, env!("CARGO_PKG_VERSION")));
+                send(&self.out, "feature done=1");
+            },
+            "ping" => send(&self.out, &format!("pong {}", arg)),
+            "new" => {
+                self.engine.new_game();
+                self.engine_side = Some(Parity::BLACK);
+                self.depth_limit = None;
+                self.own_time = self.base;
+                self.opponent_time = self.base;
+            },
+            "force" => {
+                self.engine.abort();
+                self.engine_side = None;
+            },
+            "go" => {
+                self.engine_side = Some(self.engine.state.lock().unwrap().turn);
+                self.think();
+            },
+            "playother" => {
+                self.engine_side = Some(!self.engine.state.lock().unwrap().turn);
+            },
+            "usermove" => self.user_move(arg),
+            "?" => self.engine.stop(),
+            "level" => {
+                match (words.get(1).and_then(|m| m.parse().ok()), words.get(2).and_then(|b| minutes(b)), words.get(3).and_then(|i| seconds(i))) {
+                    (Some(moves), Some(base), Some(increment)) => {
+                        self.moves_per_session = moves;
+                        self.base = base;
+                        self.increment = increment;
+                        self.own_time = base;
+                        self.opponent_time = base;
+                        self.move_time = None;
+                    },
+                    _ => send(&self.out, &format!("Error (bad time control): {}", line.trim()))
+                }
+            },
+            "st" => match seconds(arg) {
+                Some(time) => self.move_time = Some(time),
+                None => send(&self.out, &format!("Error (bad time): {}", line.trim()))
+            },
+            "sd" => match arg.parse() {
+                Ok(depth) => self.depth_limit = Some(depth),
+                Err(_) => send(&self.out, &format!("Error (bad depth): {}", line.trim()))
+            },
+            "time" => match centiseconds(arg) {
+                Some(time) => self.own_time = time,
+                None => send(&self.out, &format!("Error (bad time): {}", line.trim()))
+            },
+            "otim" => match centiseconds(arg) {
+                Some(time) => self.opponent_time = time,
+                None => send(&self.out, &format!("Error (bad time): {}", line.trim()))
+            },
+            "undo" => {
+                self.engine.undo();
+            },
+            "remove" => {
+                self.engine.undo();
+                self.engine.undo();
+            },
+            "setboard" => {
+                if let Err(e) = self.engine.set_position(&words[1..].join(" "), &[]) {
+                    send(&self.out, &format!("tellusererror Illegal position: {}", e));
+                }
+            },
+            "memory" => match arg.parse() {
+                Ok(megabytes) => self.engine.set_hash(megabytes),
+                Err(_) => send(&self.out, &format!("Error (bad memory size): {}", line.trim()))
+            },
+            "cores" => match arg.parse() {
+                Ok(threads) => self.engine.set_threads(threads),
+                Err(_) => send(&self.out, &format!("Error (bad core count): {}", line.trim()))
+            },
+            "post" => self.post = true,
+            "nopost" => self.post = false,
+            "result" => {
+                self.engine.abort();
+                self.engine_side = None;
+            },
+            "quit" => {
+                self.engine.abort();
+                return false;
+            },
+            // Without usermove=1 accepted, moves come on their own
+            _ if self.engine.state.lock().unwrap().parse_uci(first).is_ok() => self.user_move(first),
+            _ => send(&self.out, &format!("Error (unknown command): {}", first))
+        }
+        return true;
+    }
+    // The engine's clock and the opponent's
+    pub fn clocks(&self) -> (Duration, Duration) {
+        return (self.own_time, self.opponent_time);
+    }
+    fn user_move(&mut self, uci: &str) -> () {
+        if let Err(e) = self.engine.make_move(uci) {
+            send(&self.out, &format!("Illegal move ({}): {}", e, uci));
+            return;
+        }
+        if self.engine_side == Some(self.engine.state.lock().unwrap().turn) {
+            self.think();
+        }
+    }
+    fn think(&mut self) -> () {
+        let lock = self.engine.state.lock().unwrap();
+        if lock.outcome().is_some() {
+            return;
+        }
+        let fullmove = lock.info.fullmove_number.max(1) as u32;
+        drop(lock);
+        let clock = Clock {
+            remaining: self.own_time,
+            increment: self.increment,
+            moves_to_go: (self.moves_per_session > 0).then(|| self.moves_per_session - (fullmove - 1) % self.moves_per_session)
+        };
+        let limits = SearchLimits {
+            time: Some(self.move_time.unwrap_or(clock.budget())),
+            depth: self.depth_limit,
+            nodes: None,
+            wait_for_stop: false
+        };
+        let post = self.post;
+        let info = self.out.clone();
+        let out = self.out.clone();
+        self.engine.go(limits, move |report| if post { send(&info, &thinking_line(report)) }, move |state, best| {
+            let Some(best) = best else {
+                return;
+            };
+            send(&out, &format!("move {}", state.to_uci(&best)));
+            state.make_motion(&best, false);
+            if let Some(result) = result_line(state) {
+                send(&out, &result);
+            }
+        });
+    }
+}
@@ -2,14 +2,19 @@ use std::collections::HashMap;
 
 use rand_chacha::{rand_core::{RngCore, SeedableRng}, ChaCha8Rng};
 
-use crate::lib::{chessbyte::ChessByte, piece::{Parity, PieceByte}, state::{RetainedStateInfo, State}};
+use crate::lib::{chessbyte::ChessByte, mask::Mask, piece::{Parity, PieceByte}, state::{RetainedStateInfo, State}};
 
 use super::{eval::Evaluator, motion::MotionSet};
 
 pub struct Zobrist {
     pub zpieces: [[u64; 12]; 64],
-    pub zcastles: [u64; 16],
+    // [castling bit][rook file], so Chess960 rights on different rooks hash differently
+    pub zcastles: [[u64; 8]; 4],
     pub zpassant: [u64; 9],
+    // [white, black][checks given], for Three-check. No checks hashes to nothing so other games are unaffected.
+    pub zchecks: [[u64; 4]; 2],
+    // [white, black][pocket slot][pieces held], for Crazyhouse. An empty pocket hashes to nothing like the checks do.
+    pub zpockets: [[[u64; 17]; 5]; 2],
     pub zside: u64,
     table: HashMap<u64, (RetainedStateInfo, MotionSet, Option<Evaluator>)>
 }
@@ -20,8 +25,10 @@ impl Zobrist {
         let mut rng = ChaCha8Rng::seed_from_u64(seed);
         let mut zob = Zobrist {
             zpieces: [[0u64; 12]; 64],
-            zcastles: [0u64; 16],
+            zcastles: [[0u64; 8]; 4],
             zpassant: [0u64; 9],
+            zchecks: [[0u64; 4]; 2],
+            zpockets: [[[0u64; 17]; 5]; 2],
             zside: rng.next_u64(),
             table: HashMap::new()
         };
@@ -30,18 +37,50 @@ impl Zobrist {
                 zob.zpieces[i][p] = rng.next_u64();
             }
         }
-        for i in 0..16 {
-            zob.zcastles[i] = rng.next_u64();
+        for i in 0..4 {
+            for file in 0..8 {
+                zob.zcastles[i][file] = rng.next_u64();
+            }
         }
         zob.zpassant[0] = 0;
         for i in 1..9 {
             zob.zpassant[i] = rng.next_u64();
         }
+        for side in 0..2 {
+            for given in 1..4 {
+                zob.zchecks[side][given] = rng.next_u64();
+            }
+        }
+        for side in 0..2 {
+            for slot in 0..5 {
+                for held in 1..17 {
+                    zob.zpockets[side][slot][held] = rng.next_u64();
+                }
+            }
+        }
         return zob;
     }
     pub fn pieces(&self, byte: &u8, index: usize) -> u64 {
         return self.zpieces[index][Self::index_from_byte(&(byte & 0b0000_1111))];
     }
+    pub fn passant(&self, enpassant: &Mask) -> u64 {
+        return if enpassant.any() { self.zpassant[enpassant.as_index() % 8 + 1] } else { self.zpassant[0] };
+    }
+    pub fn castles(&self, allowed_castles: u8, castle_rooks: &[usize; 4]) -> u64 {
+        let mut k = 0u64;
+        for bit in 0..4 {
+            if allowed_castles & (1 << bit) != 0 {
+                k ^= self.zcastles[bit][castle_rooks[bit] % 8];
+            }
+        }
+        return k;
+    }
+    pub fn checks(&self, side: usize, given: u8) -> u64 {
+        return self.zchecks[side][(given as usize).min(3)];
+    }
+    pub fn pocket(&self, side: usize, slot: usize, held: u8) -> u64 {
+        return self.zpockets[side][slot][(held as usize).min(16)];
+    }
     pub fn index_from_byte(byte: &u8) -> usize {
         return if byte.is_white() { 6 } else { 0 } + match byte.get_piece() {
             PieceByte::ROOK => 0,
@@ -61,11 +100,17 @@ impl Zobrist {
                 k ^= self.zpieces[i][Zobrist::index_from_byte(&board[i])];
             }
         }
-        k ^= self.zpassant[state.info.enpassant_mask.as_index() % 8];
+        k ^= self.passant(&state.info.enpassant_mask);
         if state.turn == Parity::BLACK {
             k ^= self.zside;
         }
-        k ^= self.zcastles[state.info.allowed_castles as usize];
+        k ^= self.castles(state.info.allowed_castles, &state.info.castle_rooks);
+        k ^= self.checks(0, state.info.checks[0]) ^ self.checks(1, state.info.checks[1]);
+        for side in 0..2 {
+            for slot in 0..5 {
+                k ^= self.pocket(side, slot, state.info.pockets[side][slot]);
+            }
+        }
         return k;
     }
 
@@ -0,0 +1,76 @@
+use chess::lib::{
+    fen::{FenError, STARTING_FEN},
+    outcome::GameOutcome,
+    pgn::PgnGame,
+    piece::{Parity, PieceByte},
+    state::State,
+    variant::Variant
+};
+
+mod common;
+use common::play;
+
+fn antichess(fen: &str) -> State {
+    return State::from_variant_fen(fen, Variant::Antichess).unwrap();
+}
+
+#[test]
+fn perft_startpos() {
+    let mut state = antichess(STARTING_FEN);
+    // There is no castling, so the rights are dropped
+    assert_eq!(state.to_fen(), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1");
+    for (depth, nodes) in [20, 400, 8067, 153299].iter().enumerate() {
+        assert_eq!(state.perft(depth as u32 + 1), *nodes, "depth {}", depth + 1);
+    }
+}
+
+#[test]
+fn captures_are_compulsory() {
+    let mut state = antichess(STARTING_FEN);
+    play(&mut state, "e2e3");
+    play(&mut state, "b7b5");
+    let legal: Vec<String> = state.moves.parity_vect(state.turn).iter().map(|m| state.to_uci(m)).collect();
+    assert_eq!(legal, vec!["f1b5"]);
+}
+
+#[test]
+fn kings_can_be_taken_and_made() {
+    let mut state = antichess("8/P7/8/8/8/8/1k6/K7 w - - 0 1");
+    // Taking the king is forced, and leaves black with nothing
+    assert!(state.parse_uci("a7a8k").is_err());
+    play(&mut state, "a1b2");
+    assert_eq!(state.info.king_indices, [49, 65]);
+    assert_eq!(state.outcome(), Some(GameOutcome::OutOfMoves(Parity::BLACK)));
+    state.unmake_last(true);
+    assert_eq!(state.info.king_indices, [56, 49]);
+
+    let mut state = antichess("8/P7/8/8/8/8/8/7k w - - 0 1");
+    let promotion = state.parse_uci("a7a8k").unwrap();
+    assert_eq!(promotion.promotion, PieceByte::KING);
+    assert_eq!(state.to_san(&promotion), "a8=K");
+    state.make_motion(&promotion, false);
+    assert_eq!(state.to_fen(), "K7/8/8/8/8/8/8/7k b - - 0 1");
+    assert_eq!(state.info.king_indices, [0, 63]);
+    let pgn = PgnGame::from_state(&state, None).unwrap();
+    assert_eq!(pgn.tag("Variant"), Some("Antichess"));
+    assert_eq!(PgnGame::parse(&pgn.write()).unwrap().replay().unwrap().to_fen(), state.to_fen());
+}
+
+#[test]
+fn kingless_and_many_king_positions() {
+    assert!(matches!(State::from_fen("8/8/8/8/8/8/p7/8 w - - 0 1"), Err(FenError::MissingKing(_))));
+    // No pieces left to move means white has already won
+    let state = antichess("8/8/8/8/8/8/p7/8 w - - 0 1");
+    assert_eq!(state.outcome(), Some(GameOutcome::OutOfMoves(Parity::WHITE)));
+    // So does being stalemated
+    let state = antichess("8/8/8/8/8/p7/P7/8 w - - 0 1");
+    assert_eq!(state.outcome(), Some(GameOutcome::OutOfMoves(Parity::WHITE)));
+    let state = antichess("KK6/8/8/8/8/8/8/k7 w - - 0 1");
+    assert_eq!(state.moves.parity_vect(Parity::WHITE).len(), 6);
+    assert_eq!(state.outcome(), None);
+    // Switching an ordinary game over regenerates its moves under the new rules
+    let mut state = State::from_fen("4k3/8/8/8/8/8/3p4/4K3 w - - 0 1").unwrap();
+    assert_eq!(state.moves.parity_vect(Parity::WHITE).len(), 5);
+    state.set_variant(Variant::Antichess);
+    assert_eq!(state.moves.parity_vect(Parity::WHITE).len(), 1);
+}
@@ -0,0 +1,80 @@
+use chess::lib::{
+    fen::{FenError, STARTING_FEN},
+    outcome::GameOutcome,
+    pgn::PgnGame,
+    piece::Parity,
+    state::State,
+    variant::Variant
+};
+
+mod common;
+use common::{check_with, play};
+
+fn atomic(fen: &str) -> State {
+    return State::from_variant_fen(fen, Variant::Atomic).unwrap();
+}
+
+fn check(fen: &str, expected: &[u64]) {
+    check_with(atomic, fen, expected);
+}
+
+#[test]
+fn perft_positions() {
+    check(STARTING_FEN, &[20, 400, 8902]);
+    check("rn2kb1r/1pp1p2p/p2q1pp1/3P4/2P3b1/4PN2/PP3PPP/R2QKB1R b KQkq - 0 1", &[40, 1238, 45237]);
+    check("rn1qkb1r/p5pp/2p5/3p4/N3P3/5P2/PPP4P/R1BQK3 w Qkq - 0 1", &[28, 833, 23353]);
+}
+
+#[test]
+fn captures_explode() {
+    let fen = "r3k3/8/8/3pb3/2nB1N2/3P4/8/4K3 w q - 0 1";
+    let mut state = atomic(fen);
+    let before = state.info.zkey;
+    // Bxe5 blows up both bishops and the f4 knight. The pawns survive and the c4 knight is out of reach.
+    play(&mut state, "d4e5");
+    assert_eq!(state.to_fen(), "r3k3/8/8/3p4/2n5/3P4/8/4K3 b q - 0 1");
+    assert_eq!(state.info.zkey, atomic(&state.to_fen()).info.zkey);
+    state.unmake_last(true);
+    assert_eq!(state.to_fen(), fen);
+    assert_eq!(state.info.zkey, before);
+    assert_eq!(state.info.maskset.all.raw, atomic(fen).info.maskset.all.raw);
+}
+
+#[test]
+fn exploding_the_king_wins() {
+    let mut state = atomic("rnbqkb1r/pppppppp/7n/6N1/8/8/PPPPPPPP/RNBQKB1R w KQkq - 0 1");
+    play(&mut state, "g5f7");
+    assert_eq!(state.outcome(), Some(GameOutcome::Explosion(Parity::WHITE)));
+    assert!(state.moves.parity_vect(state.turn).is_empty());
+    let pgn = PgnGame::from_state(&state, state.outcome()).unwrap();
+    assert_eq!(pgn.tag("Variant"), Some("Atomic"));
+    let replayed = PgnGame::parse(&pgn.write()).unwrap().replay().unwrap();
+    assert_eq!(replayed.variant, Variant::Atomic);
+    assert_eq!(replayed.to_fen(), state.to_fen());
+}
+
+#[test]
+fn kings_never_capture_or_blow_themselves_up() {
+    let state = atomic("4k3/8/8/8/8/8/3pn3/3K4 w - - 0 1");
+    assert!(state.parse_uci("d1d2").is_err());
+    assert!(state.parse_uci("d1e2").is_err());
+    // Taking the checking pawn would blow up the king beside it
+    let state = atomic("4k3/8/8/8/8/8/3p4/1N2K3 w - - 0 1");
+    assert!(state.parse_uci("b1d2").is_err());
+    assert!(state.parse_uci("e1f2").is_ok());
+}
+
+#[test]
+fn touching_kings_are_never_in_check() {
+    // The rook would take the king in standard chess, but here that blows up its own king too
+    let fen = "8/8/8/8/8/3k4/3K4/3r4 w - - 0 1";
+    assert!(matches!(State::from_fen("8/8/8/8/8/3k4/3K4/3r4 b - - 0 1"), Err(FenError::SideNotToMoveInCheck)));
+    let state = atomic(fen);
+    assert!(!state.moves.parity_in_check(Parity::WHITE));
+    // Stepping away from the black king walks onto the rook's rank, but staying beside it is safe
+    assert!(state.parse_uci("d2e1").is_err());
+    assert!(state.parse_uci("d2c1").is_err());
+    assert!(state.parse_uci("d2e2").is_ok());
+    assert!(state.parse_uci("d2c2").is_ok());
+    assert!(atomic("8/8/8/8/8/3k4/3K4/3r4 b - - 0 1").moves.parity_vect(Parity::BLACK).len() > 0);
+}
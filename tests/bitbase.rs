@@ -0,0 +1,177 @@
+use std::sync::OnceLock;
+
+use chess::lib::{
+    bitbase::{self, Bitbase, BitbaseError, BitbaseValue, Endgame},
+    state::State
+};
+
+// Solving is quick for the three-piece endgames, so each test binary does it once
+fn solved() -> () {
+    static SOLVED: OnceLock<()> = OnceLock::new();
+    SOLVED.get_or_init(|| {
+        for endgame in [Endgame::KQK, Endgame::KRK, Endgame::KPK] {
+            bitbase::install(Bitbase::generate(endgame));
+        }
+    });
+}
+
+fn state(fen: &str) -> State {
+    return State::from_fen(fen).unwrap();
+}
+
+fn probe(fen: &str) -> Option<BitbaseValue> {
+    solved();
+    return bitbase::probe(&state(fen));
+}
+
+// One ply earlier, for the other side
+fn before(value: BitbaseValue) -> BitbaseValue {
+    return match value {
+        BitbaseValue::Win(plies) => BitbaseValue::Loss(plies + 1),
+        BitbaseValue::Loss(plies) => BitbaseValue::Win(plies + 1),
+        BitbaseValue::Draw => BitbaseValue::Draw
+    };
+}
+
+#[test]
+fn longest_mates() {
+    solved();
+    // Ten moves for the queen and sixteen for the rook, counted from the defender's move before them
+    assert_eq!(bitbase::installed(Endgame::KQK).unwrap().longest_mate(), 20);
+    assert_eq!(bitbase::installed(Endgame::KRK).unwrap().longest_mate(), 32);
+}
+
+#[test]
+fn known_positions() {
+    assert_eq!(probe("k7/7Q/1K6/8/8/8/8/8 w - - 0 1"), Some(BitbaseValue::Win(1)));
+    assert_eq!(probe("k7/1Q6/1K6/8/8/8/8/8 b - - 0 1"), Some(BitbaseValue::Loss(0)));
+    // Stalemate, and a queen left hanging
+    assert_eq!(probe("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1"), Some(BitbaseValue::Draw));
+    assert_eq!(probe("8/8/8/8/8/2k5/1Q6/7K b - - 0 1"), Some(BitbaseValue::Draw));
+    // The king in front of its pawn on the sixth wins whoever moves, a rook pawn doesn't
+    assert!(matches!(probe("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1"), Some(BitbaseValue::Win(_))));
+    assert!(matches!(probe("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1"), Some(BitbaseValue::Loss(_))));
+    assert_eq!(probe("k7/8/K7/P7/8/8/8/8 w - - 0 1"), Some(BitbaseValue::Draw));
+    // A king two squares ahead of its pawn wins, unless the defender is stalemated in front of it
+    assert!(matches!(probe("4k3/8/8/8/8/4K3/4P3/8 b - - 0 1"), Some(BitbaseValue::Loss(_))));
+    assert_eq!(probe("4k3/4P3/4K3/8/8/8/8/8 b - - 0 1"), Some(BitbaseValue::Draw));
+    // Black as the strong side reads the same tables
+    assert_eq!(probe("1q6/8/8/8/8/2k5/8/K7 b - - 0 1"), Some(BitbaseValue::Win(1)));
+    // Other material, castling rights and variants aren't covered
+    assert_eq!(probe("k7/8/1K6/8/8/8/8/1QQ5 w - - 0 1"), None);
+    assert_eq!(probe("4k3/8/8/8/8/8/8/4K2R w K - 0 1"), None);
+}
+
+// Checks each value against the best reply found with the usual move generator
+#[test]
+fn agrees_with_move_generation() {
+    solved();
+    let mut seed = 0x9E3779B97F4A7C15u64;
+    let mut random = || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        return seed;
+    };
+    let mut checked = 0;
+    while checked < 600 {
+        let pieces = ['Q', 'R', 'P'][checked % 3];
+        let mut board = ['.'; 64];
+        let squares = [random() % 64, random() % 64, random() % 64].map(|s| s as usize);
+        let white_strong = random() % 2 == 0;
+        let letters = if white_strong { ['K', 'k', pieces] } else { ['k', 'K', pieces.to_ascii_lowercase()] };
+        for (square, letter) in squares.iter().zip(letters) {
+            board[*square] = letter;
+        }
+        if squares[0] == squares[1] || squares[1] == squares[2] || squares[0] == squares[2] || (pieces == 'P' && (squares[2] < 8 || squares[2] >= 56)) {
+            continue;
+        }
+        let rows: Vec<String> = board.chunks(8).map(|row| {
+            let mut text = String::new();
+            let mut empty = 0;
+            for c in row {
+                if *c == '.' {
+                    empty += 1;
+                    continue;
+                }
+                if empty > 0 {
+                    text.push_str(&empty.to_string());
+                    empty = 0;
+                }
+                text.push(*c);
+            }
+            if empty > 0 {
+                text.push_str(&empty.to_string());
+            }
+            return text;
+        }).collect();
+        let fen = format!("{} {} - - 0 1", rows.join("/"), if random() % 2 == 0 { "w" } else { "b" });
+        let Ok(mut state) = State::from_fen(&fen) else { continue };
+
+        let moves = state.moves.parity_vect(state.turn);
+        let expected = if moves.is_empty() {
+            if state.moves.parity_in_check(state.turn) { BitbaseValue::Loss(0) } else { BitbaseValue::Draw }
+        } else {
+            let mut best = BitbaseValue::Loss(0);
+            for motion in &moves {
+                state.make_motion(motion, false);
+                // Anything the bitbases don't cover after a move is a bare king or a lone minor piece
+                let value = before(bitbase::probe(&state).unwrap_or(BitbaseValue::Draw));
+                state.unmake_last(true);
+                if value.score() > best.score() {
+                    best = value;
+                }
+            }
+            best
+        };
+        assert_eq!(bitbase::probe(&state), Some(expected), "{}", fen);
+        checked += 1;
+    }
+}
+
+#[test]
+fn root_moves() {
+    solved();
+    let mut mate = state("k7/7Q/1K6/8/8/8/8/8 w - - 0 1");
+    let best: Vec<String> = bitbase::best_moves(&mut mate).unwrap().iter().map(|m| mate.to_uci(m)).collect();
+    // Either mate will do, but not the stalemate
+    assert!(best.contains(&"h7b7".to_string()));
+    assert!(!best.contains(&"h7c7".to_string()));
+    // A queen mates soonest, and a minor piece would only draw
+    let mut promote = state("8/2P5/8/1k6/8/8/8/K7 w - - 0 1");
+    let best: Vec<String> = bitbase::best_moves(&mut promote).unwrap().iter().map(|m| promote.to_uci(m)).collect();
+    assert_eq!(best, vec!["c7c8q".to_string()]);
+}
+
+#[test]
+fn saving_bitbases() {
+    solved();
+    let kqk = bitbase::installed(Endgame::KQK).unwrap();
+    let bytes = kqk.to_bytes();
+    let read = Bitbase::from_bytes(&bytes).unwrap();
+    assert_eq!(read.endgame(), Endgame::KQK);
+    assert_eq!(read.to_bytes(), bytes);
+    let position = state("k7/7Q/1K6/8/8/8/8/8 w - - 0 1");
+    assert_eq!(read.probe(&position), kqk.probe(&position));
+    assert!(matches!(Bitbase::from_bytes(&bytes[..bytes.len() - 3]), Err(BitbaseError::Truncated)));
+    assert!(matches!(Bitbase::from_bytes(b"not a bitbase"), Err(BitbaseError::BadHeader)));
+}
+
+// Half a minute unoptimised, against a few seconds with --release
+#[test]
+#[cfg_attr(debug_assertions, ignore = "slow without optimisations")]
+fn kbnk() {
+    let kbnk = Bitbase::generate(Endgame::KBNK);
+    // Thirty-three moves from the worst start to mate in the bishop's corner
+    assert_eq!(kbnk.longest_mate(), 66);
+    let probe = |fen: &str| kbnk.probe(&state(fen));
+    assert_eq!(probe("7k/8/6KN/4B3/8/8/8/8 b - - 0 1"), Some(BitbaseValue::Loss(0)));
+    assert_eq!(probe("7k/8/6KN/8/5B2/8/8/8 w - - 0 1"), Some(BitbaseValue::Win(1)));
+    // The same mate with the colours swapped
+    assert_eq!(probe("8/8/8/8/4b3/6kn/8/7K w - - 0 1"), Some(BitbaseValue::Loss(0)));
+    // Taking the bishop leaves a knight that can't mate
+    assert_eq!(probe("8/8/8/8/8/2k5/1B6/N6K b - - 0 1"), Some(BitbaseValue::Draw));
+    // Anywhere else it's a win, however long
+    assert!(matches!(probe("8/8/8/3k4/8/8/8/KBN5 w - - 0 1"), Some(BitbaseValue::Win(plies)) if plies <= 66));
+    assert!(matches!(probe("8/8/8/3k4/8/8/8/KBN5 b - - 0 1"), Some(BitbaseValue::Loss(_))));
+}
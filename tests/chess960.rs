@@ -0,0 +1,89 @@
+use std::collections::HashSet;
+
+use chess::lib::{
+    chess960::{back_rank, start_fen, POSITION_COUNT, STANDARD_POSITION},
+    notation::NotationError,
+    pgn::PgnGame,
+    state::State
+};
+
+mod common;
+use common::{play, standard};
+
+fn check(fen: &str, expected: &[u64]) {
+    let state = standard(fen);
+    assert!(state.chess960);
+    assert_eq!(state.to_shredder_fen(), fen);
+    common::check(fen, expected);
+}
+
+#[test]
+fn generator_covers_every_setup() {
+    let mut seen = HashSet::new();
+    for n in 0..POSITION_COUNT {
+        let rank = back_rank(n);
+        let files = |piece: char| (0..8).filter(|i| rank[*i] == piece).collect::<Vec<usize>>();
+        let (bishops, rooks, king) = (files('B'), files('R'), files('K'));
+        assert_ne!(bishops[0] % 2, bishops[1] % 2, "bishops share a colour in {}", n);
+        assert!(rooks[0] < king[0] && king[0] < rooks[1], "king is not between the rooks in {}", n);
+        assert_eq!((files('Q').len(), files('N').len()), (1, 2));
+        assert!(seen.insert(rank));
+        let state = State::from_fen(&start_fen(n)).unwrap();
+        assert!(state.chess960);
+        assert_eq!(state.to_shredder_fen(), start_fen(n));
+    }
+    assert_eq!(back_rank(STANDARD_POSITION).iter().collect::<String>(), "RNBQKBNR");
+    assert_eq!(back_rank(0).iter().collect::<String>(), "BBQNNRKR");
+}
+
+#[test]
+fn castling_fields() {
+    let state = State::from_fen("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9").unwrap();
+    assert_eq!(state.info.castle_rooks, [7, 5, 63, 61]);
+    assert_eq!(state.to_fen(), "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9");
+    let xfen = State::from_fen(&state.to_fen()).unwrap();
+    assert_eq!(xfen.info.castle_rooks, state.info.castle_rooks);
+    assert_eq!(xfen.info.zkey, state.info.zkey);
+    // An inner rook needs its file even in X-FEN
+    let inner = State::from_fen("rk2r3/8/8/8/8/8/8/RK2R2R w Ee - 0 1").unwrap();
+    assert_eq!(inner.to_fen(), "rk2r3/8/8/8/8/8/8/RK2R2R w Ek - 0 1");
+    assert_eq!(inner.info.castle_rooks[2], 60);
+    assert!(State::from_fen("rk2r3/8/8/8/8/8/8/RK2R2R w C - 0 1").is_err());
+    assert!(!State::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap().chess960);
+}
+
+#[test]
+fn castling_moves_king_and_rook() {
+    let mut state = State::from_fen("r5kr/8/8/8/8/8/8/RK5R w AHah - 0 1").unwrap();
+    // King b1 takes rook a1, landing on c1 with the rook on d1
+    let castle = state.parse_uci("b1a1").unwrap();
+    assert!(state.is_castle(&castle));
+    assert_eq!(state.to_uci(&castle), "b1a1");
+    assert_eq!(state.to_san(&castle), "O-O-O");
+    play(&mut state, "b1a1");
+    assert_eq!(state.to_fen(), "r5kr/8/8/8/8/8/8/2KR3R b kq - 1 1");
+    // The king is already on g8, so only the rook moves
+    play(&mut state, "g8h8");
+    assert_eq!(state.to_fen(), "r4rk1/8/8/8/8/8/8/2KR3R w - - 2 2");
+    assert_eq!(State::from_fen(&state.to_fen()).unwrap().info.zkey, state.info.zkey);
+    let pgn = PgnGame::from_state(&state, None).unwrap();
+    assert_eq!(pgn.tag("Variant"), Some("Chess960"));
+    assert_eq!(PgnGame::parse(&pgn.write()).unwrap().replay().unwrap().to_fen(), state.to_fen());
+    state.unmake_last(true);
+    state.unmake_last(true);
+    assert_eq!(state.to_fen(), "r5kr/8/8/8/8/8/8/RK5R w KQkq - 0 1");
+}
+
+#[test]
+fn castling_rook_cannot_leave_king_exposed() {
+    // The b1 rook shields c1 from the queen, and c1 is where the king ends up
+    let state = State::from_fen("6k1/8/8/8/8/8/8/qRK5 w B - 0 1").unwrap();
+    assert!(matches!(state.parse_uci("c1b1"), Err(NotationError::Illegal(_))));
+}
+
+#[test]
+fn perft_positions() {
+    check("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", &[21, 528, 12189, 326672]);
+    check("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9", &[21, 807, 18002]);
+    check("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9", &[20, 479, 10471]);
+}
@@ -0,0 +1,34 @@
+// Each test binary builds its own copy and uses only some of these
+#![allow(dead_code)]
+
+use chess::lib::state::State;
+
+pub fn standard(fen: &str) -> State {
+    return State::from_fen(fen).unwrap();
+}
+
+pub fn play(state: &mut State, uci: &str) {
+    let motion = state.parse_uci(uci).unwrap();
+    state.make_motion(&motion, false);
+}
+
+pub fn after(fen: &str, moves: &[&str]) -> State {
+    let mut state = standard(fen);
+    for uci in moves {
+        play(&mut state, uci);
+    }
+    return state;
+}
+
+pub fn check(fen: &str, expected: &[u64]) {
+    check_with(standard, fen, expected);
+}
+
+pub fn check_with(build: fn(&str) -> State, fen: &str, expected: &[u64]) {
+    let mut state = build(fen);
+    for (depth, nodes) in expected.iter().enumerate() {
+        assert_eq!(state.perft(depth as u32 + 1), *nodes, "{} at depth {}", fen, depth + 1);
+    }
+    // Perft unmakes everything it makes
+    assert_eq!(state.to_fen(), build(fen).to_fen());
+}
@@ -0,0 +1,112 @@
+use chess::lib::{
+    fen::{FenError, STARTING_FEN},
+    motion::Motion,
+    pgn::PgnGame,
+    piece::{Parity, PieceByte},
+    state::State,
+    variant::Variant
+};
+
+mod common;
+use common::play;
+
+fn crazyhouse(fen: &str) -> State {
+    return State::from_variant_fen(fen, Variant::Crazyhouse).unwrap();
+}
+
+#[test]
+fn perft_startpos() {
+    let mut state = crazyhouse(STARTING_FEN);
+    assert_eq!(state.to_fen(), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1");
+    for (depth, nodes) in [20, 400, 8902].iter().enumerate() {
+        assert_eq!(state.perft(depth as u32 + 1), *nodes, "depth {}", depth + 1);
+    }
+}
+
+#[test]
+fn drops_go_on_empty_squares() {
+    // Five king moves and a knight for each of the 62 empty squares
+    let state = crazyhouse("4k3/8/8/8/8/8/8/4K3[Nn] w - - 0 1");
+    assert_eq!(state.moves.parity_vect(Parity::WHITE).len(), 67);
+    // Pawns skip the first and last ranks
+    let state = crazyhouse("4k3/8/8/8/8/8/8/4K3[P] w - - 0 1");
+    assert_eq!(state.moves.parity_vect(Parity::WHITE).len(), 53);
+    assert!(state.parse_uci("P@e8").is_err());
+    assert!(state.parse_uci("P@a1").is_err());
+    // A check along the first rank can be blocked on b1, c1 or d1
+    let state = crazyhouse("4k3/8/8/8/8/8/8/r3K3[N] w - - 0 1");
+    let drops: Vec<Motion> = state.moves.parity_vect(Parity::WHITE).into_iter().filter(|m| m.is_drop()).collect();
+    assert_eq!(drops, vec![Motion::drop(PieceByte::KNIGHT, 57), Motion::drop(PieceByte::KNIGHT, 58), Motion::drop(PieceByte::KNIGHT, 59)]);
+    // but a knight's check can't be blocked at all
+    let state = crazyhouse("4k3/8/8/8/8/3n4/8/4K3[QRBNP] w - - 0 1");
+    assert!(state.moves.parity_vect(Parity::WHITE).iter().all(|m| !m.is_drop()));
+}
+
+#[test]
+fn captures_fill_the_pocket() {
+    let fen = "4k3/8/8/8/8/8/3q~4/4K3[] w - - 0 1";
+    let mut state = crazyhouse(fen);
+    let before = state.info.zkey;
+    // The queen started life as a pawn, so that is what white gets
+    play(&mut state, "e1d2");
+    assert_eq!(state.to_fen(), "4k3/8/8/8/8/8/3K4/8[P] b - - 0 1");
+    assert_eq!(state.pocket(Parity::WHITE), [1, 0, 0, 0, 0]);
+    assert_eq!(state.info.zkey, crazyhouse(&state.to_fen()).info.zkey);
+    play(&mut state, "e8e7");
+    play(&mut state, "P@e6");
+    assert_eq!(state.to_fen(), "8/4k3/4P3/8/8/8/3K4/8[] b - - 2 2");
+    assert_eq!(state.info.zkey, crazyhouse(&state.to_fen()).info.zkey);
+    state.unmake_last(true);
+    state.unmake_last(true);
+    state.unmake_last(true);
+    assert_eq!(state.to_fen(), fen);
+    assert_eq!(state.info.zkey, before);
+
+    // Promoting marks the new piece, and the mark survives in the FEN
+    let mut state = crazyhouse("r3k3/1P6/8/8/8/8/8/4K3[] w - - 0 1");
+    play(&mut state, "b7a8q");
+    assert_eq!(state.to_fen(), "Q~3k3/8/8/8/8/8/8/4K3[R] b - - 0 1");
+}
+
+#[test]
+fn drop_notation() {
+    let mut state = crazyhouse("4k3/8/8/8/8/8/8/4K3[NPn] w - - 0 1");
+    let knight = Motion::drop(PieceByte::KNIGHT, 44);
+    assert_eq!(state.to_uci(&knight), "N@e3");
+    assert_eq!(knight.to_string(), "N@e3");
+    assert_eq!(state.parse_uci("N@e3"), Ok(knight));
+    assert_eq!(state.to_san(&Motion::drop(PieceByte::KNIGHT, 21)), "N@f6+");
+    assert_eq!(state.parse_san("N@f6+"), Ok(Motion::drop(PieceByte::KNIGHT, 21)));
+    let pawn = Motion::drop(PieceByte::PAWN, 36);
+    assert_eq!(state.to_san(&pawn), "P@e4");
+    assert_eq!(state.parse_san("@e4"), Ok(pawn));
+    assert_eq!(state.parse_uci("P@e4"), Ok(pawn));
+    // Nothing of the kind in hand
+    assert!(state.parse_san("Q@e4").is_err());
+}
+
+#[test]
+fn pocket_fens_and_pgn() {
+    // Pockets may also be written as a ninth rank, and they switch the variant on
+    let state = State::from_fen("4k3/8/8/8/8/8/8/4K3/QRbp w - - 0 1").unwrap();
+    assert_eq!(state.variant, Variant::Crazyhouse);
+    assert_eq!(state.to_fen(), "4k3/8/8/8/8/8/8/4K3[QRbp] w - - 0 1");
+    assert!(matches!(State::from_fen("4k3/8/8/8/8/8/8/4K3[X] w - - 0 1"), Err(FenError::BadPocket(_))));
+    // Pawns come back as drops, so more than eight is fine
+    assert!(State::from_fen("4k3/8/pppp4/pppppppp/8/8/8/4K3 w - - 0 1").is_err());
+    assert!(crazyhouse("4k3/8/pppp4/pppppppp/8/8/8/4K3 w - - 0 1").to_fen().starts_with("4k3/8/pppp4/pppppppp/8/8/8/4K3[]"));
+
+    let mut state = crazyhouse(STARTING_FEN);
+    for uci in ["e2e4", "d7d5", "e4d5", "d8d5", "b1c3", "d5a5", "P@d7"] {
+        play(&mut state, uci);
+    }
+    assert!(state.moves.parity_in_check(Parity::BLACK));
+    let pgn = PgnGame::from_state(&state, None).unwrap();
+    assert_eq!(pgn.tag("Variant"), Some("Crazyhouse"));
+    assert_eq!(pgn.tag("FEN"), None);
+    let text = pgn.write();
+    assert!(text.contains("P@d7+"));
+    let replayed = PgnGame::parse(&text).unwrap().replay().unwrap();
+    assert_eq!(replayed.variant, Variant::Crazyhouse);
+    assert_eq!(replayed.to_fen(), state.to_fen());
+}
@@ -0,0 +1,40 @@
+use chess::lib::{engine::Engine, fen, state::State};
+
+mod common;
+use common::after;
+
+fn key_after(moves: &[&str]) -> u64 {
+    return after(fen::STARTING_FEN, moves).info.zkey;
+}
+
+#[test]
+fn undo_keeps_earlier_occurrences() {
+    let mut engine = Engine::new();
+    // The knights go out and back, so the position after g1f3 comes up twice
+    engine.set_position(fen::STARTING_FEN, &["g1f3", "g8f6", "f3g1", "f6g8", "g1f3"]).unwrap();
+    let knight_out = key_after(&["g1f3"]);
+    assert!(engine.undo());
+    assert!(engine.seen(knight_out));
+    assert!(engine.seen(key_after(&[])));
+    // Once every occurrence is taken back it's forgotten
+    for _ in 0..4 {
+        assert!(engine.undo());
+    }
+    assert!(!engine.seen(knight_out));
+    assert!(!engine.seen(key_after(&["g1f3", "g8f6"])));
+    assert!(!engine.undo());
+    assert!(engine.seen(key_after(&[])));
+}
+
+#[test]
+fn position_keys() {
+    let mut state = State::from_fen(fen::STARTING_FEN).unwrap();
+    assert_eq!(state.position_keys(), vec![key_after(&[])]);
+    for uci in ["e2e4", "e7e5"] {
+        let motion = state.parse_uci(uci).unwrap();
+        state.make_motion(&motion, false);
+    }
+    assert_eq!(state.position_keys(), vec![key_after(&[]), key_after(&["e2e4"]), key_after(&["e2e4", "e7e5"])]);
+    state.unmake_last(true);
+    assert_eq!(state.position_keys(), vec![key_after(&[]), key_after(&["e2e4"])]);
+}
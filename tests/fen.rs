@@ -0,0 +1,48 @@
+use chess::lib::{
+    fen::{FenError, SAMPLE_FENS},
+    state::State
+};
+
+#[test]
+fn sample_fens_round_trip() {
+    for fen in SAMPLE_FENS {
+        let state = State::from_fen(fen).unwrap();
+        assert_eq!(state.to_fen(), fen);
+        let again = State::from_fen(&state.to_fen()).unwrap();
+        assert_eq!(again.board, state.board);
+        assert_eq!(again.turn, state.turn);
+        assert_eq!(again.info.allowed_castles, state.info.allowed_castles);
+        assert_eq!(again.info.enpassant_mask.raw, state.info.enpassant_mask.raw);
+        assert_eq!(again.info.halfmove_clock, state.info.halfmove_clock);
+        assert_eq!(again.info.fullmove_number, state.info.fullmove_number);
+    }
+}
+
+#[test]
+fn round_trip_after_moves() {
+    let mut state = State::from_fen(SAMPLE_FENS[0]).unwrap();
+    // e2e4, then c7c5
+    state.make_motion(&state.moves.parity_vect(state.turn).iter().find(|m| m.from == 52 && m.to == 36).unwrap().clone(), false);
+    assert_eq!(state.to_fen(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+    state.make_motion(&state.moves.parity_vect(state.turn).iter().find(|m| m.from == 10 && m.to == 26).unwrap().clone(), false);
+    assert_eq!(state.to_fen(), "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq c6 0 2");
+    assert_eq!(State::from_fen(&state.to_fen()).unwrap().info.zkey, state.info.zkey);
+}
+
+#[test]
+fn missing_fields_default() {
+    let state = State::from_fen("4k3/8/8/8/8/8/8/4K3 w - -").unwrap();
+    assert_eq!(state.to_fen(), "4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+}
+
+#[test]
+fn invalid_fens_are_rejected() {
+    let start = SAMPLE_FENS[0];
+    assert_eq!(State::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBN w KQkq - 0 1").err(), Some(FenError::BadRankLength { rank: 1, length: 7 }));
+    assert_eq!(State::from_fen(&start.replace("RNBQ", "RXBQ")).err(), Some(FenError::UnknownPiece('X')));
+    assert!(matches!(State::from_fen(&start.replace("KQkq", "KQkx")), Err(FenError::BadCastling(_))));
+    assert!(matches!(State::from_fen(&start.replace(" - ", " e3 ")), Err(FenError::ImpossibleEnPassant(_))));
+    assert!(matches!(State::from_fen("4k3/8/8/8/8/8/8/8 w - - 0 1"), Err(FenError::MissingKing(_))));
+    assert_eq!(State::from_fen("4k3/8/8/8/8/8/8/4K2r b - - 0 1").err(), Some(FenError::SideNotToMoveInCheck));
+    assert!(matches!(State::from_fen("4k3/8/8/8/8/P7/PPPPPPPP/4K3 w - - 0 1"), Err(FenError::TooManyPawns(_))));
+}
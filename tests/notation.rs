@@ -0,0 +1,98 @@
+use chess::lib::{
+    fen::STARTING_FEN,
+    notation::NotationError,
+    state::State
+};
+
+mod common;
+use common::after;
+
+// Writes the move given in UCI as SAN, and checks that the SAN reads back as the same move
+fn san(state: &mut State, uci: &str) -> String {
+    let motion = state.parse_uci(uci).unwrap();
+    let san = state.to_san(&motion);
+    assert_eq!(state.parse_san(&san), Ok(motion), "{}", san);
+    return san;
+}
+
+#[test]
+fn disambiguation() {
+    // Rooks on the same rank are told apart by file
+    let mut state = State::from_fen("4k3/8/8/8/8/8/7K/R6R w - - 0 1").unwrap();
+    assert_eq!(san(&mut state, "a1d1"), "Rad1");
+    assert_eq!(san(&mut state, "h1d1"), "Rhd1");
+    assert_eq!(san(&mut state, "a1a5"), "Ra5");
+    // On the same file, by rank
+    let mut state = State::from_fen("4k3/8/8/R7/8/8/7K/R7 w - - 0 1").unwrap();
+    assert_eq!(san(&mut state, "a1a3"), "R1a3");
+    assert_eq!(san(&mut state, "a5a3"), "R5a3");
+    // With one rival on the same file and another on the same rank it takes the whole square
+    let mut state = State::from_fen("6k1/8/8/8/8/Q7/8/Q1Q4K w - - 0 1").unwrap();
+    assert_eq!(san(&mut state, "a1b2"), "Qa1b2");
+    assert_eq!(san(&mut state, "a3b2"), "Q3b2");
+    assert_eq!(san(&mut state, "c1b2"), "Qcb2");
+    // Pawn captures always give the file they came from
+    let mut state = after(STARTING_FEN, &["e2e4", "d7d5"]);
+    assert_eq!(san(&mut state, "e4d5"), "exd5");
+}
+
+#[test]
+fn check_and_mate() {
+    let mut state = after(STARTING_FEN, &["e2e4", "f7f6"]);
+    assert_eq!(san(&mut state, "d1h5"), "Qh5+");
+    let mut state = after(STARTING_FEN, &["f2f3", "e7e5", "g2g4"]);
+    assert_eq!(san(&mut state, "d8h4"), "Qh4#");
+    // Suffixes and annotations are optional when reading
+    let qh4 = state.parse_uci("d8h4").unwrap();
+    for written in ["Qh4", "Qh4+", "Qh4#", "Qh4!?", "Qd8h4"] {
+        assert_eq!(state.parse_san(written), Ok(qh4), "{}", written);
+    }
+}
+
+#[test]
+fn castling() {
+    let mut state = State::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+    assert_eq!(san(&mut state, "e1g1"), "O-O");
+    assert_eq!(san(&mut state, "e1c1"), "O-O-O");
+    let short = state.parse_uci("e1g1").unwrap();
+    let long = state.parse_uci("e1c1").unwrap();
+    // Both spellings, and king takes rook in UCI as well as the king's destination
+    assert_eq!(state.parse_san("0-0"), Ok(short));
+    assert_eq!(state.parse_san("0-0-0"), Ok(long));
+    assert_eq!(state.parse_uci("e1h1"), Ok(short));
+    assert_eq!(state.to_uci(&short), "e1g1");
+    assert_eq!(state.to_uci(&long), "e1c1");
+    let mut state = after("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", &["a1a2"]);
+    assert_eq!(san(&mut state, "e8c8"), "O-O-O");
+    // Without the rights there's nothing to castle
+    let state = State::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w - - 0 1").unwrap();
+    assert_eq!(state.parse_san("O-O"), Err(NotationError::Illegal("O-O".to_string())));
+}
+
+#[test]
+fn promotion() {
+    let mut state = State::from_fen("7k/P7/8/8/8/8/8/K7 w - - 0 1").unwrap();
+    assert_eq!(san(&mut state, "a7a8q"), "a8=Q+");
+    assert_eq!(san(&mut state, "a7a8n"), "a8=N");
+    let queen = state.parse_uci("a7a8q").unwrap();
+    assert_eq!(state.parse_san("a8Q"), Ok(queen));
+    assert_eq!(state.to_uci(&queen), "a7a8q");
+    // A promotion has to say what it promotes to
+    assert_eq!(state.parse_san("a8"), Err(NotationError::Illegal("a8".to_string())));
+    assert_eq!(state.parse_uci("a7a8"), Err(NotationError::Illegal("a7a8".to_string())));
+}
+
+#[test]
+fn errors() {
+    let state = after(STARTING_FEN, &["g1f3", "d7d5", "d2d3", "g8f6"]);
+    assert_eq!(state.parse_san("Nd2"), Err(NotationError::Ambiguous("Nd2".to_string())));
+    assert!(state.parse_san("Nbd2").is_ok() && state.parse_san("Nfd2").is_ok());
+    assert_eq!(state.parse_san("Ke3"), Err(NotationError::Illegal("Ke3".to_string())));
+    assert_eq!(state.parse_san("Qh5"), Err(NotationError::Illegal("Qh5".to_string())));
+    assert_eq!(state.parse_san("Zz9"), Err(NotationError::Malformed("Zz9".to_string())));
+    assert_eq!(state.parse_san("N"), Err(NotationError::Malformed("N".to_string())));
+    assert_eq!(state.parse_uci("e2e5"), Err(NotationError::Illegal("e2e5".to_string())));
+    assert_eq!(state.parse_uci("e2"), Err(NotationError::Malformed("e2".to_string())));
+    assert_eq!(state.parse_uci("e2e9"), Err(NotationError::Malformed("e2e9".to_string())));
+    assert_eq!(NotationError::Ambiguous("Nd2".to_string()).to_string(), "ambiguous move \"Nd2\"");
+}
@@ -0,0 +1,79 @@
+use chess::lib::{
+    fen::STARTING_FEN,
+    outcome::GameOutcome,
+    piece::Parity,
+    state::State
+};
+
+mod common;
+use common::after;
+
+fn outcome(fen: &str) -> Option<GameOutcome> {
+    return State::from_fen(fen).unwrap().outcome();
+}
+
+#[test]
+fn checkmate_and_stalemate() {
+    let mated = after(STARTING_FEN, &["f2f3", "e7e5", "g2g4", "d8h4"]);
+    assert_eq!(mated.outcome(), Some(GameOutcome::Checkmate(Parity::BLACK)));
+    assert_eq!(mated.outcome().unwrap().to_string(), "BLACK WINS by checkmate");
+    assert_eq!(outcome("k7/1Q6/1K6/8/8/8/8/8 b - - 0 1"), Some(GameOutcome::Checkmate(Parity::WHITE)));
+    assert_eq!(outcome("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1"), Some(GameOutcome::Stalemate));
+    assert_eq!(outcome("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1").unwrap().result(), "1/2-1/2");
+    // In check with a way out is neither
+    assert_eq!(outcome("k7/8/1K6/8/8/8/8/Q7 b - - 0 1"), None);
+    assert_eq!(outcome(STARTING_FEN), None);
+}
+
+#[test]
+fn threefold_repetition() {
+    let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+    let twice = after(STARTING_FEN, &shuffle);
+    assert_eq!(twice.repetitions(), 2);
+    assert_eq!(twice.outcome(), None);
+    let mut moves = shuffle.to_vec();
+    moves.extend(&shuffle[..3]);
+    assert_eq!(after(STARTING_FEN, &moves).outcome(), None);
+    moves.push(shuffle[3]);
+    let thrice = after(STARTING_FEN, &moves);
+    assert_eq!(thrice.repetitions(), 3);
+    assert_eq!(thrice.outcome(), Some(GameOutcome::ThreefoldRepetition));
+    // A pawn move in between means the earlier positions can't come back
+    let reset = after(STARTING_FEN, &["g1f3", "g8f6", "f3g1", "f6g8", "e2e3", "e7e6", "g1f3", "g8f6", "f3g1", "f6g8"]);
+    assert_eq!(reset.repetitions(), 2);
+    assert_eq!(reset.outcome(), None);
+}
+
+#[test]
+fn fifty_move_rule() {
+    let fen = "4k3/8/8/8/8/8/R7/4K3 w - - 99 80";
+    assert_eq!(outcome(fen), None);
+    assert_eq!(after(fen, &["a2a3"]).outcome(), Some(GameOutcome::FiftyMoveRule));
+    // Mate on the hundredth half move still counts as mate
+    assert_eq!(outcome("k7/1Q6/1K6/8/8/8/8/8 b - - 100 80"), Some(GameOutcome::Checkmate(Parity::WHITE)));
+}
+
+#[test]
+fn insufficient_material() {
+    for fen in [
+        "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+        "4k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+        "4k3/8/8/8/8/8/8/1N2K3 w - - 0 1",
+        "4kn2/8/8/8/8/8/8/4K3 w - - 0 1",
+        // c1 and f8 are both dark squares
+        "4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1",
+        "4k3/8/8/8/8/8/3B4/2B1K3 w - - 0 1"
+    ] {
+        assert_eq!(outcome(fen), Some(GameOutcome::InsufficientMaterial), "{}", fen);
+    }
+    for fen in [
+        // Bishops on opposite colours, two knights, a knight against a bishop, and any pawn or major piece can mate
+        "2b1k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+        "4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1",
+        "4kn2/8/8/8/8/8/8/2B1K3 w - - 0 1",
+        "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1",
+        "4k3/8/8/8/8/8/8/R3K3 w - - 0 1"
+    ] {
+        assert_eq!(outcome(fen), None, "{}", fen);
+    }
+}
@@ -0,0 +1,63 @@
+use chess::lib::{fen::STARTING_FEN, state::State};
+
+mod common;
+use common::check;
+
+const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+const POSITION_3: &str = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
+const POSITION_4: &str = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+const POSITION_5: &str = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8";
+const POSITION_6: &str = "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10";
+
+#[test]
+fn startpos() {
+    check(STARTING_FEN, &[20, 400, 8902, 197281]);
+}
+
+#[test]
+fn kiwipete() {
+    check(KIWIPETE, &[48, 2039, 97862]);
+}
+
+#[test]
+fn position_3() {
+    check(POSITION_3, &[14, 191, 2812, 43238, 674624]);
+}
+
+#[test]
+fn position_4() {
+    check(POSITION_4, &[6, 264, 9467, 422333]);
+}
+
+#[test]
+fn position_5() {
+    check(POSITION_5, &[44, 1486, 62379]);
+}
+
+#[test]
+fn position_6() {
+    check(POSITION_6, &[46, 2079, 89890]);
+}
+
+#[test]
+fn divide_sums_to_perft() {
+    let mut state = State::from_fen(KIWIPETE).unwrap();
+    let split = state.divide(2);
+    assert_eq!(split.len(), 48);
+    assert_eq!(split.iter().map(|(_, nodes)| nodes).sum::<u64>(), 2039);
+}
+
+#[test]
+fn unmaking_puts_the_motions_back() {
+    let mut state = State::from_fen(KIWIPETE).unwrap();
+    let mut sorted = state.moves.parity_vect(state.turn);
+    sorted.reverse();
+    state.set_sorted_motions(sorted.clone());
+    let motion = state.parse_uci("e2a6").unwrap();
+    state.make_motion(&motion, false);
+    // With the cache gone, only the held motions could still be in the order they were sorted
+    state.cached_moves.clear();
+    state.unmake_last(true);
+    assert_eq!(state.moves.parity_vect(state.turn), sorted);
+    assert_eq!(state.to_fen(), KIWIPETE);
+}
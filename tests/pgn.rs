@@ -0,0 +1,121 @@
+use chess::lib::{
+    fen::STARTING_FEN,
+    notation::NotationError,
+    outcome::GameOutcome,
+    pgn::{PgnError, PgnGame},
+    piece::Parity,
+    state::State
+};
+
+mod common;
+use common::after;
+
+const ANNOTATED: &str = r#"[Event "Casual \"blitz\" game"]
+[Site "?"]
+[White "Anderssen"]
+[Black "Kieseritzky"]
+[Result "1-0"]
+
+{Before the first move} 1. e4 e5 2. f4!? exf4 $6 3. Bc4 (3. Nf3 g5 (3... d6) 4. h4) 3... Qh4+ ; the king has to move
+4. Kf1 {forced} b5?! 1-0
+"#;
+
+#[test]
+fn tags_comments_and_nags() {
+    let game = PgnGame::parse(ANNOTATED).unwrap();
+    assert_eq!(game.tag("Event"), Some("Casual \"blitz\" game"));
+    assert_eq!(game.tag("White"), Some("Anderssen"));
+    assert_eq!(game.tag("Round"), None);
+    assert_eq!(game.result(), "1-0");
+    let sans: Vec<&str> = game.moves.iter().map(|m| m.san.as_str()).collect();
+    assert_eq!(sans, vec!["e4", "e5", "f4", "exf4", "Bc4", "Qh4+", "Kf1", "b5"]);
+    assert_eq!(game.moves[0].comment_before.as_deref(), Some("Before the first move"));
+    // Suffixes and numeric annotations both become NAGs
+    assert_eq!(game.moves[2].nags, vec![5]);
+    assert_eq!(game.moves[3].nags, vec![6]);
+    assert_eq!(game.moves[7].nags, vec![6]);
+    assert_eq!(game.moves[5].comment.as_deref(), Some("the king has to move"));
+    assert_eq!(game.moves[6].comment.as_deref(), Some("forced"));
+    assert_eq!(game.replay().unwrap().to_fen(), after(STARTING_FEN, &["e2e4", "e7e5", "f2f4", "e5f4", "f1c4", "d8h4", "e1f1", "b7b5"]).to_fen());
+}
+
+#[test]
+fn nested_variations() {
+    let game = PgnGame::parse(ANNOTATED).unwrap();
+    // 3. Nf3 stands in for 3. Bc4, and 3... d6 for 3... g5 inside it
+    let alternatives = &game.moves[4].variations;
+    assert_eq!(alternatives.len(), 1);
+    let sans: Vec<&str> = alternatives[0].iter().map(|m| m.san.as_str()).collect();
+    assert_eq!(sans, vec!["Nf3", "g5", "h4"]);
+    assert_eq!(alternatives[0][1].variations.len(), 1);
+    assert_eq!(alternatives[0][1].variations[0][0].san, "d6");
+    assert!(game.moves.iter().enumerate().all(|(i, m)| i == 4 || m.variations.is_empty()));
+}
+
+#[test]
+fn results_and_several_games() {
+    let games = PgnGame::parse_all("1. e4 e5 1/2-1/2\n\n[Result \"0-1\"]\n1. f3 e5 2. g4 Qh4# 0-1\n\n1. d4 *\n").unwrap();
+    assert_eq!(games.len(), 3);
+    assert_eq!(games[0].result(), "1/2-1/2");
+    assert_eq!(games[1].result(), "0-1");
+    assert_eq!(games[1].moves.last().unwrap().san, "Qh4#");
+    assert_eq!(games[2].result(), "*");
+    assert_eq!(games[2].moves.len(), 1);
+    // Move numbers can be glued to the move
+    assert_eq!(PgnGame::parse("1.e4 1...e5 2.Nf3").unwrap().moves.len(), 3);
+}
+
+#[test]
+fn errors() {
+    assert_eq!(PgnGame::parse(""), Err(PgnError::NoGame));
+    assert_eq!(PgnGame::parse("[Event \"unclosed]"), Err(PgnError::BadTag("[Event \"unclosed]".to_string())));
+    assert_eq!(PgnGame::parse("1. e4 {never closed"), Err(PgnError::UnterminatedComment));
+    assert_eq!(PgnGame::parse("1. e4 (1. d4"), Err(PgnError::UnbalancedVariation));
+    assert_eq!(PgnGame::parse("1. e4 e5)"), Err(PgnError::UnbalancedVariation));
+    assert_eq!(PgnGame::parse("(1. e4)"), Err(PgnError::UnbalancedVariation));
+    assert!(matches!(PgnGame::parse("[FEN \"8/8/8\"]\n1. e4"), Err(PgnError::BadFen(_))));
+    assert_eq!(PgnGame::parse("1. e4 e5 2. Ke3"), Err(PgnError::IllegalMove {
+        move_number: 2,
+        parity: Parity::WHITE,
+        error: NotationError::Illegal("Ke3".to_string())
+    }));
+    // Both knights can reach d2
+    let ambiguous = PgnGame::parse("1. Nf3 d5 2. d3 Nf6 3. Nd2").unwrap_err();
+    assert_eq!(ambiguous, PgnError::IllegalMove { move_number: 3, parity: Parity::WHITE, error: NotationError::Ambiguous("Nd2".to_string()) });
+    assert_eq!(ambiguous.to_string(), "3. ambiguous move \"Nd2\"");
+    assert!(matches!(PgnGame::parse("1. e4 Nbd7"), Err(PgnError::IllegalMove { move_number: 1, parity: Parity::BLACK, .. })));
+}
+
+#[test]
+fn write_then_parse() {
+    let game = PgnGame::parse(ANNOTATED).unwrap();
+    let again = PgnGame::parse(&game.write()).unwrap();
+    assert_eq!(again, game);
+
+    let state = after(STARTING_FEN, &["f2f3", "e7e5", "g2g4", "d8h4"]);
+    let outcome = state.outcome();
+    assert_eq!(outcome, Some(GameOutcome::Checkmate(Parity::BLACK)));
+    let game = PgnGame::from_state(&state, outcome).unwrap();
+    let written = game.write();
+    assert!(written.contains("[Result \"0-1\"]"));
+    assert!(written.ends_with("1. f3 e5 2. g4 Qh4# {Black wins by checkmate} 0-1\n"));
+    let again = PgnGame::parse(&written).unwrap();
+    assert_eq!(again, game);
+    assert_eq!(again.replay().unwrap().to_fen(), state.to_fen());
+}
+
+#[test]
+fn set_up_positions() {
+    // Black to move first, so the movetext opens with a numbered black move
+    let fen = "4k3/8/8/8/8/8/4P3/4K3 b - - 0 12";
+    let mut state = State::from_fen(fen).unwrap();
+    for uci in ["e8d7", "e2e4"] {
+        let motion = state.parse_uci(uci).unwrap();
+        state.make_motion(&motion, false);
+    }
+    let game = PgnGame::from_state(&state, None).unwrap();
+    assert_eq!(game.tag("SetUp"), Some("1"));
+    assert_eq!(game.tag("FEN"), Some(fen));
+    assert!(game.write().ends_with("12... Kd7 13. e4 *\n"));
+    assert_eq!(PgnGame::parse(&game.write()).unwrap(), game);
+}
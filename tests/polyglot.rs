@@ -0,0 +1,144 @@
+use std::sync::Arc;
+
+use chess::lib::{
+    fen::STARTING_FEN,
+    pgn::PgnGame,
+    piece::Parity,
+    polyglot::{decode_motion, encode_motion, polyglot_key, Book, BookBuilder, BookError, BookSelection, BookSettings},
+    state::State
+};
+
+fn entry(key: u64, motion: u16, weight: u16) -> Vec<u8> {
+    return [&key.to_be_bytes()[..], &motion.to_be_bytes(), &weight.to_be_bytes(), &0u32.to_be_bytes()].concat();
+}
+
+fn uci_motion(state: &State, uci: &str) -> u16 {
+    return encode_motion(&state.parse_uci(uci).unwrap());
+}
+
+fn key_after(sans: &[&str]) -> u64 {
+    let mut state = State::from_fen(STARTING_FEN).unwrap();
+    for san in sans {
+        let motion = state.parse_san(san).unwrap();
+        state.make_motion(&motion, false);
+    }
+    return polyglot_key(&state);
+}
+
+#[test]
+fn reference_keys() {
+    // The published examples from the Polyglot book format
+    assert_eq!(key_after(&[]), 0x463b96181691fc9c);
+    assert_eq!(key_after(&["e4"]), 0x823c9b50fd114196);
+    assert_eq!(key_after(&["e4", "d5"]), 0x0756b94461c50fb0);
+    assert_eq!(key_after(&["e4", "d5", "e5"]), 0x662fafb965db29d4);
+    // f5 can be taken en passant, so the square counts
+    assert_eq!(key_after(&["e4", "d5", "e5", "f5"]), 0x22a48b5a8e47ff78);
+    assert_eq!(key_after(&["e4", "d5", "e5", "f5", "Ke2"]), 0x652a607ca3f242c1);
+    assert_eq!(key_after(&["e4", "d5", "e5", "f5", "Ke2", "Kf7"]), 0x00fdd303c946bdd9);
+    assert_eq!(key_after(&["a4", "b5", "h4", "b4", "c4"]), 0x3c8123ea7b067637);
+    assert_eq!(key_after(&["a4", "b5", "h4", "b4", "c4", "bxc3", "Ra3"]), 0x5c3f9b829b279560);
+}
+
+#[test]
+fn unreachable_passant_is_ignored() {
+    // After 1. e4 nothing can take on e3, so the key matches the same position written without it
+    let with = State::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").unwrap();
+    let without = State::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1").unwrap();
+    assert_eq!(polyglot_key(&with), polyglot_key(&without));
+    assert_eq!(polyglot_key(&with), 0x823c9b50fd114196);
+}
+
+#[test]
+fn motion_encoding() {
+    let state = State::from_fen("r3k2r/1P6/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+    // Castling is the king taking its rook, e1h1, in both encodings
+    assert_eq!(uci_motion(&state, "e1g1"), 0x0107);
+    assert_eq!(uci_motion(&state, "e1c1"), 0x0100);
+    // Promotions carry the piece in bits 12-14, queen being 4
+    let queen = uci_motion(&state, "b7a8q");
+    assert_eq!(queen >> 12, 4);
+    assert_eq!(decode_motion(&state, queen), state.parse_uci("b7a8q").ok());
+    assert_eq!(decode_motion(&state, uci_motion(&state, "b7b8n")), state.parse_uci("b7b8n").ok());
+    // e2e4 is not legal here
+    assert_eq!(decode_motion(&state, 0x031c), None);
+}
+
+#[test]
+fn book_probing() {
+    let state = State::from_fen(STARTING_FEN).unwrap();
+    let start = polyglot_key(&state);
+    let mut bytes = [
+        entry(start, uci_motion(&state, "d2d4"), 5),
+        entry(start, uci_motion(&state, "e2e4"), 10),
+        // An illegal move from a colliding or broken book is never offered
+        entry(start, 0x0224, 100),
+        entry(0x823c9b50fd114196, 0, 1)
+    ].concat();
+    let book = Book::from_bytes(&bytes).unwrap();
+    assert_eq!(book.len(), 4);
+    assert_eq!(book.entries(start).len(), 3);
+    let moves: Vec<String> = book.moves(&state).iter().map(|(m, _)| m.to_string()).collect();
+    assert_eq!(moves, vec!["e2e4", "d2d4"]);
+
+    let best = book.choose(&state, BookSelection::Best).unwrap();
+    assert_eq!((best.motion.to_string(), best.weight, best.total), ("e2e4".to_string(), 10, 15));
+    for _ in 0..20 {
+        let pick = book.choose(&state, BookSelection::Weighted).unwrap();
+        assert!(["e2e4", "d2d4"].contains(&pick.motion.to_string().as_str()));
+    }
+
+    // Past the depth limit the book stays quiet
+    let settings = BookSettings { book: Arc::new(book), selection: BookSelection::Best, max_ply: 1 };
+    assert!(settings.probe(&state).is_some());
+    let later = State::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 2").unwrap();
+    assert_eq!(settings.probe(&later), None);
+
+    bytes.pop();
+    assert!(matches!(Book::from_bytes(&bytes), Err(BookError::Truncated(63))));
+}
+
+const GAMES: &str = "[Result \"1-0\"]\n\n1. e4 e5 2. Nf3 Nc6 1-0\n\n\
+[Result \"0-1\"]\n\n1. e4 c5 0-1\n\n\
+[Result \"1/2-1/2\"]\n\n1. d4 d5 1/2-1/2\n\n\
+[Result \"*\"]\n\n1. e4 *\n\n\
+[Variant \"Atomic\"]\n[Result \"1-0\"]\n\n1. e4 1-0\n";
+
+fn built(min_games: u32, max_ply: u64, side: Parity) -> Book {
+    let mut builder = BookBuilder::new(min_games, max_ply, side);
+    let used = PgnGame::parse_all(GAMES).unwrap().iter().filter(|g| builder.add_game(g)).count();
+    // The unfinished game and the Atomic one are left out
+    assert_eq!((used, builder.games()), (3, 3));
+    return builder.build();
+}
+
+fn weights(book: &Book, sans: &[&str]) -> Vec<(String, u16)> {
+    let mut state = State::from_fen(STARTING_FEN).unwrap();
+    for san in sans {
+        let motion = state.parse_san(san).unwrap();
+        state.make_motion(&motion, false);
+    }
+    return book.moves(&state).iter().map(|(m, w)| (m.to_string(), *w)).collect();
+}
+
+#[test]
+fn building_books() {
+    let book = built(1, 20, Parity::BOTH);
+    // e4 won once and lost once, d4 drew
+    assert_eq!(weights(&book, &[]), vec![("e2e4".to_string(), 2), ("d2d4".to_string(), 1)]);
+    // c5 won for black, e5 lost and so never makes it in
+    assert_eq!(weights(&book, &["e4"]), vec![("c7c5".to_string(), 2)]);
+    assert_eq!(weights(&book, &["e4", "e5"]), vec![("g1f3".to_string(), 2)]);
+
+    // Reading the written book back gives the same thing
+    let reread = Book::from_bytes(&book.to_bytes()).unwrap();
+    assert_eq!(reread.to_bytes(), book.to_bytes());
+    assert_eq!(weights(&reread, &["e4", "e5"]), vec![("g1f3".to_string(), 2)]);
+
+    // Only e4 was played twice
+    assert_eq!(weights(&built(2, 20, Parity::BOTH), &[]), vec![("e2e4".to_string(), 2)]);
+    assert!(weights(&built(1, 1, Parity::BOTH), &["e4"]).is_empty());
+    let black = built(1, 20, Parity::BLACK);
+    assert!(weights(&black, &[]).is_empty());
+    assert_eq!(weights(&black, &["e4"]), vec![("c7c5".to_string(), 2)]);
+}
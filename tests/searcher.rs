@@ -0,0 +1,22 @@
+use std::sync::{Arc, Mutex};
+
+use chess::lib::{attacks, searcher::Searcher, state::State};
+
+fn best(fen: &str, depth: u8) -> String {
+    attacks::init();
+    let state = Arc::new(Mutex::new(State::from_fen(fen).unwrap()));
+    let mut searcher = Searcher { depth_limit: Some(depth), ..Searcher::default() };
+    let best = searcher.run(state.clone());
+    return state.lock().unwrap().to_uci(&best);
+}
+
+#[test]
+fn stalemate_is_a_draw() {
+    // The king steps up to f7 or f2 and leaves the other side without a move, but a knight ahead is worth more
+    for depth in [2, 3, 4] {
+        assert_ne!(best("7k/7p/5K1P/8/8/8/8/N7 w - - 0 1", depth), "f6f7", "{}", depth);
+        assert_ne!(best("n7/8/8/8/8/5k1p/7P/7K b - - 0 1", depth), "f3f2", "{}", depth);
+    }
+    // With the mate on the board it still takes the mate
+    assert_eq!(best("k7/8/1K6/8/8/8/7Q/8 w - - 0 1", 3), "h2h8");
+}
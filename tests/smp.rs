@@ -0,0 +1,49 @@
+use std::{sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use chess::lib::{attacks, searcher::Searcher, state::State};
+
+#[test]
+fn thread_copies_are_independent() {
+    attacks::init();
+    let mut state = State::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+    let motion = state.parse_uci("e2e4").unwrap();
+    state.make_motion(&motion, false);
+    let mut copy = state.thread_copy();
+    assert_eq!(copy.to_fen(), state.to_fen());
+    assert_eq!(copy.info.zkey, state.info.zkey);
+    assert!(!Arc::ptr_eq(&copy.zobrist, &state.zobrist));
+    // The played moves come along, so the copy can take them back
+    assert_eq!(copy.played_motions(), state.played_motions());
+    copy.unmake_last(true);
+    assert_eq!(copy.to_fen(), "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1");
+    // The moves from before e2e4 came along with it, though its move cache started empty
+    assert_eq!(copy.moves.parity_vect(copy.turn).len(), 6);
+    assert_eq!(state.played_motions().len(), 1);
+    // Keys from the copy's own tables still match the original's
+    let motion = copy.parse_uci("e2e4").unwrap();
+    copy.make_motion(&motion, false);
+    assert_eq!(copy.info.zkey, state.info.zkey);
+}
+
+#[test]
+fn helpers_still_find_the_mate() {
+    attacks::init();
+    let state = Arc::new(Mutex::new(State::from_fen("k7/8/1K6/8/8/8/7Q/8 w - - 0 1").unwrap()));
+    let mut searcher = Searcher { depth_limit: Some(3), threads: 4, ..Searcher::default() };
+    let best = searcher.run(state.clone());
+    assert_eq!(state.lock().unwrap().to_uci(&best), "h2h8");
+}
+
+#[test]
+fn helpers_stop_with_the_main_thread() {
+    attacks::init();
+    let state = Arc::new(Mutex::new(State::from_fen(chess::lib::fen::STARTING_FEN).unwrap()));
+    let mut searcher = Searcher { time_limit: Duration::from_millis(500), threads: 3, ..Searcher::default() };
+    let start = Instant::now();
+    let best = searcher.run(state.clone());
+    assert!(start.elapsed() < Duration::from_secs(5));
+    let lock = state.lock().unwrap();
+    assert!(lock.parse_uci(&lock.to_uci(&best)).is_ok());
+    // The position the helpers searched on was their own
+    assert_eq!(lock.to_fen(), chess::lib::fen::STARTING_FEN);
+}
@@ -0,0 +1,107 @@
+use std::sync::{Arc, Mutex};
+
+use chess::lib::{
+    attacks,
+    heap::EvaluatedMotion,
+    motion::Motion,
+    piece::PieceByte,
+    searcher::Searcher,
+    state::State,
+    transposition::{Bound, TranspositionTable}
+};
+
+fn motion(from: usize, to: usize) -> Motion {
+    return Motion { from, to, promotion: PieceByte::NONE };
+}
+
+#[test]
+fn storing_and_probing() {
+    let tt = TranspositionTable::new(1);
+    assert_eq!(tt.megabytes(), 1);
+    assert_eq!(tt.capacity(), 65536);
+    let promotion = Motion { from: 8, to: 0, promotion: PieceByte::KNIGHT };
+    tt.store(0xDEADBEEF, promotion, -250, 6, Bound::Upper, 3);
+    let entry = tt.probe(0xDEADBEEF, 3).unwrap();
+    assert_eq!((entry.motion, entry.score, entry.depth, entry.bound), (promotion, -250, 6, Bound::Upper));
+    // Only the exact key matches, even within the same bucket
+    assert_eq!(tt.probe(0xDEADBEEE, 3), None);
+    tt.clear();
+    assert_eq!(tt.probe(0xDEADBEEF, 3), None);
+}
+
+#[test]
+fn mate_scores_follow_the_ply() {
+    let tt = TranspositionTable::new(1);
+    // Mate five plies from the root, stored three plies in, is two plies from the position itself
+    tt.store(1, motion(0, 1), i32::MAX - 5, 4, Bound::Exact, 3);
+    assert_eq!(tt.probe(1, 1).unwrap().score, i32::MAX - 3);
+    tt.store(2, motion(0, 1), -(i32::MAX - 5), 4, Bound::Exact, 3);
+    assert_eq!(tt.probe(2, 6).unwrap().score, -(i32::MAX - 8));
+    tt.store(3, motion(0, 1), 100_000 - 10, 4, Bound::Lower, 4);
+    assert_eq!(tt.probe(3, 2).unwrap().score, 100_000 - 8);
+    // Everything else is the same from any ply
+    tt.store(4, motion(0, 1), 1234, 4, Bound::Exact, 7);
+    assert_eq!(tt.probe(4, 0).unwrap().score, 1234);
+}
+
+#[test]
+fn replacement() {
+    // With 2^14 buckets the top 14 bits pick the bucket, so small keys share the first one
+    let tt = TranspositionTable::new(1);
+    tt.store(1, motion(0, 1), 10, 9, Bound::Lower, 0);
+    // A shallower bound from the same search doesn't push out a deeper one
+    tt.store(1, motion(0, 2), 20, 3, Bound::Upper, 0);
+    assert_eq!(tt.probe(1, 0).unwrap().depth, 9);
+    // An exact score does, and keeps the old move when it has none of its own
+    tt.store(1, Motion::default(), 30, 3, Bound::Exact, 0);
+    let entry = tt.probe(1, 0).unwrap();
+    assert_eq!((entry.motion, entry.score, entry.depth), (motion(0, 1), 30, 3));
+
+    for (key, depth) in [(2, 5), (3, 7), (4, 8)] {
+        tt.store(key, motion(0, 1), 0, depth, Bound::Exact, 0);
+    }
+    // The bucket is full, so the shallowest entry goes
+    tt.store(5, motion(0, 1), 0, 1, Bound::Exact, 0);
+    assert_eq!(tt.probe(1, 0), None);
+    assert!([2, 3, 4, 5].iter().all(|key| tt.probe(*key, 0).is_some()));
+    // Once a new search starts, a deep entry from the last one is worth less than a fresh shallow one
+    tt.new_search();
+    tt.store(6, motion(0, 1), 0, 2, Bound::Exact, 0);
+    tt.store(7, motion(0, 1), 0, 2, Bound::Exact, 0);
+    assert!(tt.probe(6, 0).is_some() && tt.probe(7, 0).is_some());
+    assert_eq!(tt.probe(5, 0), None);
+    assert_eq!(tt.probe(2, 0), None);
+}
+
+#[test]
+fn hashfull() {
+    let tt = TranspositionTable::new(1);
+    assert_eq!(tt.hashfull(), 0);
+    for key in 0..200_000u64 {
+        tt.store(key.wrapping_mul(0x9E3779B97F4A7C15), motion(0, 1), 0, 1, Bound::Exact, 0);
+    }
+    assert!(tt.hashfull() > 900);
+    // Entries from an earlier search don't count
+    tt.new_search();
+    assert_eq!(tt.hashfull(), 0);
+}
+
+#[test]
+fn evaluated_motions_keep_their_key() {
+    let evaluated = vec![EvaluatedMotion { evaluation: 5, motion: motion(12, 28), key: 42 }];
+    assert_eq!(evaluated.clone()[0].key, 42);
+}
+
+#[test]
+fn searching_again_uses_the_table() {
+    attacks::init();
+    let state = Arc::new(Mutex::new(State::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap()));
+    let mut searcher = Searcher { depth_limit: Some(3), ..Searcher::default() };
+    let first = searcher.run(state.clone());
+    let nodes = searcher.driver.nodes;
+    let second = searcher.run(state.clone());
+    assert_eq!(first, second);
+    assert!(searcher.driver.cache_saves > 0);
+    assert!(searcher.driver.nodes <= nodes);
+    assert!(state.lock().unwrap().parse_uci(&first.to_string()).is_ok());
+}
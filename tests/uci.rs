@@ -0,0 +1,164 @@
+use std::{io::Cursor, sync::{Arc, Mutex}, thread, time::{Duration, Instant}};
+
+use chess::lib::{
+    engine::{Clock, Output},
+    state::State,
+    uci::{self, Uci}
+};
+
+fn session() -> (Uci, Arc<Mutex<Vec<u8>>>) {
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let out: Output = buffer.clone();
+    return (Uci::new(out), buffer);
+}
+
+fn lines(buffer: &Arc<Mutex<Vec<u8>>>) -> Vec<String> {
+    return String::from_utf8(buffer.lock().unwrap().clone()).unwrap().lines().map(|l| l.to_string()).collect();
+}
+
+fn script(commands: &str) -> Vec<String> {
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let out: Output = buffer.clone();
+    uci::run(Cursor::new(commands.to_string()), out);
+    return lines(&buffer);
+}
+
+fn best_move(lines: &[String]) -> Option<String> {
+    return lines.iter().find_map(|l| l.strip_prefix("bestmove ")).map(|m| m.split_whitespace().next().unwrap().to_string());
+}
+
+fn wait_for_best_move(buffer: &Arc<Mutex<Vec<u8>>>) -> Option<String> {
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(30) {
+        if let Some(best) = best_move(&lines(buffer)) {
+            return Some(best);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    return None;
+}
+
+#[test]
+fn handshake() {
+    let out = script("uci\nisready\nquit\n");
+    let uciok = out.iter().position(|l| l == "uciok").unwrap();
+    assert!(out[0].starts_with("id name "));
+    assert!(out[..uciok].iter().any(|l| l.starts_with("option name SyzygyPath type string")));
+    assert_eq!(out.last().unwrap(), "readyok");
+}
+
+#[test]
+fn searching_to_a_depth() {
+    let fen = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1";
+    let out = script(&format!("position fen {} moves e1d1 e8d8\ngo depth 2\n", fen));
+    assert!(out.iter().any(|l| l.starts_with("info depth 1 score ")));
+    assert!(out.iter().any(|l| l.starts_with("info depth 2 score ")));
+    // The move comes after everything said about the search
+    assert!(out.last().unwrap().starts_with("bestmove "));
+    let mut state = State::from_fen(fen).unwrap();
+    for uci in ["e1d1", "e8d8"] {
+        let motion = state.parse_uci(uci).unwrap();
+        state.make_motion(&motion, false);
+    }
+    assert!(state.parse_uci(&best_move(&out).unwrap()).is_ok());
+}
+
+#[test]
+fn infinite_waits_for_stop() {
+    let (mut uci, buffer) = session();
+    uci.command("position fen 4k3/8/8/8/8/8/4P3/4K3 w - - 0 1");
+    uci.command("go infinite");
+    thread::sleep(Duration::from_millis(300));
+    assert_eq!(best_move(&lines(&buffer)), None);
+    uci.command("stop");
+    assert!(best_move(&lines(&buffer)).is_some());
+}
+
+#[test]
+fn ponderhit_starts_the_clock() {
+    let (mut uci, buffer) = session();
+    uci.command("position fen 4k3/8/8/8/8/8/4P3/4K3 w - - 0 1");
+    uci.command("go ponder wtime 3000 btime 3000");
+    thread::sleep(Duration::from_millis(300));
+    assert_eq!(best_move(&lines(&buffer)), None);
+    uci.command("ponderhit");
+    assert!(wait_for_best_move(&buffer).is_some());
+}
+
+#[test]
+fn bad_input() {
+    let out = script("position startpos moves e2e4 e2e4\nposition fen 8/8/8\nfoo\nsetoption name Nonsense value 1\n");
+    assert!(out[0].starts_with("info string ") && out[0].contains("illegal move \"e2e4\""));
+    assert!(out[1].starts_with("info string ") && out[1].contains("bad FEN"));
+    assert_eq!(out[2], "info string Unknown command: foo");
+    assert_eq!(out[3], "info string Unknown option: nonsense");
+    // Checkmated, so there's no move to give
+    assert_eq!(best_move(&script("position fen k7/1Q6/1K6/8/8/8/8/8 b - - 0 1\ngo depth 3\n")), Some("0000".to_string()));
+}
+
+#[test]
+fn clock_budget() {
+    let clock = Clock { remaining: Duration::from_millis(30_050), increment: Duration::ZERO, moves_to_go: None };
+    assert_eq!(clock.budget(), Duration::from_secs(1));
+    let clock = Clock { remaining: Duration::from_millis(10_050), increment: Duration::from_millis(400), moves_to_go: Some(5) };
+    assert_eq!(clock.budget(), Duration::from_millis(2300));
+    // Nearly out of time, it never plans past the safety margin
+    let clock = Clock { remaining: Duration::from_millis(100), increment: Duration::from_secs(5), moves_to_go: None };
+    assert_eq!(clock.budget(), Duration::from_millis(50));
+}
+
+#[test]
+fn searching_on_more_threads() {
+    let out = script("uci\nsetoption name Threads value 3\nposition startpos moves e2e4\ngo depth 2\n");
+    assert!(out.iter().any(|l| l == "option name Threads type spin default 1 min 1 max 256"));
+    let mut state = State::from_fen(chess::lib::fen::STARTING_FEN).unwrap();
+    let motion = state.parse_uci("e2e4").unwrap();
+    state.make_motion(&motion, false);
+    assert!(state.parse_uci(&best_move(&out).unwrap()).is_ok());
+    assert_eq!(script("setoption name Threads value lots\n"), vec!["info string Bad thread count: lots".to_string()]);
+}
+
+fn last_score(lines: &[String]) -> Option<String> {
+    let info = lines.iter().rfind(|l| l.starts_with("info depth "))?;
+    let fields: Vec<&str> = info.split_whitespace().collect();
+    let at = fields.iter().position(|f| *f == "score")?;
+    return Some(format!("{} {}", fields[at + 1], fields[at + 2]));
+}
+
+#[test]
+fn plays_either_colour() {
+    // Each position and its mirror image, so White and Black must both find the same move
+    for (fen, best, score) in [
+        ("k7/8/1K6/8/8/8/7Q/8 w - - 0 1", "h2h8", "mate 1"),
+        ("8/7q/8/8/8/1k6/8/K7 b - - 0 1", "h7h1", "mate 1"),
+        ("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1", "d2d5", ""),
+        ("4k3/3r4/8/8/3Q4/8/8/4K3 b - - 0 1", "d7d4", "")
+    ] {
+        let out = script(&format!("position fen {}\ngo depth 2\n", fen));
+        assert_eq!(best_move(&out), Some(best.to_string()), "{}", fen);
+        let reported = last_score(&out).unwrap();
+        if score.is_empty() {
+            // Winning the queen is good for whoever the engine plays
+            assert!(reported.strip_prefix("cp ").unwrap().parse::<i32>().unwrap() > 500, "{}: {}", fen, reported);
+        } else {
+            assert_eq!(reported, score, "{}", fen);
+        }
+    }
+    // Stepping up to f7 or f2 leaves the other side without a move, which is a draw and not a mate.
+    // On pawns alone the evaluation likes the draw, but a knight ahead it should stay clear of it.
+    for (fen, stalemate, ahead) in [
+        ("7k/7p/5K1P/8/8/8/8/8 w - - 0 1", "f6f7", false),
+        ("8/8/8/8/8/5k1p/7P/7K b - - 0 1", "f3f2", false),
+        ("7k/7p/5K1P/8/8/8/8/N7 w - - 0 1", "f6f7", true),
+        ("n7/8/8/8/8/5k1p/7P/7K b - - 0 1", "f3f2", true)
+    ] {
+        let out = script(&format!("position fen {}\ngo depth 4\n", fen));
+        for line in out.iter().filter(|l| l.starts_with("info depth ")) {
+            assert!(!line.contains("score mate"), "{}: {}", fen, line);
+        }
+        if ahead {
+            assert_ne!(best_move(&out), Some(stalemate.to_string()), "{}", fen);
+            assert!(last_score(&out).unwrap().strip_prefix("cp ").unwrap().parse::<i32>().unwrap() > 200, "{}", fen);
+        }
+    }
+}
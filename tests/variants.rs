@@ -0,0 +1,77 @@
+use chess::lib::{
+    eval::{start_eval, VARIANT_WIN},
+    fen::{FenError, STARTING_FEN},
+    outcome::GameOutcome,
+    pgn::PgnGame,
+    piece::Parity,
+    state::State,
+    variant::Variant
+};
+
+mod common;
+use common::play;
+
+#[test]
+fn check_field_round_trip() {
+    let fen = "rnbqkbnr/ppp2ppp/8/3pp3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 2+3 0 3";
+    let state = State::from_fen(fen).unwrap();
+    assert_eq!(state.variant, Variant::ThreeCheck);
+    assert_eq!(state.info.checks, [1, 0]);
+    assert_eq!(state.remaining_checks(), [2, 3]);
+    assert_eq!(state.to_fen(), fen);
+    // The trailing checks-given form reads the same
+    let given = State::from_fen("rnbqkbnr/ppp2ppp/8/3pp3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 3 +1+0").unwrap();
+    assert_eq!(given.to_fen(), fen);
+    assert_eq!(given.info.zkey, state.info.zkey);
+    assert!(matches!(State::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 4+3 0 1"), Err(FenError::BadChecks(_))));
+    assert!(!State::from_fen(STARTING_FEN).unwrap().to_fen().contains('+'));
+}
+
+#[test]
+fn third_check_wins() {
+    let mut state = State::from_fen("rnbqkbnr/ppp2ppp/8/3pp3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 1+3 0 3").unwrap();
+    let before = state.info.zkey;
+    play(&mut state, "f1b5");
+    assert_eq!(state.info.checks, [3, 0]);
+    assert_eq!(state.outcome(), Some(GameOutcome::ThreeChecks(Parity::WHITE)));
+    assert_eq!(start_eval(&state).eval, VARIANT_WIN);
+    assert_eq!(State::from_fen(&state.to_fen()).unwrap().info.zkey, state.info.zkey);
+    state.unmake_last(true);
+    assert_eq!(state.info.checks, [2, 0]);
+    assert_eq!(state.info.zkey, before);
+    assert_eq!(state.outcome(), None);
+}
+
+#[test]
+fn checks_count_through_a_game() {
+    let mut state = State::from_fen(STARTING_FEN).unwrap();
+    state.variant = Variant::ThreeCheck;
+    for uci in ["e2e4", "d7d5", "f1b5", "c7c6", "b5c6", "b8c6", "d1h5", "g8f6", "h5f7"] {
+        assert_eq!(state.outcome(), None, "game ended before {}", uci);
+        play(&mut state, uci);
+    }
+    assert_eq!(state.remaining_checks(), [0, 3]);
+    assert_eq!(state.outcome().map(|o| o.to_string()), Some("WHITE WINS by three checks".to_string()));
+    let pgn = PgnGame::from_state(&state, state.outcome()).unwrap();
+    assert_eq!(pgn.tag("Variant"), Some("Three-check"));
+    let replayed = PgnGame::parse(&pgn.write()).unwrap().replay().unwrap();
+    assert_eq!(replayed.variant, Variant::ThreeCheck);
+    assert_eq!(replayed.to_fen(), state.to_fen());
+}
+
+#[test]
+fn king_reaches_the_hill() {
+    let mut state = State::from_fen("8/8/8/8/8/4K3/8/k7 w - - 0 1").unwrap();
+    state.variant = Variant::KingOfTheHill;
+    assert_eq!(state.outcome(), None);
+    play(&mut state, "e3d4");
+    assert_eq!(state.outcome(), Some(GameOutcome::KingOfTheHill(Parity::WHITE)));
+    assert_eq!(start_eval(&state).eval, VARIANT_WIN);
+    state.unmake_last(true);
+    // Bare kings are only a dead draw when the hill does not count
+    assert_eq!(state.outcome(), None);
+    state.variant = Variant::Standard;
+    assert_eq!(state.outcome(), Some(GameOutcome::InsufficientMaterial));
+    assert_eq!(Variant::from_name("King of the Hill"), Some(Variant::KingOfTheHill));
+    assert_eq!(Variant::from_name("3check"), Some(Variant::ThreeCheck));
+}
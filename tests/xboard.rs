@@ -0,0 +1,153 @@
+use std::{io::Cursor, sync::{Arc, Mutex}, time::Duration};
+
+use chess::lib::{
+    engine::Output,
+    motion::Motion,
+    searcher::SearchReport,
+    state::State,
+    xboard::{self, Xboard}
+};
+
+fn script(commands: &str) -> Vec<String> {
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let out: Output = buffer.clone();
+    xboard::run(Cursor::new(commands.to_string()), out);
+    let text = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+    return text.lines().map(|l| l.to_string()).collect();
+}
+
+fn engine_moves(lines: &[String]) -> Vec<String> {
+    return lines.iter().filter_map(|l| l.strip_prefix("move ")).map(|m| m.to_string()).collect();
+}
+
+fn legal_after(fen: &str, moves: &[&str], reply: &str) -> bool {
+    let mut state = State::from_fen(fen).unwrap();
+    for uci in moves {
+        let motion = state.parse_uci(uci).unwrap();
+        state.make_motion(&motion, false);
+    }
+    return state.parse_uci(reply).is_ok();
+}
+
+#[test]
+fn handshake() {
+    let out = script("xboard\nprotover 2\nping 7\n");
+    assert!(out[0].starts_with("feature ") && out[0].contains("usermove=1") && out[0].contains("setboard=1"));
+    assert_eq!(out[1], "feature done=1");
+    assert_eq!(out[2], "pong 7");
+}
+
+#[test]
+fn replies_as_black() {
+    let out = script("xboard\nnew\nsd 1\nusermove e2e4\n");
+    let moves = engine_moves(&out);
+    assert_eq!(moves.len(), 1);
+    assert!(legal_after(chess::lib::fen::STARTING_FEN, &["e2e4"], &moves[0]));
+    // Bare moves are read too, for GUIs that didn't take usermove=1
+    let out = script("xboard\nnew\nsd 1\nd2d4\n");
+    assert!(legal_after(chess::lib::fen::STARTING_FEN, &["d2d4"], &engine_moves(&out)[0]));
+}
+
+#[test]
+fn force_mode_and_go() {
+    let out = script("new\nforce\nusermove e2e4\nusermove e7e5\nsd 1\n");
+    assert!(engine_moves(&out).is_empty());
+    // Go hands the side to move to the engine
+    let out = script("new\nforce\nusermove e2e4\nusermove e7e5\nsd 1\ngo\n");
+    let moves = engine_moves(&out);
+    assert_eq!(moves.len(), 1);
+    assert!(legal_after(chess::lib::fen::STARTING_FEN, &["e2e4", "e7e5"], &moves[0]));
+}
+
+#[test]
+fn taking_moves_back() {
+    let out = script("new\nforce\nusermove e2e4\nusermove e7e5\nremove\nusermove e7e5\nusermove e2e4\nundo\nusermove d2d4\n");
+    assert_eq!(out, vec!["Illegal move (illegal move \"e7e5\"): e7e5".to_string()]);
+}
+
+#[test]
+fn thinking_output() {
+    let fen = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1";
+    let out = script(&format!("new\nforce\nsetboard {}\nsd 2\npost\ngo\n", fen));
+    let thinking: Vec<&String> = out.iter().filter(|l| !l.starts_with("move ")).collect();
+    assert_eq!(thinking.len(), 2);
+    for (ply, line) in thinking.iter().enumerate() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        assert_eq!(fields.len(), 5);
+        assert_eq!(fields[0], (ply + 1).to_string());
+        assert!(fields[1..4].iter().all(|f| f.parse::<i64>().is_ok()));
+    }
+    let out = script(&format!("new\nforce\nsetboard {}\nsd 2\npost\nnopost\ngo\n", fen));
+    assert_eq!(out.len(), 1);
+    assert!(legal_after(fen, &[], &engine_moves(&out)[0]));
+}
+
+#[test]
+fn bad_input() {
+    let out = script("level 40 five 0\nst soon\nsd deep\nsetboard 8/8\nfoo\nusermove e2e5\n");
+    assert_eq!(out, vec![
+        "Error (bad time control): level 40 five 0".to_string(),
+        "Error (bad time): st soon".to_string(),
+        "Error (bad depth): sd deep".to_string(),
+        "tellusererror Illegal position: bad FEN: expected 4 to 6 space separated fields, found 1".to_string(),
+        "Error (unknown command): foo".to_string(),
+        "Illegal move (illegal move \"e2e5\"): e2e5".to_string()
+    ]);
+}
+
+#[test]
+fn clocks() {
+    let out: Output = Arc::new(Mutex::new(Vec::new()));
+    let mut session = Xboard::new(out);
+    session.command("level 40 5 0");
+    assert_eq!(session.clocks(), (Duration::from_secs(300), Duration::from_secs(300)));
+    // Both come in centiseconds
+    session.command("time 12345");
+    session.command("otim 6789");
+    assert_eq!(session.clocks(), (Duration::from_millis(123_450), Duration::from_millis(67_890)));
+    session.command("new");
+    assert_eq!(session.clocks(), (Duration::from_secs(300), Duration::from_secs(300)));
+    assert_eq!(script("time soon\notim later\n"), vec!["Error (bad time): time soon".to_string(), "Error (bad time): otim later".to_string()]);
+}
+
+#[test]
+fn scores_and_results() {
+    let report = SearchReport { depth: 3, score: i32::MAX - 2, nodes: 100, tb_hits: 0, hashfull: 0, elapsed: Duration::from_millis(1234), best: Motion::default(), pv: "g1g8".to_string() };
+    assert_eq!(xboard::thinking_line(&report), "3 100002 123 100 g1g8");
+    let report = SearchReport { score: -(i32::MAX - 3), ..report };
+    assert_eq!(xboard::thinking_line(&report), "3 -100002 123 100 g1g8");
+    let mated = State::from_fen("k7/1Q6/1K6/8/8/8/8/8 b - - 0 1").unwrap();
+    assert_eq!(xboard::result_line(&mated), Some("1-0 {WHITE WINS by checkmate}".to_string()));
+    let stalemate = State::from_fen("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1").unwrap();
+    assert_eq!(xboard::result_line(&stalemate), Some("1/2-1/2 {DRAW by stalemate}".to_string()));
+    assert_eq!(xboard::result_line(&State::from_fen(chess::lib::fen::STARTING_FEN).unwrap()), None);
+}
+
+#[test]
+fn searching_on_more_cores() {
+    let out = script("xboard\nprotover 2\nnew\ncores 3\nsd 2\nusermove e2e4\n");
+    assert!(out[0].contains("smp=1"));
+    assert!(legal_after(chess::lib::fen::STARTING_FEN, &["e2e4"], &engine_moves(&out)[0]));
+    assert_eq!(script("cores lots\n"), vec!["Error (bad core count): cores lots".to_string()]);
+}
+
+#[test]
+fn plays_either_colour() {
+    // Each position and its mirror image, with go handing the engine whichever side is to move
+    for (fen, best, mate) in [
+        ("k7/8/1K6/8/8/8/7Q/8 w - - 0 1", "h2h8", true),
+        ("8/7q/8/8/8/1k6/8/K7 b - - 0 1", "h7h1", true),
+        ("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1", "d2d5", false),
+        ("4k3/3r4/8/8/3Q4/8/8/4K3 b - - 0 1", "d7d4", false)
+    ] {
+        let out = script(&format!("new\nforce\nsetboard {}\nsd 2\npost\ngo\n", fen));
+        assert_eq!(engine_moves(&out), vec![best.to_string()], "{}", fen);
+        let thinking = out.iter().rfind(|l| l.starts_with("2 ")).unwrap();
+        let score: i32 = thinking.split_whitespace().nth(1).unwrap().parse().unwrap();
+        if mate {
+            assert_eq!(score, 100_001, "{}", fen);
+        } else {
+            assert!(score > 500, "{}: {}", fen, thinking);
+        }
+    }
+}